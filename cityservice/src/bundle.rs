@@ -0,0 +1,109 @@
+use warp::http::header::{HeaderValue, CONTENT_TYPE};
+use warp::reply::Response;
+
+/// One file in a bundle
+struct Part {
+    file_name: String,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+/// Several files sent back in a single `multipart/mixed` response,
+/// so a client can fetch every channel of a city without generating
+/// it more than once.
+#[derive(Default)]
+pub struct Bundle {
+    parts: Vec<Part>,
+}
+
+impl Bundle {
+    pub fn add(&mut self, file_name: String, content_type: &'static str, body: Vec<u8>) {
+        self.parts.push(Part {
+            file_name,
+            content_type,
+            body,
+        });
+    }
+
+    /// Picks a boundary that doesn't turn up inside any of the
+    /// parts, the images are binary so anything could be in there
+    fn boundary(&self) -> String {
+        (0..)
+            .map(|attempt| format!("citygen-bundle-{}", attempt))
+            .find(|boundary| {
+                let boundary = boundary.as_bytes();
+                self.parts.iter().all(|part| {
+                    !part.body.windows(boundary.len()).any(|window| window == boundary)
+                })
+            })
+            .unwrap()
+    }
+
+    pub fn into_response(self) -> Response {
+        let boundary = self.boundary();
+
+        let mut body = Vec::new();
+        for part in self.parts {
+            body.extend_from_slice(format!(
+                "--{}\r\n\
+                 Content-Type: {}\r\n\
+                 Content-Disposition: attachment; filename=\"{}\"\r\n\
+                 \r\n",
+                boundary, part.content_type, part.file_name,
+            ).as_bytes());
+            body.extend(part.body);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+
+        let mut response = Response::new(body.into());
+        let content_type = format!("multipart/mixed; boundary={}", boundary);
+        response.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_str(&content_type).unwrap(),
+        );
+        response
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn boundary_avoids_the_parts() {
+        let mut bundle = Bundle::default();
+        bundle.add(String::from("a.png"), "image/png", b"xxcitygen-bundle-0xx".to_vec());
+        bundle.add(String::from("b.png"), "image/png", b"citygen-bundle-1".to_vec());
+        assert_eq!(bundle.boundary(), "citygen-bundle-2");
+
+        assert_eq!(Bundle::default().boundary(), "citygen-bundle-0");
+    }
+
+    #[tokio::test]
+    async fn parts_are_framed_by_the_boundary() {
+        let mut bundle = Bundle::default();
+        bundle.add(String::from("albedo.jpg"), "image/jpeg", b"citygen-bundle-0".to_vec());
+        bundle.add(String::from("metadata.json"), "application/json", b"{}".to_vec());
+
+        let response = bundle.into_response();
+        assert_eq!(
+            response.headers()[CONTENT_TYPE],
+            "multipart/mixed; boundary=citygen-bundle-1",
+        );
+
+        let body = warp::hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let expected = "--citygen-bundle-1\r\n\
+                        Content-Type: image/jpeg\r\n\
+                        Content-Disposition: attachment; filename=\"albedo.jpg\"\r\n\
+                        \r\n\
+                        citygen-bundle-0\r\n\
+                        --citygen-bundle-1\r\n\
+                        Content-Type: application/json\r\n\
+                        Content-Disposition: attachment; filename=\"metadata.json\"\r\n\
+                        \r\n\
+                        {}\r\n\
+                        --citygen-bundle-1--\r\n";
+        assert_eq!(body, expected.as_bytes());
+    }
+}
@@ -0,0 +1,97 @@
+use std::io::Write;
+
+use image::ImageEncoder;
+
+/// The ways a run of frames, like a growth time-lapse, can be sent
+/// back
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Animation {
+    Gif,
+    Apng,
+    /// Each frame as a png of its own, numbered in order, in a zip
+    Zip,
+}
+
+impl Animation {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "gif" => Some(Animation::Gif),
+            "apng" => Some(Animation::Apng),
+            "zip" => Some(Animation::Zip),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Animation::Gif => "image/gif",
+            Animation::Apng => "image/apng",
+            Animation::Zip => "application/zip",
+        }
+    }
+
+    /// Encodes the frames, each shown for the duration in seconds.
+    /// The frames are taken one at a time, so only one of them needs
+    /// to be held at once. Animations loop forever.
+    pub fn encode<I>(&self, frames: I, frame_duration: f64) -> Vec<u8> where
+        I: ExactSizeIterator<Item = image::RgbImage>,
+    {
+        let milliseconds = (frame_duration * 1000.0).round().clamp(1.0, u16::MAX as f64) as u16;
+        let mut encoded = Vec::new();
+
+        match self {
+            Animation::Gif => {
+                // The slowest speeds take far too long to quantize a
+                // full sized frame for what they gain
+                let mut encoder = image::codecs::gif::GifEncoder::new_with_speed(&mut encoded, 10);
+                encoder.set_repeat(image::codecs::gif::Repeat::Infinite)
+                    .expect("Failed to encode animation to memory");
+
+                let delay = image::Delay::from_numer_denom_ms(milliseconds as u32, 1);
+                encoder.encode_frames(frames.map(|frame| {
+                    let rgba = image::DynamicImage::ImageRgb8(frame).into_rgba8();
+                    image::Frame::from_parts(rgba, 0, 0, delay)
+                })).expect("Failed to encode animation to memory");
+            },
+            Animation::Apng => {
+                let mut frames = frames.peekable();
+                let (width, height) = frames.peek().map_or((1, 1), |frame| frame.dimensions());
+                let mut encoder = png::Encoder::new(&mut encoded, width, height);
+                encoder.set_color(png::ColorType::Rgb);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.set_animated(frames.len() as u32, 0)
+                    .and_then(|_| encoder.set_frame_delay(milliseconds, 1000))
+                    .expect("Failed to encode animation to memory");
+
+                let mut writer = encoder.write_header()
+                    .expect("Failed to encode animation to memory");
+                for frame in frames {
+                    writer.write_image_data(frame.as_raw())
+                        .expect("Failed to encode animation to memory");
+                }
+                writer.finish().expect("Failed to encode animation to memory");
+            },
+            Animation::Zip => {
+                // The frames are compressed already, so they're only
+                // stored in the archive
+                let mut archive = zip::ZipWriter::new(std::io::Cursor::new(&mut encoded));
+                let options = zip::write::FileOptions::default()
+                    .compression_method(zip::CompressionMethod::Stored);
+
+                for (index, frame) in frames.enumerate() {
+                    let mut png = Vec::new();
+                    image::codecs::png::PngEncoder::new(&mut png)
+                        .write_image(frame.as_raw(), frame.width(), frame.height(), image::ColorType::Rgb8)
+                        .expect("Failed to encode image to memory");
+
+                    archive.start_file(format!("frame-{:03}.png", index), options)
+                        .and_then(|_| Ok(archive.write_all(&png)?))
+                        .expect("Failed to encode animation to memory");
+                }
+                archive.finish().expect("Failed to encode animation to memory");
+            },
+        }
+
+        encoded
+    }
+}
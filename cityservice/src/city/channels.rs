@@ -0,0 +1,516 @@
+use image::{DynamicImage, GenericImage, GenericImageView, GrayImage, Luma, Rgb, RgbImage, Rgba};
+
+use super::geom::{Rectangle, Vector2};
+use super::lighting::Sun;
+use super::palette::Palette;
+use super::texture::Texture;
+
+/// The maps a city can be rendered into, all of them line up pixel
+/// for pixel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+    Albedo,
+    Height,
+    Normal,
+    Roughness,
+    Metallic,
+    Shadow,
+    /// What each pixel is, each kind of surface in its own colour
+    Mask,
+}
+
+impl Channel {
+    /// The full texture set
+    pub const ALL: [Channel; 7] = [
+        Channel::Albedo,
+        Channel::Height,
+        Channel::Normal,
+        Channel::Roughness,
+        Channel::Metallic,
+        Channel::Shadow,
+        Channel::Mask,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Channel::Albedo => "albedo",
+            Channel::Height => "height",
+            Channel::Normal => "normal",
+            Channel::Roughness => "roughness",
+            Channel::Metallic => "metallic",
+            Channel::Shadow => "shadow",
+            Channel::Mask => "mask",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|channel| channel.name() == name)
+    }
+
+    /// The file extension of the channel once encoded
+    pub fn extension(self) -> &'static str {
+        match self {
+            Channel::Albedo => "jpg",
+            _ => "png",
+        }
+    }
+
+    /// The MIME type of the channel once encoded
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Channel::Albedo => "image/jpeg",
+            _ => "image/png",
+        }
+    }
+}
+
+/// What a surface is made of, which decides how it reflects light in
+/// the roughness and metallic channels
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Material {
+    Asphalt,
+    Concrete,
+    Roof,
+    Water,
+    Grass,
+    Metal,
+    Glass,
+    Snow,
+}
+
+impl Material {
+    pub const ALL: [Material; 8] = [
+        Material::Asphalt,
+        Material::Concrete,
+        Material::Roof,
+        Material::Water,
+        Material::Grass,
+        Material::Metal,
+        Material::Glass,
+        Material::Snow,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Material::Asphalt => "asphalt",
+            Material::Concrete => "concrete",
+            Material::Roof => "roof",
+            Material::Water => "water",
+            Material::Grass => "grass",
+            Material::Metal => "metal",
+            Material::Glass => "glass",
+            Material::Snow => "snow",
+        }
+    }
+
+    /// How rough the surface is, in [0, 1], from mirror smooth to
+    /// fully diffuse
+    pub fn roughness(self) -> f64 {
+        match self {
+            Material::Asphalt => 0.9,
+            Material::Concrete => 0.8,
+            // Mostly membrane and tiles, with some flashing
+            Material::Roof => 0.6,
+            Material::Water => 0.05,
+            Material::Grass => 0.95,
+            Material::Metal => 0.4,
+            Material::Glass => 0.05,
+            Material::Snow => 0.85,
+        }
+    }
+
+    /// How metallic the surface is, in [0, 1]
+    pub fn metallic(self) -> f64 {
+        match self {
+            Material::Roof => 0.3,
+            Material::Metal => 1.0,
+            _ => 0.0,
+        }
+    }
+}
+
+/// What a pixel is, as it shows up in the mask channel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Label {
+    Ground,
+    Road,
+    Building,
+    Water,
+    Vegetation,
+    Parking,
+    Vacant,
+    Construction,
+    Rail,
+}
+
+impl Label {
+    pub const ALL: [Label; 9] = [
+        Label::Ground,
+        Label::Road,
+        Label::Building,
+        Label::Water,
+        Label::Vegetation,
+        Label::Parking,
+        Label::Vacant,
+        Label::Construction,
+        Label::Rail,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Label::Ground => "ground",
+            Label::Road => "road",
+            Label::Building => "building",
+            Label::Water => "water",
+            Label::Vegetation => "vegetation",
+            Label::Parking => "parking",
+            Label::Vacant => "vacant",
+            Label::Construction => "construction",
+            Label::Rail => "rail",
+        }
+    }
+
+    /// The flat colour the label is drawn in
+    pub fn color(self) -> [u8; 3] {
+        match self {
+            Label::Ground => [0x80, 0x80, 0x80],
+            Label::Road => [0x40, 0x40, 0x40],
+            Label::Building => [0xFF, 0x00, 0x00],
+            Label::Water => [0x00, 0x00, 0xFF],
+            Label::Vegetation => [0x00, 0xFF, 0x00],
+            Label::Parking => [0xFF, 0xFF, 0x00],
+            Label::Vacant => [0xA0, 0x60, 0x20],
+            Label::Construction => [0xFF, 0x80, 0x00],
+            Label::Rail => [0x80, 0x00, 0x80],
+        }
+    }
+}
+
+impl Material {
+    /// What a surface made of the material is, unless whatever
+    /// painted it says otherwise
+    fn label(self) -> Label {
+        match self {
+            Material::Asphalt => Label::Road,
+            Material::Concrete | Material::Snow => Label::Ground,
+            Material::Roof | Material::Metal | Material::Glass => Label::Building,
+            Material::Water => Label::Water,
+            Material::Grass => Label::Vegetation,
+        }
+    }
+}
+
+/// Everything a city renders into, filled in by each object in a
+/// single pass so the channels always line up pixel for pixel.
+#[derive(Clone)]
+pub struct Canvas {
+    pub albedo: DynamicImage,
+    pub heights: HeightField,
+    /// How shadowed each pixel is, white is full shadow. This is
+    /// only filled in once everything has been drawn.
+    pub shadows: GrayImage,
+    /// The sun lighting the canvas, sloped surfaces shade themselves
+    /// by it
+    pub sun: Sun,
+    /// The colours everything is painted in
+    pub palette: Palette,
+    /// The grain and patterns surfaces are broken up with
+    pub texture: Texture,
+    /// The light given off by lamps, windows and the like. This only
+    /// shows up at night.
+    pub emission: RgbImage,
+    materials: Vec<Material>,
+    labels: Vec<Label>,
+}
+
+impl Canvas {
+    /// A canvas covered in a single colour and material, at ground
+    /// level
+    pub fn new(width: u32, height: u32, color: Rgb<u8>, material: Material, sun: Sun) -> Self {
+        Self {
+            albedo: DynamicImage::ImageRgb8(RgbImage::from_pixel(width, height, color)),
+            heights: HeightField::new(width, height),
+            shadows: GrayImage::new(width, height),
+            sun,
+            palette: Palette::default(),
+            texture: Texture::default(),
+            emission: RgbImage::new(width, height),
+            materials: vec![material; (width * height) as usize],
+            labels: vec![material.label(); (width * height) as usize],
+        }
+    }
+
+    /// Paints a region (in pixels) with a colour and material,
+    /// anything outside the canvas is ignored
+    pub fn paint(&mut self, region: Rectangle, color: Rgba<u8>, material: Material) {
+        for pixel in region.interior_int_coords() {
+            self.paint_pixel(pixel.x, pixel.y, color, material);
+        }
+    }
+
+    /// Paints a single pixel, if it's on the canvas
+    pub fn paint_pixel(&mut self, x: u32, y: u32, color: Rgba<u8>, material: Material) {
+        let (width, height) = self.albedo.dimensions();
+        if x < width && y < height {
+            self.albedo.put_pixel(x, y, color);
+            self.materials[(y * width + x) as usize] = material;
+            self.labels[(y * width + x) as usize] = material.label();
+        }
+    }
+
+    pub fn material(&self, x: u32, y: u32) -> Material {
+        let (width, _) = self.albedo.dimensions();
+        self.materials[(y * width + x) as usize]
+    }
+
+    pub fn color(&self, x: u32, y: u32) -> Rgb<u8> {
+        let [red, green, blue, _] = self.albedo.get_pixel(x, y).0;
+        Rgb([red, green, blue])
+    }
+
+    /// Recolours a pixel and changes what it's made of, but not what
+    /// it's labelled as, so snow on a roof is still a building
+    pub fn restyle(&mut self, x: u32, y: u32, color: Rgb<u8>, material: Material) {
+        let (width, _) = self.albedo.dimensions();
+        let [red, green, blue] = color.0;
+        self.albedo.put_pixel(x, y, Rgba([red, green, blue, 0xFF]));
+        self.materials[(y * width + x) as usize] = material;
+    }
+
+    /// Labels a region (in pixels) for the mask channel, over the
+    /// label its material gave it
+    pub fn label(&mut self, region: Rectangle, label: Label) {
+        for pixel in region.interior_int_coords() {
+            self.label_pixel(pixel.x, pixel.y, label);
+        }
+    }
+
+    pub fn label_pixel(&mut self, x: u32, y: u32, label: Label) {
+        let (width, height) = self.albedo.dimensions();
+        if x < width && y < height {
+            self.labels[(y * width + x) as usize] = label;
+        }
+    }
+
+    /// Raises a region (in pixels) to the given height, if it's lower
+    pub fn raise(&mut self, region: Rectangle, height: f64) {
+        for pixel in region.interior_int_coords() {
+            self.heights.raise(pixel.x, pixel.y, height);
+        }
+    }
+
+    /// Darkens the albedo under the shadows, the darkness is how
+    /// much light a full shadow takes away
+    pub fn shade(&mut self, darkness: f64) {
+        let darkness = darkness.clamp(0.0, 1.0);
+        if let Some(albedo) = self.albedo.as_mut_rgb8() {
+            for (pixel, shadow) in albedo.pixels_mut().zip(self.shadows.pixels()) {
+                let light = 1.0 - darkness * shadow.0[0] as f64 / u8::MAX as f64;
+                pixel.0 = pixel.0.map(|channel| (channel as f64 * light).round() as u8);
+            }
+        }
+    }
+
+    /// Adds a soft glow to the emission, fading out from its centre
+    /// to the edge of an ellipse with the given radii (all in pixels)
+    pub fn glow(&mut self, centre: Vector2, radii: Vector2, color: [u8; 3], strength: f64) {
+        let (width, height) = self.emission.dimensions();
+        let radii = Vector2 { x: radii.x.max(0.5), y: radii.y.max(0.5) };
+        let bounds = Rectangle::new(centre - radii, centre + radii);
+
+        for pixel in bounds.interior_int_coords() {
+            if pixel.x >= width || pixel.y >= height {
+                continue;
+            }
+
+            let offset = Vector2 {
+                x: (pixel.x as f64 + 0.5 - centre.x) / radii.x,
+                y: (pixel.y as f64 + 0.5 - centre.y) / radii.y,
+            };
+            let falloff = (1.0 - offset.x * offset.x - offset.y * offset.y).max(0.0).powi(2) * strength;
+
+            let glow = self.emission.get_pixel_mut(pixel.x, pixel.y);
+            for (channel, color) in glow.0.iter_mut().zip(color) {
+                *channel = channel.saturating_add((color as f64 * falloff).round() as u8);
+            }
+        }
+    }
+
+    /// Turns the albedo to night, keeping the brightness share of the
+    /// daylight, tinted towards blue, with the emission added over it
+    pub fn darken(&mut self, brightness: f64, tint: [f64; 3]) {
+        if let Some(albedo) = self.albedo.as_mut_rgb8() {
+            for (pixel, glow) in albedo.pixels_mut().zip(self.emission.pixels()) {
+                for ((channel, glow), tint) in pixel.0.iter_mut().zip(glow.0).zip(tint) {
+                    let dark = *channel as f64 * brightness.clamp(0.0, 1.0) * tint;
+                    *channel = (dark + glow as f64).round().min(u8::MAX as f64) as u8;
+                }
+            }
+        }
+    }
+
+    fn material_map(&self, value: impl Fn(Material) -> f64) -> GrayImage {
+        let (width, height) = self.albedo.dimensions();
+        GrayImage::from_fn(width, height, |x, y| {
+            let material = self.materials[(y * width + x) as usize];
+            Luma([(value(material).clamp(0.0, 1.0) * u8::MAX as f64).round() as u8])
+        })
+    }
+
+    /// The roughness of every pixel, white is fully rough
+    pub fn roughness(&self) -> GrayImage {
+        self.material_map(Material::roughness)
+    }
+
+    /// The mask, with every pixel in the colour of its label
+    pub fn mask(&self) -> RgbImage {
+        let (width, height) = self.albedo.dimensions();
+        RgbImage::from_fn(width, height, |x, y| {
+            Rgb(self.labels[(y * width + x) as usize].color())
+        })
+    }
+
+    /// The metalness of every pixel, white is fully metallic
+    pub fn metallic(&self) -> GrayImage {
+        self.material_map(Material::metallic)
+    }
+}
+
+/// A grid of heights in meters, one per pixel, that renders out to
+/// a 16 bit grayscale heightmap.
+#[derive(Clone)]
+pub struct HeightField {
+    width: u32,
+    height: u32,
+    heights: Vec<f64>,
+}
+
+impl HeightField {
+    /// A flat field with every pixel at ground level
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            heights: vec![0.0; (width * height) as usize],
+        }
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> f64 {
+        self.heights[(y * self.width + x) as usize]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, height: f64) {
+        self.heights[(y * self.width + x) as usize] = height;
+    }
+
+    /// Raises a pixel to the given height, if it's lower
+    pub fn raise(&mut self, x: u32, y: u32, height: f64) {
+        if x < self.width && y < self.height && self.get(x, y) < height {
+            self.set(x, y, height);
+        }
+    }
+
+    /// Converts the heights to 16 bit samples, with 0 at ground level
+    /// and full white at the vertical scale (in meters). Anything
+    /// taller than the vertical scale is clipped.
+    pub fn to_luma16(&self, vertical_scale: f64) -> Vec<u16> {
+        self.heights
+            .iter()
+            .map(|height| {
+                let fraction = (height / vertical_scale).clamp(0.0, 1.0);
+                (fraction * u16::MAX as f64).round() as u16
+            })
+            .collect()
+    }
+
+    /// The rise over run at a pixel along each axis, the pixel size
+    /// is in meters. The edges of the field are taken to carry on
+    /// flat.
+    pub fn gradient(&self, x: u32, y: u32, pixel_size: Vector2) -> Vector2 {
+        let height_at = |x: i64, y: i64| {
+            let x = x.clamp(0, self.width as i64 - 1) as u32;
+            let y = y.clamp(0, self.height as i64 - 1) as u32;
+            self.get(x, y)
+        };
+
+        let (x, y) = (x as i64, y as i64);
+        Vector2 {
+            x: (height_at(x + 1, y) - height_at(x - 1, y)) / (2.0 * pixel_size.x),
+            y: (height_at(x, y + 1) - height_at(x, y - 1)) / (2.0 * pixel_size.y),
+        }
+    }
+
+    /// Works out a tangent space normal map from the heights, in the
+    /// OpenGL convention with green pointing up the image. The pixel
+    /// size is in meters, and the strength exaggerates (or flattens)
+    /// the slopes.
+    pub fn to_normals(&self, pixel_size: Vector2, strength: f64) -> RgbImage {
+        RgbImage::from_fn(self.width, self.height, |x, y| {
+            let Vector2 { x: dx, y: dy } = self.gradient(x, y, pixel_size);
+
+            // Image rows run down the page, so a rise down the page
+            // tilts the normal towards the top of the image
+            let normal = [-dx * strength, dy * strength, 1.0];
+            let length = normal.iter().map(|n| n * n).sum::<f64>().sqrt();
+            Rgb(normal.map(|n| ((n / length * 0.5 + 0.5) * u8::MAX as f64).round() as u8))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn labels_follow_materials_unless_overridden() {
+        let sun = Sun::from_degrees(0.0, 45.0);
+        let mut canvas = Canvas::new(2, 1, Rgb([0, 0, 0]), Material::Concrete, sun);
+        let white = Rgba([0xFF, 0xFF, 0xFF, 0xFF]);
+        canvas.paint_pixel(0, 0, white, Material::Asphalt);
+        canvas.paint_pixel(1, 0, white, Material::Asphalt);
+        canvas.label_pixel(1, 0, Label::Parking);
+
+        let mask = canvas.mask();
+        assert_eq!(mask.get_pixel(0, 0).0, Label::Road.color());
+        assert_eq!(mask.get_pixel(1, 0).0, Label::Parking.color());
+    }
+
+    #[test]
+    fn raise_only_raises() {
+        let mut field = HeightField::new(2, 2);
+        field.raise(1, 1, 10.0);
+        field.raise(1, 1, 5.0);
+        assert_eq!(field.get(1, 1), 10.0);
+        assert_eq!(field.get(0, 0), 0.0);
+    }
+
+    #[test]
+    fn luma_scaling() {
+        let mut field = HeightField::new(3, 1);
+        field.set(1, 0, 50.0);
+        field.set(2, 0, 500.0);
+        assert_eq!(field.to_luma16(100.0), vec![0, 32768, u16::MAX]);
+    }
+
+    #[test]
+    fn flat_normals_point_up() {
+        let field = HeightField::new(2, 2);
+        let normals = field.to_normals(Vector2 { x: 1.0, y: 1.0 }, 1.0);
+        assert_eq!(*normals.get_pixel(0, 0), Rgb([128, 128, 255]));
+    }
+
+    #[test]
+    fn normals_lean_away_from_rise() {
+        // Ground rising towards the east faces west
+        let mut field = HeightField::new(3, 1);
+        field.set(2, 0, 2.0);
+        let normals = field.to_normals(Vector2 { x: 1.0, y: 1.0 }, 1.0);
+        let Rgb([red, green, _]) = *normals.get_pixel(1, 0);
+        assert!(red < 128);
+        assert_eq!(green, 128);
+    }
+}
@@ -0,0 +1,299 @@
+use super::channels::{Canvas, Label, Material};
+use super::geom::*;
+use super::noise::Noise;
+use super::objects::Renderable;
+use super::palette::Surface;
+use super::traffic::{Vehicle, VehicleKind};
+
+/// The size of a parking bay and the breadth of the aisle between two
+/// rows of them, in meters
+const BAY_WIDTH: f64 = 2.5;
+const BAY_DEPTH: f64 = 5.0;
+const AISLE_BREADTH: f64 = 6.0;
+
+/// The gap kept between the bays and the edge of a car park
+const PARKING_MARGIN: f64 = 1.0;
+
+/// The breadth of the lines painted between bays
+const BAY_LINE: f64 = 0.15;
+
+/// The breadth of the fence around a construction site
+const FENCE_BREADTH: f64 = 0.3;
+
+/// The height of a tower crane's jib, and the breadth of its mast
+/// and jib
+const CRANE_HEIGHT: f64 = 45.0;
+const CRANE_BREADTH: f64 = 2.0;
+
+/// The spacing and breadth of the columns sticking up from a
+/// foundation slab
+const COLUMN_SPACING: f64 = 6.0;
+const COLUMN_BREADTH: f64 = 0.6;
+
+/// What fills a block or lot that isn't built on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FillerKind {
+    /// A surface car park, painted with bays
+    Parking,
+    /// Bare earth going to weeds
+    Vacant,
+    /// A fenced site with a slab going up under a tower crane
+    Construction,
+}
+
+impl FillerKind {
+    pub const ALL: [FillerKind; 3] = [
+        FillerKind::Parking,
+        FillerKind::Vacant,
+        FillerKind::Construction,
+    ];
+
+    /// The name of the filler as it appears in cityscript
+    pub fn name(self) -> &'static str {
+        match self {
+            FillerKind::Parking => "parking",
+            FillerKind::Vacant => "vacant",
+            FillerKind::Construction => "construction",
+        }
+    }
+
+    /// What the filler shows up as in the mask channel
+    pub fn label(self) -> Label {
+        match self {
+            FillerKind::Parking => Label::Parking,
+            FillerKind::Vacant => Label::Vacant,
+            FillerKind::Construction => Label::Construction,
+        }
+    }
+}
+
+/// A block or lot left unbuilt
+#[derive(Clone, Debug)]
+pub struct Filler {
+    pub kind: FillerKind,
+    /// Stored relative to the buildings boundary of its block
+    pub footprint: Rectangle,
+    /// The cars in a car park, in the same frame as the footprint
+    pub cars: Vec<Vehicle>,
+    /// Seeds the weeds and the layout of a site, so they don't
+    /// change between renders
+    pub seed: u32,
+    /// The height of the ground it's on
+    pub elevation: f64,
+}
+
+/// Lays out rows of parking bays over an area, in pairs facing each
+/// other across an aisle, running along the longer side
+pub fn bays(area: Rectangle) -> Vec<Rectangle> {
+    let along_x = area.width() >= area.height();
+    let (length, breadth) = if along_x {
+        (area.width(), area.height())
+    } else {
+        (area.height(), area.width())
+    };
+
+    // The bays either side of each aisle, across the car park
+    let mut rows = Vec::new();
+    let mut across = PARKING_MARGIN;
+    while across + BAY_DEPTH + AISLE_BREADTH <= breadth - PARKING_MARGIN {
+        rows.push(across);
+        across += BAY_DEPTH + AISLE_BREADTH;
+        if across + BAY_DEPTH <= breadth - PARKING_MARGIN {
+            rows.push(across);
+            across += BAY_DEPTH;
+        }
+    }
+
+    let per_row = ((length - 2.0 * PARKING_MARGIN) / BAY_WIDTH).floor().max(0.0) as u32;
+    let mut bays = Vec::new();
+    for row in rows {
+        for bay in 0..per_row {
+            let along = PARKING_MARGIN + bay as f64 * BAY_WIDTH;
+            let bay = Rectangle::new(
+                Vector2 { x: along, y: row },
+                Vector2 { x: along + BAY_WIDTH, y: row + BAY_DEPTH },
+            );
+
+            let bay = if along_x { bay } else { bay.transpose() };
+            bays.push(bay.translate(area.start()));
+        }
+    }
+
+    bays
+}
+
+/// A car parked nose in, in the middle of a bay
+pub fn park(bay: Rectangle, paint: Surface) -> Vehicle {
+    let heading = if bay.width() >= bay.height() {
+        Vector2 { x: 1.0, y: 0.0 }
+    } else {
+        Vector2 { x: 0.0, y: 1.0 }
+    };
+
+    Vehicle::new(VehicleKind::Car, bay.centre(), heading, paint)
+}
+
+impl Filler {
+    fn render_parking(&self, offset: Vector2, scale: Vector2, canvas: &mut Canvas) {
+        let footprint = self.footprint.translate(offset);
+        let asphalt = canvas.palette.rgba(Surface::ParkingAsphalt);
+        let line = canvas.palette.rgba(Surface::ParkingLines);
+        canvas.paint(footprint.scale(scale), asphalt, Material::Asphalt);
+        canvas.raise(footprint.scale(scale), self.elevation);
+
+        // Lines run down either side of each bay
+        for bay in bays(self.footprint).into_iter().map(|bay| bay.translate(offset)) {
+            let sides = if bay.width() < bay.height() {
+                [
+                    Rectangle::new(bay.start(), Vector2 { x: bay.start().x + BAY_LINE, y: bay.end().y }),
+                    Rectangle::new(Vector2 { x: bay.end().x - BAY_LINE, y: bay.start().y }, bay.end()),
+                ]
+            } else {
+                [
+                    Rectangle::new(bay.start(), Vector2 { x: bay.end().x, y: bay.start().y + BAY_LINE }),
+                    Rectangle::new(Vector2 { x: bay.start().x, y: bay.end().y - BAY_LINE }, bay.end()),
+                ]
+            };
+
+            for side in sides {
+                canvas.paint(side.scale(scale), line, Material::Asphalt);
+            }
+        }
+
+        for car in self.cars.iter() {
+            car.render(offset, scale, canvas);
+        }
+    }
+
+    /// Paints earth over the footprint, broken up into patches of
+    /// weeds where the noise is high enough
+    fn render_ground(&self, offset: Vector2, scale: Vector2, canvas: &mut Canvas, weeds: f64) {
+        let footprint = self.footprint.translate(offset);
+        let noise = Noise::new(self.seed);
+        let earth = canvas.palette.color(Surface::Earth);
+        let weed = canvas.palette.color(Surface::Weeds);
+
+        for pixel in footprint.scale(scale).interior_int_coords() {
+            let point = Vector2 {
+                x: (pixel.x as f64 + 0.5) / scale.x,
+                y: (pixel.y as f64 + 0.5) / scale.y,
+            };
+
+            // Patches a few meters across, with a fine grain
+            let patch = noise.fractal(point * 0.15, 3);
+            let grain = noise.value(point * 2.0) * 0.08;
+            let (color, material) = if patch > 1.0 - 2.0 * weeds {
+                (weed, Material::Grass)
+            } else {
+                (earth, Material::Concrete)
+            };
+
+            let shade = 1.0 + grain;
+            let [red, green, blue] = color.map(|channel| (channel as f64 * shade).round().min(255.0) as u8);
+            canvas.paint_pixel(pixel.x, pixel.y, image::Rgba::from([red, green, blue, 0xFF]), material);
+            canvas.heights.raise(pixel.x, pixel.y, self.elevation);
+        }
+    }
+
+    fn render_construction(&self, offset: Vector2, scale: Vector2, canvas: &mut Canvas) {
+        let footprint = self.footprint.translate(offset);
+        self.render_ground(offset, scale, canvas, 0.1);
+
+        // A fence runs around the site
+        let fence = canvas.palette.rgba(Surface::Hoarding);
+        let inside = footprint.inset(FENCE_BREADTH);
+        for side in [
+            Rectangle::new(footprint.start(), Vector2 { x: footprint.end().x, y: inside.start().y }),
+            Rectangle::new(Vector2 { x: footprint.start().x, y: inside.end().y }, footprint.end()),
+            Rectangle::new(footprint.start(), Vector2 { x: inside.start().x, y: footprint.end().y }),
+            Rectangle::new(Vector2 { x: inside.end().x, y: footprint.start().y }, footprint.end()),
+        ] {
+            canvas.paint(side.scale(scale), fence, Material::Metal);
+            canvas.raise(side.scale(scale), self.elevation + 2.0);
+        }
+
+        // The slab is poured across the middle of the site, with the
+        // first columns standing up from it on a grid
+        let short_span = footprint.width().min(footprint.height());
+        let slab = footprint.inset(short_span * 0.2);
+        if slab.width() <= 0.0 || slab.height() <= 0.0 {
+            return;
+        }
+
+        let concrete = canvas.palette.rgba(Surface::Slab);
+        let column = canvas.palette.rgba(Surface::Columns);
+        canvas.paint(slab.scale(scale), concrete, Material::Concrete);
+        canvas.raise(slab.scale(scale), self.elevation + 0.5);
+
+        let mut x = slab.start().x + COLUMN_SPACING / 2.0;
+        while x < slab.end().x {
+            let mut y = slab.start().y + COLUMN_SPACING / 2.0;
+            while y < slab.end().y {
+                let half = Vector2 { x: COLUMN_BREADTH / 2.0, y: COLUMN_BREADTH / 2.0 };
+                let centre = Vector2 { x, y };
+                let region = Rectangle::new(centre - half, centre + half).scale(scale);
+                canvas.paint(region, column, Material::Concrete);
+                canvas.raise(region, self.elevation + 3.5);
+                y += COLUMN_SPACING;
+            }
+            x += COLUMN_SPACING;
+        }
+
+        // The crane stands at one corner of the slab, with its jib
+        // swung out over the site
+        let yellow = canvas.palette.rgba(Surface::Crane);
+        let corner = if self.seed.is_multiple_of(2) { slab.start() } else { slab.end() };
+        let half = Vector2 { x: CRANE_BREADTH / 2.0, y: CRANE_BREADTH / 2.0 };
+        let mast = Rectangle::new(corner - half, corner + half);
+        let reach = if (self.seed / 2).is_multiple_of(2) {
+            Vector2 { x: slab.width(), y: 0.0 }
+        } else {
+            Vector2 { x: 0.0, y: slab.height() }
+        };
+        let reach = if self.seed.is_multiple_of(2) { reach } else { reach * -1.0 };
+        let jib = Rectangle::new(corner - half, corner + half + reach)
+            .intersection(&footprint)
+            .unwrap_or(mast);
+
+        for part in [mast, jib] {
+            canvas.paint(part.scale(scale), yellow, Material::Metal);
+            canvas.raise(part.scale(scale), self.elevation + CRANE_HEIGHT);
+        }
+    }
+}
+
+impl Renderable for Filler {
+    fn render(&self, offset: Vector2, scale: Vector2, canvas: &mut Canvas) {
+        match self.kind {
+            FillerKind::Parking => self.render_parking(offset, scale, canvas),
+            FillerKind::Vacant => self.render_ground(offset, scale, canvas, 0.4),
+            FillerKind::Construction => self.render_construction(offset, scale, canvas),
+        }
+
+        let region = self.footprint.translate(offset).scale(scale);
+        canvas.label(region, self.kind.label());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bays_fit_inside_the_car_park() {
+        let area = Rectangle::new(Vector2 { x: 10.0, y: 20.0 }, Vector2 { x: 40.0, y: 60.0 });
+        let bays = bays(area);
+
+        // The car park runs along y, with a pair of rows facing each
+        // other and another backing onto them
+        assert_eq!(bays.len(), 3 * 15);
+        for (i, bay) in bays.iter().enumerate() {
+            let inside = bay.intersection(&area).unwrap();
+            assert!((inside.dimensions() - bay.dimensions()).mag() < 1e-9);
+            assert!(bay.height() < bay.width());
+            for other in bays.iter().skip(i + 1) {
+                assert!(bay.intersection(other).is_none());
+            }
+        }
+    }
+}
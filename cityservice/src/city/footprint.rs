@@ -0,0 +1,192 @@
+use super::geom::*;
+
+/// Shaped footprints need at least this much room across their lot,
+/// in meters, narrower lots get a plain box
+pub const MIN_SHAPED_SPAN: f64 = 12.0;
+
+/// The share of the full height reached by each tier of a stepped
+/// tower, from the bottom up
+const TIER_HEIGHTS: [f64; 3] = [0.4, 0.7, 1.0];
+
+/// How far each tier of a stepped tower is set in from the one below,
+/// as a share of the short span of the lot
+const TIER_INSET: f64 = 0.15;
+
+/// The outline of a building in plan
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FootprintShape {
+    Box,
+    /// Two wings meeting at a corner
+    L,
+    /// Three wings around a yard open on one side
+    U,
+    /// A wing across one end, with another running from its middle
+    T,
+    /// Two parallel wings joined across their middles
+    H,
+    /// Four wings around an enclosed courtyard
+    Courtyard,
+    /// A tower set back in tiers as it rises
+    Stepped,
+}
+
+impl FootprintShape {
+    pub const ALL: [FootprintShape; 7] = [
+        FootprintShape::Box,
+        FootprintShape::L,
+        FootprintShape::U,
+        FootprintShape::T,
+        FootprintShape::H,
+        FootprintShape::Courtyard,
+        FootprintShape::Stepped,
+    ];
+
+    /// The name of the shape as it appears in cityscript
+    pub fn name(self) -> &'static str {
+        match self {
+            FootprintShape::Box => "box",
+            FootprintShape::L => "l",
+            FootprintShape::U => "u",
+            FootprintShape::T => "t",
+            FootprintShape::H => "h",
+            FootprintShape::Courtyard => "courtyard",
+            FootprintShape::Stepped => "stepped",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Footprint {
+    pub shape: FootprintShape,
+    /// How the shape is turned within its lot, as three flags: mirror
+    /// across x, mirror across y, and swap x for y
+    pub orientation: u8,
+    /// The breadth of each wing as a share of the short span of the
+    /// lot
+    pub wing: f64,
+}
+
+impl Default for Footprint {
+    fn default() -> Self {
+        Self {
+            shape: FootprintShape::Box,
+            orientation: 0,
+            wing: 0.5,
+        }
+    }
+}
+
+impl Footprint {
+    /// The wings making up a building that fills a lot of the given
+    /// size, relative to the start of the lot. Each wing comes with
+    /// the share of the building's full height it reaches. Wings can
+    /// overlap where they meet.
+    pub fn wings(&self, size: Vector2) -> Vec<(Rectangle, f64)> {
+        let transposed = self.orientation & 4 != 0;
+        let size = if transposed { size.transpose() } else { size };
+
+        let short_span = size.x.min(size.y);
+        let breadth = short_span * self.wing.clamp(0.1, 0.45);
+        let rectangle = |x0: f64, y0: f64, x1: f64, y1: f64| Rectangle::new(
+            Vector2 { x: x0, y: y0 },
+            Vector2 { x: x1, y: y1 },
+        );
+
+        let (width, height) = (size.x, size.y);
+        let middle = |span: f64| ((span - breadth) / 2.0, (span + breadth) / 2.0);
+        let left = rectangle(0.0, 0.0, breadth, height);
+        let right = rectangle(width - breadth, 0.0, width, height);
+        let top = rectangle(0.0, 0.0, width, breadth);
+        let bottom = rectangle(0.0, height - breadth, width, height);
+
+        let wings = match self.shape {
+            FootprintShape::Box => vec![(rectangle(0.0, 0.0, width, height), 1.0)],
+            FootprintShape::L => vec![(left, 1.0), (bottom, 1.0)],
+            FootprintShape::U => vec![(left, 1.0), (right, 1.0), (bottom, 1.0)],
+            FootprintShape::T => {
+                let (stem_start, stem_end) = middle(width);
+                vec![(top, 1.0), (rectangle(stem_start, 0.0, stem_end, height), 1.0)]
+            },
+            FootprintShape::H => {
+                let (bar_start, bar_end) = middle(height);
+                vec![(left, 1.0), (right, 1.0), (rectangle(0.0, bar_start, width, bar_end), 1.0)]
+            },
+            FootprintShape::Courtyard => vec![(top, 1.0), (bottom, 1.0), (left, 1.0), (right, 1.0)],
+            FootprintShape::Stepped => {
+                let lot = rectangle(0.0, 0.0, width, height);
+                TIER_HEIGHTS
+                    .iter()
+                    .enumerate()
+                    .map(|(tier, &share)| (lot.inset(short_span * TIER_INSET * tier as f64), share))
+                    .collect()
+            },
+        };
+
+        wings
+            .into_iter()
+            .map(|(wing, share)| {
+                let wing = if self.orientation & 1 != 0 { wing.mirror_x(width) } else { wing };
+                let wing = if self.orientation & 2 != 0 { wing.mirror_y(height) } else { wing };
+                let wing = if transposed { wing.transpose() } else { wing };
+                (wing, share)
+            })
+            .collect()
+    }
+
+    /// The share of the full height the building reaches at a point
+    /// (relative to the start of its lot), or None off the building
+    pub fn tier_at(wings: &[(Rectangle, f64)], point: Vector2) -> Option<f64> {
+        wings
+            .iter()
+            .filter(|(wing, _)| wing.contains(point))
+            .map(|&(_, share)| share)
+            .reduce(f64::max)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SIZE: Vector2 = Vector2 { x: 30.0, y: 20.0 };
+
+    #[test]
+    fn wings_fit_inside_the_lot() {
+        let lot = Rectangle::new(Vector2::default(), SIZE);
+        for shape in FootprintShape::ALL {
+            for orientation in 0..8 {
+                let footprint = Footprint { shape, orientation, wing: 0.4 };
+                for (wing, _) in footprint.wings(SIZE) {
+                    let inside = wing.intersection(&lot).unwrap();
+                    assert!((inside.dimensions() - wing.dimensions()).mag() < 1e-9);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn courtyard_is_open_in_the_middle() {
+        let footprint = Footprint {
+            shape: FootprintShape::Courtyard,
+            orientation: 0,
+            wing: 0.3,
+        };
+
+        let wings = footprint.wings(SIZE);
+        assert!(Footprint::tier_at(&wings, SIZE * 0.5).is_none());
+        assert_eq!(Footprint::tier_at(&wings, Vector2 { x: 1.0, y: 10.0 }), Some(1.0));
+    }
+
+    #[test]
+    fn stepped_tower_rises_towards_the_middle() {
+        let footprint = Footprint {
+            shape: FootprintShape::Stepped,
+            orientation: 0,
+            wing: 0.3,
+        };
+
+        let wings = footprint.wings(SIZE);
+        assert_eq!(Footprint::tier_at(&wings, Vector2 { x: 1.0, y: 1.0 }), Some(0.4));
+        assert_eq!(Footprint::tier_at(&wings, SIZE * 0.5), Some(1.0));
+    }
+}
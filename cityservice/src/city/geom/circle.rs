@@ -1,13 +1,23 @@
-use super::Vector2;
+use super::{Rectangle, Vector2};
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub struct Circle {
-    center: Vector2,
-    radius: f64,
+    pub center: Vector2,
+    pub radius: f64,
 }
 
 impl Circle {
-    fn contains(&self, point: Vector2) -> bool {
+    pub fn new(center: Vector2, radius: f64) -> Self {
+        Self { center, radius }
+    }
+
+    pub fn contains(&self, point: Vector2) -> bool {
         (point - self.center).mag() < self.radius
     }
+
+    /// The smallest rectangle the circle fits in
+    pub fn bounds(&self) -> Rectangle {
+        let corner = Vector2 { x: self.radius, y: self.radius };
+        Rectangle::new(self.center - corner, self.center + corner)
+    }
 }
@@ -1,9 +1,7 @@
 pub mod rectangle;
 
-// This module remains unused as parks aren't implemented and there
-// are no air conditioners tha need fans
-// pub mod circle;
+pub mod circle;
 
 pub mod vector;
 
-pub use {rectangle::*, /*circle::*,*/ vector::*};
+pub use {rectangle::*, circle::*, vector::*};
@@ -1,6 +1,6 @@
 use super::{Vector2, Vector2i};
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub struct Rectangle {
     start: Vector2,
     end: Vector2,
@@ -66,10 +66,14 @@ impl Rectangle {
         Vector2{ x: self.width(), y: self.height() }
     }
 
+    pub fn centre(&self) -> Vector2 {
+        (self.start + self.end) * 0.5
+    }
+
     #[allow(dead_code)]
     pub fn contains(&self, point: Vector2) -> bool {
         point.east_of(self.start) && point.west_of(self.end) &&
-            point.north_of(self.end) && point.south_of(self.start)
+            point.north_of(self.start) && point.south_of(self.end)
     }
 
     pub fn scale(&self, by: Vector2) -> Self {
@@ -95,4 +99,49 @@ impl Rectangle {
             (other.start.y..other.end.y).contains(&self.end.y) &&
             (other.end.x < self.start.x || other.start.x < self.end.x)
     }
+
+    /// Returns the overlapping region of the two rectangles, or
+    /// `None` if they do not overlap (touching edges don't count).
+    pub fn intersection(&self, other: &Rectangle) -> Option<Rectangle> {
+        let start = Vector2 {
+            x: f64::max(self.start.x, other.start.x),
+            y: f64::max(self.start.y, other.start.y),
+        };
+
+        let end = Vector2 {
+            x: f64::min(self.end.x, other.end.x),
+            y: f64::min(self.end.y, other.end.y),
+        };
+
+        if start.x < end.x && start.y < end.y {
+            Some(Self { start, end })
+        } else {
+            None
+        }
+    }
+
+    /// Mirrors the rectangle across the line x = y, this lets code
+    /// that only cares about one axis handle the other one too.
+    pub fn transpose(&self) -> Self {
+        Self {
+            start: self.start.transpose(),
+            end: self.end.transpose(),
+        }
+    }
+
+    /// Reflects the rectangle across the vertical line at x = width / 2
+    pub fn mirror_x(&self, width: f64) -> Self {
+        Self::new(
+            Vector2 { x: width - self.start.x, y: self.start.y },
+            Vector2 { x: width - self.end.x, y: self.end.y },
+        )
+    }
+
+    /// Reflects the rectangle across the horizontal line at y = height / 2
+    pub fn mirror_y(&self, height: f64) -> Self {
+        Self::new(
+            Vector2 { x: self.start.x, y: height - self.start.y },
+            Vector2 { x: self.end.x, y: height - self.end.y },
+        )
+    }
 }
@@ -91,6 +91,13 @@ impl Vector2 {
     pub fn west_of(self, point: Vector2) -> bool {
         self.x < point.x
     }
+
+    pub fn transpose(self) -> Vector2 {
+        Vector2 {
+            x: self.y,
+            y: self.x,
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -0,0 +1,162 @@
+use super::geom::*;
+use super::noise::Noise;
+
+/// Roads are reached a piece at a time, each piece this long
+const ROAD_PIECE_LENGTH: f64 = 8.0;
+
+/// The bulges in the edge of the city as it spreads are roughly this
+/// far apart, in meters
+const EDGE_FEATURE_SIZE: f64 = 150.0;
+
+/// Buildings go up no taller than this, in meters, and rise to their
+/// full height from there as the city densifies
+const STARTING_HEIGHT: f64 = 6.0;
+
+/// How a city spreads out from its middle over a number of epochs.
+/// Times run from zero at the start of the growth to one at the end
+/// of the last epoch, when the city stands complete.
+#[derive(Clone, Copy, Debug)]
+pub struct Growth {
+    pub epochs: u32,
+    pub centre: Vector2,
+    /// The distance from the centre to the furthest corner
+    pub reach: f64,
+    /// The share of the time buildings take to rise to full height,
+    /// the city spreads over the rest
+    pub densification: f64,
+    /// How far the edge wanders, as a share of the reach
+    pub irregularity: f64,
+    pub noise: Noise,
+}
+
+impl Growth {
+    /// The time at the end of an epoch, counting from zero
+    pub fn time(&self, epoch: u32) -> f64 {
+        (epoch + 1) as f64 / self.epochs.max(1) as f64
+    }
+
+    /// When the city reaches a point, the nearer the centre the
+    /// sooner, but with the edge bulging out in some places and
+    /// lagging behind in others
+    pub fn arrival(&self, point: Vector2) -> f64 {
+        let spread = 1.0 - self.densification.clamp(0.0, 1.0);
+        let distance = (point - self.centre).mag() / self.reach.max(1.0);
+        let wander = self.irregularity * self.noise.value(point * (1.0 / EDGE_FEATURE_SIZE));
+
+        spread * (distance + wander).clamp(0.0, 1.0)
+    }
+
+    /// When a building goes up on a block, somewhere in the first
+    /// half of the densification after the block is reached. The
+    /// share picks where.
+    pub fn founding(&self, block: Rectangle, share: f64) -> f64 {
+        self.arrival(block.centre()) + share.clamp(0.0, 1.0) * self.densification / 2.0
+    }
+
+    /// How tall a building of a full height founded at one time
+    /// stands at another, if it's up yet. It goes up low and rises
+    /// over the second half of the densification, so by the end of
+    /// the growth everything stands at full height.
+    pub fn height(&self, founded: f64, full: f64, time: f64) -> Option<f64> {
+        if time >= 1.0 {
+            return Some(full);
+        }
+
+        if time < founded {
+            return None;
+        }
+
+        let rising = self.densification / 2.0;
+        let progress = if rising > 0.0 { ((time - founded) / rising).min(1.0) } else { 1.0 };
+        let start = full.min(STARTING_HEIGHT);
+
+        Some(start + (full - start) * progress)
+    }
+
+    /// The pieces of a road reached by a time
+    pub fn road_pieces(&self, asphalt: Rectangle, time: f64) -> Vec<Rectangle> {
+        let along_x = asphalt.width() >= asphalt.height();
+        let frame = if along_x { asphalt } else { asphalt.transpose() };
+        let count = (frame.width() / ROAD_PIECE_LENGTH).ceil().max(1.0) as u32;
+        let length = frame.width() / count as f64;
+
+        (0..count)
+            .map(|i| {
+                let x = frame.start().x + i as f64 * length;
+                let piece = Rectangle::new(
+                    Vector2 { x, y: frame.start().y },
+                    Vector2 { x: x + length, y: frame.end().y },
+                );
+                if along_x { piece } else { piece.transpose() }
+            })
+            .filter(|piece| self.arrival(piece.centre()) <= time)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::settings::Settings;
+    use super::super::settings::config::GrowthConfig;
+
+    fn growth() -> Growth {
+        Growth {
+            epochs: 10,
+            centre: Vector2 { x: 500.0, y: 500.0 },
+            reach: 500.0 * 2f64.sqrt(),
+            densification: 0.3,
+            irregularity: 0.15,
+            noise: Noise::new(7),
+        }
+    }
+
+    #[test]
+    fn roads_spread_out_until_complete() {
+        let growth = growth();
+        let road = Rectangle::new(Vector2 { x: 0.0, y: 495.0 }, Vector2 { x: 1000.0, y: 505.0 });
+
+        let mut reached = 0.0;
+        for epoch in 0..growth.epochs {
+            let pieces = growth.road_pieces(road, growth.time(epoch));
+            let length: f64 = pieces.iter().map(|piece| piece.width()).sum();
+            assert!(length >= reached);
+            reached = length;
+        }
+
+        assert!(growth.road_pieces(road, growth.time(0)).len() < 125);
+        assert!((reached - 1000.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn buildings_only_ever_rise() {
+        let growth = growth();
+        let block = Rectangle::new(Vector2 { x: 800.0, y: 100.0 }, Vector2 { x: 900.0, y: 180.0 });
+
+        for share in [0.0, 0.5, 1.0] {
+            let founded = growth.founding(block, share);
+            let heights: Vec<Option<f64>> = (0..growth.epochs)
+                .map(|epoch| growth.height(founded, 40.0, growth.time(epoch)))
+                .collect();
+
+            assert!(heights[0].is_none());
+            assert_eq!(heights.last().copied().flatten(), Some(40.0));
+            for pair in heights.windows(2) {
+                assert!(pair[0].unwrap_or(0.0) <= pair[1].unwrap_or(0.0));
+            }
+        }
+    }
+
+    #[test]
+    fn epochs_are_a_plain_count() {
+        let mut settings = Settings::default();
+        settings.update("let growth.epochs be 24").unwrap();
+        assert_eq!(GrowthConfig::try_from(&settings).unwrap().epochs, 24);
+
+        settings.update("let growth.epochs be 0").unwrap();
+        assert_eq!(GrowthConfig::try_from(&settings).unwrap().epochs, 1);
+
+        settings.update("let growth.epochs be 61").unwrap();
+        assert!(GrowthConfig::try_from(&settings).is_err());
+    }
+}
@@ -0,0 +1,337 @@
+use super::channels::{Canvas, Material};
+use super::footprint::Footprint;
+use super::geom::*;
+use super::objects::{Building, Renderable};
+use super::palette::Surface;
+use super::rail::{GAUGE, PLATFORM_BREADTH, PLATFORM_HEIGHT, RAIL_BREADTH, TRACK_SPACING};
+use super::roof::{Roof, RoofShape};
+use super::rooftop::{Equipment, RooftopKind};
+use super::texture::Finish;
+use super::zone::Zone;
+
+/// The share of a stadium's radius taken by the pitch and the grass
+/// around it, and by the running track
+const STADIUM_FIELD: f64 = 0.6;
+const STADIUM_TRACK: f64 = 0.72;
+
+/// The height of the top row of a stadium's stands, and how many
+/// rows they're painted in
+const STAND_HEIGHT: f64 = 24.0;
+const STAND_TIERS: f64 = 12.0;
+
+/// The breadth of the stripes mown into a pitch, and of the lines
+/// painted on it, in meters
+const PITCH_STRIPE: f64 = 5.0;
+const PITCH_LINE: f64 = 0.4;
+
+/// The spacing of the air conditioning units along a mall's roof
+const MALL_HVAC_SPACING: f64 = 14.0;
+
+/// The rare large structures that take up a whole block, or a pair of
+/// blocks merged into one
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LandmarkKind {
+    /// An oval of stands around a running track and a pitch
+    Stadium,
+    /// A cross shaped church with towers at its west end
+    Cathedral,
+    /// A station building in front of a shed over the platforms
+    Station,
+    /// A shopping mall under one big roof, lit by an atrium
+    Mall,
+}
+
+impl LandmarkKind {
+    pub const ALL: [LandmarkKind; 4] = [
+        LandmarkKind::Stadium,
+        LandmarkKind::Cathedral,
+        LandmarkKind::Station,
+        LandmarkKind::Mall,
+    ];
+
+    /// The name of the kind as it appears in cityscript
+    pub fn name(self) -> &'static str {
+        match self {
+            LandmarkKind::Stadium => "stadium",
+            LandmarkKind::Cathedral => "cathedral",
+            LandmarkKind::Station => "station",
+            LandmarkKind::Mall => "mall",
+        }
+    }
+
+    /// The zone the block holding the landmark is put in
+    pub fn zone(self) -> Zone {
+        match self {
+            LandmarkKind::Mall => Zone::Commercial,
+            _ => Zone::Civic,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Landmark {
+    pub kind: LandmarkKind,
+    /// Stored relative to the buildings boundary of its block, the
+    /// landmark's length runs along the longer side
+    pub footprint: Rectangle,
+    /// The height of the ground it stands on
+    pub elevation: f64,
+}
+
+impl Landmark {
+    /// Places a rectangle given in a frame where the landmark's length
+    /// runs along x, relative to its start
+    fn place(&self, rectangle: Rectangle) -> Rectangle {
+        let rectangle = if self.footprint.width() >= self.footprint.height() {
+            rectangle
+        } else {
+            rectangle.transpose()
+        };
+
+        rectangle.translate(self.footprint.start())
+    }
+
+    /// The length and breadth of the landmark
+    fn extent(&self) -> Vector2 {
+        let size = self.footprint.dimensions();
+        if size.x >= size.y { size } else { size.transpose() }
+    }
+
+    /// A plain building standing as part of the landmark, given in
+    /// the frame where the length runs along x
+    fn part(&self, rectangle: Rectangle, height: f64, roof: Roof, roof_color: [u8; 4], finish: Finish) -> Building {
+        Building {
+            footprint: self.place(rectangle),
+            shape: Footprint::default(),
+            roof_edge_breadth: 0.8,
+            floors: 1,
+            height,
+            elevation: self.elevation,
+            roof_color,
+            roof,
+            finish,
+            equipment: Vec::new(),
+        }
+    }
+
+    fn render_stadium(&self, offset: Vector2, scale: Vector2, canvas: &mut Canvas) {
+        let footprint = self.footprint.translate(offset);
+        let centre = footprint.centre();
+        let radii = footprint.dimensions() * 0.5;
+
+        // The pitch sits in the middle of the field, along its length
+        let along_x = radii.x >= radii.y;
+        let pitch_radii = if along_x {
+            Vector2 { x: radii.x * 0.42, y: radii.y * 0.38 }
+        } else {
+            Vector2 { x: radii.x * 0.38, y: radii.y * 0.42 }
+        };
+
+        // Every other tier of the stands is in shadow, and every other
+        // stripe of the pitch is mown the other way
+        let stands = canvas.palette.rgba(Surface::Stands);
+        let stands_shadowed = canvas.palette.shaded(Surface::Stands, 0.85);
+        let track = canvas.palette.rgba(Surface::RunningTrack);
+        let grass = canvas.palette.rgba(Surface::Pitch);
+        let grass_mown = canvas.palette.shaded(Surface::Pitch, 1.12);
+        let line = canvas.palette.rgba(Surface::PitchLines);
+
+        for pixel in footprint.scale(scale).interior_int_coords() {
+            let point = Vector2 {
+                x: (pixel.x as f64 + 0.5) / scale.x,
+                y: (pixel.y as f64 + 0.5) / scale.y,
+            };
+
+            let from_centre = point - centre;
+            let radius = ((from_centre.x / radii.x).powi(2) + (from_centre.y / radii.y).powi(2)).sqrt();
+            if radius > 1.0 {
+                continue;
+            }
+
+            if radius >= STADIUM_TRACK {
+                // The stands rise away from the track
+                let rise = (radius - STADIUM_TRACK) / (1.0 - STADIUM_TRACK);
+                let color = if ((rise * STAND_TIERS) as u32).is_multiple_of(2) { stands } else { stands_shadowed };
+                canvas.paint_pixel(pixel.x, pixel.y, color, Material::Concrete);
+                canvas.heights.raise(pixel.x, pixel.y, self.elevation + STAND_HEIGHT * rise);
+                continue;
+            }
+
+            if radius >= STADIUM_FIELD {
+                canvas.paint_pixel(pixel.x, pixel.y, track, Material::Concrete);
+                canvas.heights.raise(pixel.x, pixel.y, self.elevation);
+                continue;
+            }
+
+            let along = if along_x { from_centre.x } else { from_centre.y };
+            let on_pitch = from_centre.x.abs() <= pitch_radii.x && from_centre.y.abs() <= pitch_radii.y;
+            let on_line = on_pitch && (
+                pitch_radii.x - from_centre.x.abs() < PITCH_LINE
+                || pitch_radii.y - from_centre.y.abs() < PITCH_LINE
+                || along.abs() < PITCH_LINE / 2.0
+            );
+
+            let color = if on_line {
+                line
+            } else if on_pitch && ((along / PITCH_STRIPE).floor() as i64).rem_euclid(2) == 0 {
+                grass_mown
+            } else {
+                grass
+            };
+
+            canvas.paint_pixel(pixel.x, pixel.y, color, Material::Grass);
+            canvas.heights.raise(pixel.x, pixel.y, self.elevation);
+        }
+    }
+
+    fn render_cathedral(&self, offset: Vector2, scale: Vector2, canvas: &mut Canvas) {
+        let Vector2 { x: length, y: breadth } = self.extent();
+        let span = |x0: f64, x1: f64, y0: f64, y1: f64| Rectangle::new(
+            Vector2 { x: length * x0, y: breadth * y0 },
+            Vector2 { x: length * x1, y: breadth * y1 },
+        );
+
+        let copper = canvas.palette.rgba(Surface::Copper).0;
+        let slate = canvas.palette.rgba(Surface::Slate).0;
+        let stone = canvas.palette.rgba(Surface::Stone).0;
+        let gabled = |pitch| Roof { shape: RoofShape::Gabled, pitch };
+        let spire = Roof { shape: RoofShape::Hipped, pitch: 3.0 };
+
+        // Lower aisles either side of the nave, then the nave and the
+        // transept crossing it, the apse at the east end, and a pair
+        // of towers at the west end
+        let parts = [
+            self.part(span(0.16, 0.84, 0.18, 0.82), 12.0, gabled(0.5), slate, Finish::Tiles),
+            self.part(span(0.14, 0.88, 0.33, 0.67), 24.0, gabled(1.0), copper, Finish::Seams),
+            self.part(span(0.6, 0.74, 0.0, 1.0), 24.0, gabled(1.0), copper, Finish::Seams),
+            self.part(span(0.86, 0.98, 0.36, 0.64), 18.0, Roof { shape: RoofShape::Hipped, pitch: 1.0 }, copper, Finish::Seams),
+            self.part(span(0.02, 0.16, 0.12, 0.38), 36.0, spire, stone, Finish::Tiles),
+            self.part(span(0.02, 0.16, 0.62, 0.88), 36.0, spire, stone, Finish::Tiles),
+        ];
+
+        for part in parts.iter() {
+            part.render(offset, scale, canvas);
+        }
+    }
+
+    fn render_station(&self, offset: Vector2, scale: Vector2, canvas: &mut Canvas) {
+        let Vector2 { x: length, y: breadth } = self.extent();
+        let strip = |x0: f64, y0: f64, x1: f64, y1: f64| self
+            .place(Rectangle::new(Vector2 { x: x0, y: y0 }, Vector2 { x: x1, y: y1 }))
+            .translate(offset)
+            .scale(scale);
+
+        let head_house = length * 0.18;
+        let ballast = canvas.palette.shaded(Surface::Ballast, 0.85);
+        let platform = canvas.palette.rgba(Surface::Platforms);
+        let rail = canvas.palette.rgba(Surface::Rails);
+
+        // Platforms and pairs of tracks take turns across the station
+        canvas.paint(strip(head_house, 0.0, length, breadth), ballast, Material::Concrete);
+        canvas.raise(strip(head_house, 0.0, length, breadth), self.elevation);
+
+        let mut across = 0.0;
+        let mut tracks: u32 = 0;
+        while across < breadth {
+            if tracks.is_multiple_of(3) {
+                let end = (across + PLATFORM_BREADTH).min(breadth);
+                let region = strip(head_house, across, length, end);
+                canvas.paint(region, platform, Material::Concrete);
+                canvas.raise(region, self.elevation + PLATFORM_HEIGHT);
+                across = end;
+            } else {
+                let centre = across + TRACK_SPACING / 2.0;
+                for side in [-1.0, 1.0] {
+                    let rail_centre = centre + side * GAUGE / 2.0;
+                    if rail_centre + RAIL_BREADTH / 2.0 <= breadth {
+                        let region = strip(
+                            head_house,
+                            rail_centre - RAIL_BREADTH / 2.0,
+                            length,
+                            rail_centre + RAIL_BREADTH / 2.0,
+                        );
+                        canvas.paint(region, rail, Material::Metal);
+                    }
+                }
+
+                across += TRACK_SPACING;
+            }
+
+            tracks += 1;
+        }
+
+        // The shed covers the inner end of the platforms, and the
+        // station building stands across their heads
+        let glass = canvas.palette.rgba(Surface::TrainShed).0;
+        let stone = canvas.palette.rgba(Surface::Terminus).0;
+        let shed = Rectangle::new(
+            Vector2 { x: head_house, y: 0.0 },
+            Vector2 { x: length * 0.65, y: breadth },
+        );
+        let building = Rectangle::new(Vector2::default(), Vector2 { x: head_house, y: breadth });
+
+        self.part(shed, 10.0, Roof { shape: RoofShape::Gabled, pitch: 0.35 }, glass, Finish::Seams)
+            .render(offset, scale, canvas);
+        self.part(building, 18.0, Roof { shape: RoofShape::Flat, pitch: 0.0 }, stone, Finish::Gravel)
+            .render(offset, scale, canvas);
+    }
+
+    fn render_mall(&self, offset: Vector2, scale: Vector2, canvas: &mut Canvas) {
+        let Vector2 { x: length, y: breadth } = self.extent();
+        let height = 14.0;
+        let span = |x0: f64, y0: f64, x1: f64, y1: f64| Rectangle::new(
+            Vector2 { x: x0, y: y0 },
+            Vector2 { x: x1, y: y1 },
+        );
+
+        let mut mall = self.part(
+            span(0.0, 0.0, length, breadth),
+            height,
+            Roof { shape: RoofShape::Flat, pitch: 0.0 },
+            canvas.palette.rgba(Surface::Mall).0,
+            Finish::Gravel,
+        );
+
+        // Atriums run down the length of the mall and across its
+        // middle, and units line the roof either side
+        let equipment = |kind, rectangle| Equipment {
+            kind,
+            footprint: self.place(rectangle),
+            elevation: self.elevation + height,
+        };
+
+        let atrium = breadth * 0.12;
+        mall.equipment.push(equipment(
+            RooftopKind::Skylight,
+            span(length * 0.08, (breadth - atrium) / 2.0, length * 0.92, (breadth + atrium) / 2.0),
+        ));
+        mall.equipment.push(equipment(
+            RooftopKind::Skylight,
+            span((length - atrium) / 2.0, breadth * 0.1, (length + atrium) / 2.0, breadth * 0.9),
+        ));
+
+        let mut along = MALL_HVAC_SPACING;
+        while along + MALL_HVAC_SPACING <= length {
+            for row in [0.22, 0.78] {
+                let centre = Vector2 { x: along, y: breadth * row };
+                let half = Vector2 { x: 2.0, y: 1.5 };
+                mall.equipment.push(equipment(RooftopKind::Hvac, Rectangle::new(centre - half, centre + half)));
+            }
+
+            along += MALL_HVAC_SPACING;
+        }
+
+        mall.render(offset, scale, canvas);
+    }
+}
+
+impl Renderable for Landmark {
+    fn render(&self, offset: Vector2, scale: Vector2, canvas: &mut Canvas) {
+        match self.kind {
+            LandmarkKind::Stadium => self.render_stadium(offset, scale, canvas),
+            LandmarkKind::Cathedral => self.render_cathedral(offset, scale, canvas),
+            LandmarkKind::Station => self.render_station(offset, scale, canvas),
+            LandmarkKind::Mall => self.render_mall(offset, scale, canvas),
+        }
+    }
+}
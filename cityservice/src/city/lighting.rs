@@ -0,0 +1,233 @@
+use std::f64::consts::FRAC_PI_2;
+
+use image::{GrayImage, Luma};
+
+use super::channels::HeightField;
+use super::geom::Vector2;
+
+/// The lowest the sun is allowed to sit when casting shadows, below
+/// this the shadows would stretch right across the city
+const MIN_SHADOW_ELEVATION: f64 = 1.0;
+
+/// The share of light that comes from the rest of the sky rather
+/// than the sun, so slopes facing away from the sun aren't black
+const AMBIENT_LIGHT: f64 = 0.35;
+
+/// Where the sun is in the sky, both angles in radians
+#[derive(Clone, Copy, Debug)]
+pub struct Sun {
+    /// Clockwise from north, the top of the image
+    pub azimuth: f64,
+    /// Above the horizon
+    pub elevation: f64,
+}
+
+impl Sun {
+    pub fn from_degrees(azimuth: f64, elevation: f64) -> Self {
+        Self {
+            azimuth: azimuth.to_radians(),
+            elevation: elevation.clamp(0.0, 90.0).to_radians(),
+        }
+    }
+
+    /// The unit direction along the ground towards the sun, in image
+    /// coordinates (y runs down the page)
+    pub fn heading(&self) -> Vector2 {
+        Vector2 {
+            x: self.azimuth.sin(),
+            y: -self.azimuth.cos(),
+        }
+    }
+
+    /// How brightly the sun lights a surface with the given gradient
+    /// (rise over run along x and y), relative to flat ground. Slopes
+    /// facing the sun come out above 1, and those facing away below.
+    pub fn shade(&self, gradient: Vector2) -> f64 {
+        let heading = self.heading();
+        let (across, up) = (self.elevation.cos(), self.elevation.sin());
+        let facing = -gradient.x * heading.x * across - gradient.y * heading.y * across + up;
+        let tilt = (gradient.mag_squared() + 1.0).sqrt();
+
+        let direct = (facing / tilt / up.max(f64::EPSILON)).max(0.0);
+        AMBIENT_LIGHT + (1.0 - AMBIENT_LIGHT) * direct
+    }
+}
+
+/// How the city is lit, and how dark and soft its shadows are
+#[derive(Clone, Copy, Debug)]
+pub struct Lighting {
+    pub sun: Sun,
+    /// How much light a shadow takes away, in [0, 1]
+    pub shadow_darkness: f64,
+    /// The angular width of the penumbra, in radians
+    pub shadow_softness: f64,
+}
+
+impl Default for Lighting {
+    fn default() -> Self {
+        Self {
+            sun: Sun::from_degrees(135.0, 45.0),
+            shadow_darkness: 0.0,
+            shadow_softness: 0.0,
+        }
+    }
+}
+
+impl Lighting {
+    /// Works out how shadowed every pixel is in a single sweep over
+    /// the heights, away from the sun. Each pixel carries on the top
+    /// of the shadow cast by everything towards the sun from it,
+    /// which drops by the slope of the sunlight each step, and is
+    /// raised by whatever stands there. Anything below the shadow is
+    /// in it, and anything within the penumbra is partly shadowed.
+    /// This puts shadows on the ground and on lower roofs alike.
+    ///
+    /// Full white in the result is full shadow. The pixel size is in
+    /// meters.
+    pub fn cast_shadows(&self, heights: &HeightField, pixel_size: Vector2) -> GrayImage {
+        let (width, height) = heights.dimensions();
+
+        let half_softness = self.shadow_softness.max(0.0) / 2.0;
+        let lit_above = (self.sun.elevation + half_softness).min(FRAC_PI_2);
+        let shadowed_below = (self.sun.elevation - half_softness)
+            .max(MIN_SHADOW_ELEVATION.to_radians());
+
+        // Each step goes a whole pixel along whichever axis the sun
+        // is most nearly along, and some share of one along the other
+        let heading = self.sun.heading();
+        let along_x = heading.x.abs() >= heading.y.abs();
+        let step = heading * (1.0 / heading.x.abs().max(heading.y.abs()));
+        let step_length = ((step.x * pixel_size.x).powi(2) + (step.y * pixel_size.y).powi(2)).sqrt();
+        let (lit_drop, shadowed_drop) = (step_length * lit_above.tan(), step_length * shadowed_below.tan());
+
+        let (lines, length) = if along_x { (width, height) } else { (height, width) };
+        let (towards_sun, across) = if along_x { (step.x, step.y) } else { (step.y, step.x) };
+        let pixel = |line: u32, index: u32| if along_x { (line, index) } else { (index, line) };
+
+        // The top of the shadow for the edges of the penumbra along
+        // the last line swept, nothing casts one onto the first
+        let mut lit_tops = vec![f64::NEG_INFINITY; length as usize];
+        let mut shadowed_tops = vec![f64::NEG_INFINITY; length as usize];
+        let mut previous = vec![f64::NEG_INFINITY; length as usize];
+
+        let mut shadows = GrayImage::new(width, height);
+        for sweep in 0..lines {
+            let line = if towards_sun > 0.0 { lines - 1 - sweep } else { sweep };
+            let mut lit_line = Vec::with_capacity(length as usize);
+            let mut shadowed_line = Vec::with_capacity(length as usize);
+
+            for index in 0..length {
+                let (x, y) = pixel(line, index);
+                let base = heights.get(x, y);
+
+                // The shadow over the pixel a step towards the sun,
+                // raised by what stands there, less the drop
+                let from = index as f64 + across;
+                let cast = |tops: &[f64], drop: f64| {
+                    let top = |i: f64| {
+                        let i = i as i64;
+                        if i < 0 || i >= length as i64 {
+                            f64::NEG_INFINITY
+                        } else {
+                            tops[i as usize].max(previous[i as usize])
+                        }
+                    };
+
+                    let (below, share) = (from.floor(), from - from.floor());
+                    let top = if share < 1e-9 {
+                        top(below)
+                    } else if share > 1.0 - 1e-9 {
+                        top(below + 1.0)
+                    } else {
+                        top(below) * (1.0 - share) + top(below + 1.0) * share
+                    };
+                    top - drop
+                };
+
+                let lit_top = cast(&lit_tops, lit_drop);
+                let shadowed_top = cast(&shadowed_tops, shadowed_drop);
+
+                let shadow = if lit_top >= base {
+                    1.0
+                } else if shadowed_top <= base || shadowed_top <= lit_top {
+                    0.0
+                } else {
+                    let t = (shadowed_top - base) / (shadowed_top - lit_top);
+                    t * t * (3.0 - 2.0 * t)
+                };
+                shadows.put_pixel(x, y, Luma([(shadow * u8::MAX as f64).round() as u8]));
+
+                lit_line.push(lit_top);
+                shadowed_line.push(shadowed_top);
+            }
+
+            lit_tops = lit_line;
+            shadowed_tops = shadowed_line;
+            previous = (0..length).map(|index| {
+                let (x, y) = pixel(line, index);
+                heights.get(x, y)
+            }).collect();
+        }
+
+        shadows
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A single 10m tall pixel in the middle of a flat field
+    fn tower() -> HeightField {
+        let mut heights = HeightField::new(21, 21);
+        heights.set(10, 10, 10.0);
+        heights
+    }
+
+    #[test]
+    fn shadow_falls_away_from_sun() {
+        let lighting = Lighting {
+            // Low in the east
+            sun: Sun::from_degrees(90.0, 30.0),
+            shadow_darkness: 1.0,
+            shadow_softness: 0.0,
+        };
+
+        let shadows = lighting.cast_shadows(&tower(), Vector2 { x: 1.0, y: 1.0 });
+        assert_eq!(shadows.get_pixel(5, 10).0, [u8::MAX]);
+        assert_eq!(shadows.get_pixel(15, 10).0, [0]);
+        assert_eq!(shadows.get_pixel(5, 5).0, [0]);
+    }
+
+    #[test]
+    fn high_sun_casts_short_shadows() {
+        let lighting = Lighting {
+            sun: Sun::from_degrees(90.0, 80.0),
+            shadow_darkness: 1.0,
+            shadow_softness: 0.0,
+        };
+
+        let shadows = lighting.cast_shadows(&tower(), Vector2 { x: 1.0, y: 1.0 });
+        assert_eq!(shadows.get_pixel(9, 10).0, [u8::MAX]);
+        assert_eq!(shadows.get_pixel(5, 10).0, [0]);
+    }
+
+    #[test]
+    fn oblique_sun_shadows_along_the_diagonal() {
+        let lighting = Lighting {
+            // Halfway up the sky in the north east
+            sun: Sun::from_degrees(45.0, 45.0),
+            shadow_darkness: 1.0,
+            shadow_softness: 0.0,
+        };
+
+        // The shadow reaches 10m out along the diagonal, about seven
+        // pixels each way
+        let shadows = lighting.cast_shadows(&tower(), Vector2 { x: 1.0, y: 1.0 });
+        assert_eq!(shadows.get_pixel(7, 13).0, [u8::MAX]);
+        assert_eq!(shadows.get_pixel(5, 15).0, [u8::MAX]);
+        assert_eq!(shadows.get_pixel(1, 19).0, [0]);
+        assert_eq!(shadows.get_pixel(13, 7).0, [0]);
+        assert_eq!(shadows.get_pixel(10, 15).0, [0]);
+    }
+}
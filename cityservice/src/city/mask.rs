@@ -0,0 +1,151 @@
+use image::{Rgb, RgbImage};
+
+use super::geom::Vector2;
+
+/// The number of points averaged when weighing a whole line
+const LINE_SAMPLES: u32 = 32;
+
+/// A painted image stretched over the whole city, used to weigh how
+/// much gets built where. White is full density and black is empty.
+///
+/// A grayscale mask weighs everything the same way. A colour-coded
+/// mask uses its red channel for roads, its green channel for
+/// buildings and its blue channel for zoning.
+pub struct Mask {
+    pixels: RgbImage,
+    /// The size of the city in meters, the mask covers all of it
+    size: Vector2,
+    colour_coded: bool,
+}
+
+impl Mask {
+    pub fn from_bytes(
+        bytes: &[u8],
+        size: Vector2,
+        colour_coded: bool,
+    ) -> Result<Self, image::ImageError> {
+        let pixels = image::load_from_memory(bytes)?.to_rgb8();
+
+        Ok(Self {
+            pixels,
+            size,
+            colour_coded,
+        })
+    }
+
+    /// The colour of the mask at a point in the city, with each
+    /// channel in [0, 1]
+    fn sample(&self, point: Vector2) -> [f64; 3] {
+        let (width, height) = self.pixels.dimensions();
+        let x = (point.x / self.size.x * width as f64) as i64;
+        let y = (point.y / self.size.y * height as f64) as i64;
+        let x = x.clamp(0, width as i64 - 1) as u32;
+        let y = y.clamp(0, height as i64 - 1) as u32;
+
+        let Rgb(colour) = *self.pixels.get_pixel(x, y);
+        colour.map(|channel| channel as f64 / u8::MAX as f64)
+    }
+
+    fn weight(&self, point: Vector2, channel: usize) -> f64 {
+        let colour = self.sample(point);
+        if self.colour_coded {
+            colour[channel]
+        } else {
+            // Rec. 709 luma
+            0.2126 * colour[0] + 0.7152 * colour[1] + 0.0722 * colour[2]
+        }
+    }
+
+    pub fn road_weight(&self, point: Vector2) -> f64 {
+        self.weight(point, 0)
+    }
+
+    pub fn building_weight(&self, point: Vector2) -> f64 {
+        self.weight(point, 1)
+    }
+
+    pub fn zone_weight(&self, point: Vector2) -> f64 {
+        self.weight(point, 2)
+    }
+
+    /// The average road weight along a straight line across the city
+    pub fn road_weight_along(&self, from: Vector2, to: Vector2) -> f64 {
+        let total: f64 = (0..LINE_SAMPLES)
+            .map(|i| {
+                let progress = (i as f64 + 0.5) / LINE_SAMPLES as f64;
+                self.road_weight(from + (to - from) * progress)
+            })
+            .sum();
+
+        total / LINE_SAMPLES as f64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A 4x4 mask over a 100m square city, each pixel painted by
+    /// the given function
+    fn mask<F>(colour_coded: bool, paint: F) -> Mask where
+        F: Fn(u32, u32) -> [u8; 3],
+    {
+        let image = RgbImage::from_fn(4, 4, |x, y| Rgb(paint(x, y)));
+        let mut bytes = Vec::new();
+        image::DynamicImage::ImageRgb8(image)
+            .write_to(&mut std::io::Cursor::new(&mut bytes), image::ImageOutputFormat::Png)
+            .unwrap();
+
+        Mask::from_bytes(&bytes, Vector2 { x: 100.0, y: 100.0 }, colour_coded).unwrap()
+    }
+
+    #[test]
+    fn grayscale_weighs_everything_alike() {
+        // White in the west half, black in the east
+        let mask = mask(false, |x, _| if x < 2 { [0xFF; 3] } else { [0x00; 3] });
+        let west = Vector2 { x: 10.0, y: 60.0 };
+        let east = Vector2 { x: 90.0, y: 60.0 };
+
+        for weight in [Mask::road_weight, Mask::building_weight, Mask::zone_weight] {
+            assert!((weight(&mask, west) - 1.0).abs() < 1e-9);
+            assert!(weight(&mask, east).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn colour_coded_channels_are_separate() {
+        let mask = mask(true, |_, _| [0xFF, 0x00, 0x80]);
+        let point = Vector2 { x: 50.0, y: 50.0 };
+
+        assert!((mask.road_weight(point) - 1.0).abs() < 1e-9);
+        assert!(mask.building_weight(point).abs() < 1e-9);
+        assert!((mask.zone_weight(point) - 128.0 / 255.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn points_off_the_edge_take_the_edge_pixel() {
+        // Only the top left and bottom right pixels are white
+        let mask = mask(false, |x, y| if (x, y) == (0, 0) || (x, y) == (3, 3) { [0xFF; 3] } else { [0x00; 3] });
+
+        assert!((mask.road_weight(Vector2 { x: -50.0, y: -50.0 }) - 1.0).abs() < 1e-9);
+        assert!((mask.road_weight(Vector2 { x: 100.0, y: 100.0 }) - 1.0).abs() < 1e-9);
+        assert!((mask.road_weight(Vector2 { x: 500.0, y: 120.0 }) - 1.0).abs() < 1e-9);
+        assert!(mask.road_weight(Vector2 { x: 500.0, y: -20.0 }).abs() < 1e-9);
+    }
+
+    #[test]
+    fn dark_areas_thin_out_roads_and_buildings() {
+        // Full weight in the west half, a quarter in the east
+        let mask = mask(true, |x, _| if x < 2 { [0xFF; 3] } else { [0x40; 3] });
+
+        let offsets = super::super::Builder::weighted_offsets(vec![0.1; 12], |x| {
+            mask.road_weight_along(Vector2 { x, y: 0.0 }, Vector2 { x, y: 100.0 })
+        });
+        let (west, east) = (offsets[0], offsets[offsets.len() - 1]);
+        assert!((west - 10.0).abs() < 1e-9);
+        assert!(east > 3.5 * west);
+
+        assert!(mask.building_weight(Vector2 { x: 80.0, y: 50.0 }) < 0.3);
+        assert!(mask.building_weight(Vector2 { x: 20.0, y: 50.0 }) > 0.99);
+    }
+}
@@ -1,19 +1,125 @@
-use std::io::BufWriter;
+use std::f64::consts::{PI, TAU};
+
+use image::{GrayImage, ImageEncoder};
+use serde_json::json;
 
 mod settings;
 use settings::config::Config;
 pub use settings::Settings;
 pub use settings::Error as SettingsError;
 
+mod animation;
+pub use animation::Animation;
+
+mod channels;
+use channels::{Canvas, Label, Material};
+pub use channels::Channel;
+
 mod geom;
 use geom::*;
 
+mod growth;
+use growth::Growth;
+
+mod palette;
+use palette::{Palette, Surface};
+
+mod parcel;
+use parcel::{LotStrategy, Parcel};
+
+mod footprint;
+use footprint::{Footprint, FootprintShape};
+
+mod landmark;
+use landmark::{Landmark, LandmarkKind};
+
+mod filler;
+use filler::{Filler, FillerKind};
+
 mod objects;
-use objects::{Block, Building, Road, Renderable};
+use objects::{Block, Bridge, Building, Courtyard, CourtyardKind, Pier, Road, Renderable, Waterway};
+
+mod lighting;
+use lighting::{Lighting, Sun};
+
+mod mask;
+use mask::Mask;
+
+mod night;
+use night::{Light, TimeOfDay};
+
+mod noise;
+use noise::Noise;
+
+mod roof;
+use roof::{Roof, RoofShape};
+
+mod rail;
+use rail::{Railway, Station};
+
+mod rooftop;
+use rooftop::RooftopKind;
 
 mod stats;
 use stats::{Distribution};
 
+mod suburb;
+use suburb::SuburbLayout;
+
+mod texture;
+use texture::{Finish, Texture};
+
+mod terrain;
+use terrain::Terrain;
+
+mod traffic;
+use traffic::{TrafficSide, Vehicle, VehicleKind};
+
+mod weather;
+use weather::{Conditions, Season, Weather};
+pub use weather::VARIANTS;
+
+mod zone;
+use zone::Zone;
+
+/// The length of each slice of a river channel or stretch of coast in
+/// meters, shorter slices make for smoother banks but more work when
+/// clipping.
+const WATER_SLICE_LENGTH: f64 = 2.0;
+
+/// The number of octaves of noise displacing a shoreline
+const SHORE_OCTAVES: u32 = 4;
+
+/// The length of each slice of a road bent to follow the terrain
+const ROAD_SLICE_LENGTH: f64 = 2.0;
+
+/// Roads don't bend on ground flatter than this (rise over run) as
+/// there's nothing to gain by it
+const MIN_BEND_GRADE: f64 = 0.01;
+
+/// The lowest weight a density mask can give, so that even black
+/// areas of the mask don't leave infinite gaps between roads
+const MIN_MASK_WEIGHT: f64 = 0.05;
+
+/// The number of points along each side of a block at which the
+/// slope is sampled to decide whether the block is too steep
+const BLOCK_SLOPE_SAMPLES: u32 = 5;
+
+/// The number of sine waves summed together to make a river meander
+const MEANDER_HARMONICS: u32 = 3;
+
+/// The narrowest yard left between one ring of buildings in a block
+/// and the next, in meters
+const MIN_YARD_BREADTH: f64 = 4.0;
+
+/// The smallest span, in meters, inside a ring of buildings that's
+/// worth building another ring in
+const MIN_RING_SPAN: f64 = 20.0;
+
+/// No building has more lights than this at night, however large or
+/// tall it is
+const MAX_BUILDING_LIGHTS: usize = 12;
+
 #[derive(Debug)]
 pub enum GenerateError {
     StatsRequest(stats::RequestError),
@@ -22,6 +128,18 @@ pub enum GenerateError {
         min: f64,
         max: f64,
     },
+    UnknownOption {
+        setting: String,
+        value: String,
+    },
+    BadInputImage {
+        input: String,
+        error: image::ImageError,
+    },
+    MissingInputImage {
+        input: String,
+        needed_by: String,
+    },
 }
 
 impl std::fmt::Display for GenerateError {
@@ -39,6 +157,23 @@ impl std::fmt::Display for GenerateError {
                 min,
                 max,
             ),
+            Self::UnknownOption{setting, value} => format!(
+                "\"{}\" is not a valid option for {}, check the \
+                 cityscript docs for the supported values.",
+                value,
+                setting,
+            ),
+            Self::BadInputImage{input, error} => format!(
+                "The {} could not be read as an image ({}), \
+                 please supply a PNG.",
+                input,
+                error,
+            ),
+            Self::MissingInputImage{input, needed_by} => format!(
+                "The {} needs a {} to be uploaded along with the script.",
+                needed_by,
+                input,
+            ),
             // Don't leak information about internal infrastructure to users
             _ => format!("Unknown"),
         };
@@ -57,8 +192,34 @@ pub struct City {
     /// The size of the city in meters
     size: Vector2,
     image_size: (u32, u32),
+    /// The height in meters of full white in the heightmap
+    heightmap_scale: f64,
+    /// How strongly slopes tilt the normal map
+    normal_strength: f64,
+    lighting: Lighting,
     roads: Vec<Road>,
+    vehicles: Vec<Vehicle>,
     blocks: Vec<Block>,
+    waterways: Vec<Waterway>,
+    bridges: Vec<Bridge>,
+    piers: Vec<Pier>,
+    railways: Vec<Railway>,
+    /// Street lamps, windows and the lights of vehicles, these only
+    /// show up at night
+    lights: Vec<Light>,
+    time: TimeOfDay,
+    /// The share of daylight left at night
+    night_brightness: f64,
+    conditions: Conditions,
+    /// The colours everything is drawn in
+    palette: Palette,
+    texture: Texture,
+    terrain: Option<Terrain>,
+    mask: Option<Mask>,
+    /// How the city spread out to get where it is, for time-lapses
+    growth: Option<Growth>,
+    /// How long each frame of a time-lapse is shown for, in seconds
+    frame_duration: f64,
 }
 
 impl City {
@@ -68,42 +229,414 @@ impl City {
         Self {
             size: Vector2::default(),
             image_size: (2048, 2048),
+            heightmap_scale: 1.0,
+            normal_strength: 1.0,
+            lighting: Lighting::default(),
             roads: Vec::new(),
+            vehicles: Vec::new(),
             blocks: Vec::new(),
+            waterways: Vec::new(),
+            bridges: Vec::new(),
+            piers: Vec::new(),
+            railways: Vec::new(),
+            lights: Vec::new(),
+            time: TimeOfDay::Day,
+            night_brightness: 1.0,
+            conditions: Conditions {
+                season: Season::Summer,
+                weather: Weather::Clear,
+                snow_cover: 0.0,
+                puddles: 0.0,
+                noise: Noise::new(0),
+            },
+            palette: Palette::default(),
+            texture: Texture::default(),
+            terrain: None,
+            mask: None,
+            growth: None,
+            frame_duration: 0.4,
         }
     }
 
-    /// Renders the city as a jpeg albedo map and returns a buffered
-    /// writer over the encoded image
-    pub fn into_jpeg(self) -> BufWriter<Vec<u8>> {
+    /// The number of pixels per meter in each direction
+    fn pixel_scale(&self) -> Vector2 {
         let (width, height) = self.image_size;
-        let mut image = image::DynamicImage::new_rgb8(width, height);
-        let scale = Vector2 {
+        Vector2 {
             x: (width as f64 / self.size.x),
             y: (height as f64 / self.size.y)
-        };
+        }
+    }
+
+    /// The size of a pixel in meters
+    fn pixel_size(&self) -> Vector2 {
+        let scale = self.pixel_scale();
+        Vector2 {
+            x: 1.0 / scale.x,
+            y: 1.0 / scale.y,
+        }
+    }
+
+    /// The objects in the city in the order they're drawn
+    fn renderables(&self) -> impl Iterator<Item = &dyn Renderable> {
+        let roads = self.roads.iter().map(|road| road as &dyn Renderable);
+        let vehicles = self.vehicles.iter().map(|vehicle| vehicle as &dyn Renderable);
+        let blocks = self.blocks.iter().map(|block| block as &dyn Renderable);
+        let waterways = self.waterways.iter().map(|waterway| waterway as &dyn Renderable);
+        let railways = self.railways.iter().map(|railway| railway as &dyn Renderable);
+        let bridges = self.bridges.iter().map(|bridge| bridge as &dyn Renderable);
+        let piers = self.piers.iter().map(|pier| pier as &dyn Renderable);
+        let lights = self.lights.iter().map(|light| light as &dyn Renderable);
+
+        roads.chain(vehicles).chain(blocks).chain(waterways).chain(railways).chain(bridges).chain(piers).chain(lights)
+    }
+
+    /// Renders every object into every channel in a single pass.
+    /// The ground is flat at zero, unless the city was built on
+    /// terrain.
+    fn render(&self) -> Canvas {
+        self.render_objects(self.renderables(), &self.conditions)
+    }
+
+    /// Renders some of the objects in the city, drawn in the order
+    /// they come in, over its ground, in the season and weather of
+    /// the conditions
+    fn render_objects<'a>(
+        &self,
+        renderables: impl Iterator<Item = &'a dyn Renderable>,
+        conditions: &Conditions,
+    ) -> Canvas {
+        let mut canvas = self.paint_objects(renderables);
+        let shadows = self.cast_shadows(&canvas);
+        self.light(&mut canvas, conditions, shadows.as_ref());
+        canvas
+    }
+
+    /// Draws some of the objects in the city over its ground, in the
+    /// order they come in, and textures them. Nothing is lit yet.
+    fn paint_objects<'a>(&self, renderables: impl Iterator<Item = &'a dyn Renderable>) -> Canvas {
+        let (width, height) = self.image_size;
+        // Anything left uncovered (like where a road bent away from
+        // its grid line) is bare concrete
+        let [red, green, blue] = self.palette.color(Surface::Pavement);
+        let mut canvas = Canvas::new(
+            width,
+            height,
+            image::Rgb([red, green, blue]),
+            Material::Concrete,
+            self.lighting.sun,
+        );
+        canvas.palette = self.palette.clone();
+        canvas.texture = self.texture;
+        let scale = self.pixel_scale();
+
+        if let Some(terrain) = &self.terrain {
+            for y in 0..height {
+                for x in 0..width {
+                    let pixel_centre = Vector2 { x: x as f64 + 0.5, y: y as f64 + 0.5 };
+                    let point = Vector2 {
+                        x: pixel_centre.x / scale.x,
+                        y: pixel_centre.y / scale.y,
+                    };
+
+                    canvas.heights.set(x, y, terrain.height(point));
+                }
+            }
+        }
 
         let offset = Vector2 {
             x: 0.0,
             y: 0.0,
         };
-        
-        for road in self.roads {
-            road.render(offset, scale, &mut image);
+
+        for renderable in renderables {
+            renderable.render(offset, scale, &mut canvas);
         }
 
-        for block in self.blocks {
-            block.render(offset, scale, &mut image);
+        self.texture.apply(&mut canvas, self.pixel_size());
+        canvas
+    }
+
+    /// The shadows cast over everything painted on a canvas, there's
+    /// no sun to cast them by at night
+    fn cast_shadows(&self, canvas: &Canvas) -> Option<GrayImage> {
+        match self.time {
+            TimeOfDay::Day => Some(self.lighting.cast_shadows(&canvas.heights, self.pixel_size())),
+            TimeOfDay::Night => None,
         }
-        
-        let jpeg = Vec::with_capacity((width * height) as usize);
-        let mut buf_writer = BufWriter::new(jpeg);
-        let mut encoder = image::codecs::jpeg::JpegEncoder::new(&mut buf_writer);
-        encoder.encode_image(&image).expect("Failed to encode image to memory");
-        buf_writer
+    }
+
+    /// Puts a painted canvas in the season and weather of the
+    /// conditions, then shades it by day or darkens it by night. The
+    /// shadow mask is left clear at night.
+    fn light(&self, canvas: &mut Canvas, conditions: &Conditions, shadows: Option<&GrayImage>) {
+        conditions.apply(canvas, self.pixel_size());
+
+        match shadows {
+            Some(shadows) => {
+                canvas.shadows = shadows.clone();
+                canvas.shade(self.lighting.shadow_darkness);
+            },
+            None => canvas.darken(self.night_brightness, night::NIGHT_TINT),
+        }
+    }
+
+    /// Encodes one channel of a rendered canvas. The albedo is a
+    /// jpeg, the heightmap a 16 bit grayscale png suitable for use as
+    /// a displacement map, and the rest are 8 bit pngs.
+    fn encode(&self, canvas: &Canvas, channel: Channel) -> Vec<u8> {
+        let (width, height) = self.image_size;
+        let mut encoded = Vec::with_capacity((width * height) as usize);
+
+        let (samples, color_type) = match channel {
+            Channel::Albedo => {
+                image::codecs::jpeg::JpegEncoder::new(&mut encoded)
+                    .encode_image(&canvas.albedo)
+                    .expect("Failed to encode image to memory");
+                return encoded;
+            },
+            Channel::Height => {
+                let samples = canvas.heights
+                    .to_luma16(self.heightmap_scale)
+                    .into_iter()
+                    .flat_map(|sample| sample.to_ne_bytes())
+                    .collect();
+                (samples, image::ColorType::L16)
+            },
+            Channel::Normal => {
+                let normals = canvas.heights.to_normals(self.pixel_size(), self.normal_strength);
+                (normals.into_raw(), image::ColorType::Rgb8)
+            },
+            Channel::Roughness => (canvas.roughness().into_raw(), image::ColorType::L8),
+            Channel::Metallic => (canvas.metallic().into_raw(), image::ColorType::L8),
+            Channel::Shadow => (canvas.shadows.clone().into_raw(), image::ColorType::L8),
+            Channel::Mask => (canvas.mask().into_raw(), image::ColorType::Rgb8),
+        };
+
+        image::codecs::png::PngEncoder::new(&mut encoded)
+            .write_image(&samples, width, height, color_type)
+            .expect("Failed to encode image to memory");
+        encoded
+    }
+
+    /// Describes the city and how to read its channels back, to be
+    /// sent alongside them
+    pub fn metadata(&self, channels: &[Channel]) -> serde_json::Value {
+        let (width, height) = self.image_size;
+        let buildings: usize = self.blocks.iter().map(|block| block.buildings.len()).sum();
+        let parcels: usize = self.blocks.iter().map(|block| block.parcels.len()).sum();
+        let landmarks = self.blocks.iter().filter(|block| block.landmark.is_some()).count();
+        let fillers: usize = self.blocks.iter().map(|block| block.fillers.len()).sum();
+        let materials: serde_json::Map<String, serde_json::Value> = Material::ALL
+            .into_iter()
+            .map(|material| (
+                String::from(material.name()),
+                json!({
+                    "roughness": material.roughness(),
+                    "metallic": material.metallic(),
+                }),
+            ))
+            .collect();
+
+        let labels: serde_json::Map<String, serde_json::Value> = Label::ALL
+            .into_iter()
+            .map(|label| {
+                let [red, green, blue] = label.color();
+                (String::from(label.name()), json!(format!("#{:02x}{:02x}{:02x}", red, green, blue)))
+            })
+            .collect();
+
+        json!({
+            "size": { "width": self.size.x, "height": self.size.y },
+            "image": { "width": width, "height": height },
+            "channels": channels
+                .iter()
+                .map(|channel| json!({
+                    "name": channel.name(),
+                    "file": format!("{}.{}", channel.name(), channel.extension()),
+                    "contentType": channel.content_type(),
+                }))
+                .collect::<Vec<_>>(),
+            "heightmap": { "scale": self.heightmap_scale },
+            "normal": { "convention": "opengl", "strength": self.normal_strength },
+            "time": self.time.name(),
+            "season": self.conditions.season.name(),
+            "weather": self.conditions.weather.name(),
+            "theme": self.palette.theme.name(),
+            "sun": {
+                "azimuth": self.lighting.sun.azimuth.to_degrees(),
+                "elevation": self.lighting.sun.elevation.to_degrees(),
+            },
+            "materials": materials,
+            "labels": labels,
+            "counts": {
+                "roads": self.roads.len(),
+                "blocks": self.blocks.len(),
+                "buildings": buildings,
+                "parcels": parcels,
+                "landmarks": landmarks,
+                "fillers": fillers,
+                "vehicles": self.vehicles.len(),
+                "waterways": self.waterways.len(),
+                "bridges": self.bridges.len(),
+                "piers": self.piers.len(),
+                "railways": self.railways.len(),
+                "stations": self.railways.iter().map(|railway| railway.stations.len()).sum::<usize>(),
+                "lights": self.lights.len(),
+            },
+        })
+    }
+
+    /// Renders the city as it stood at the end of an epoch of its
+    /// growth. Roads are reached a piece at a time, and blocks along
+    /// with the roads in front of them, with their buildings going up
+    /// over the epochs after. Waterways and railways were there from
+    /// the start.
+    fn render_epoch(&self, growth: &Growth, epoch: u32) -> Canvas {
+        let time = growth.time(epoch);
+        let reached = |area: Rectangle| growth.arrival(area.centre()) <= time;
+
+        let roads: Vec<Road> = self.roads
+            .iter()
+            .flat_map(|road| growth.road_pieces(road.asphalt, time))
+            .map(|asphalt| Road { asphalt })
+            .collect();
+
+        let blocks: Vec<Block> = self.blocks
+            .iter()
+            .filter(|block| reached(block.footprint))
+            .map(|block| {
+                let start = block.buildings_boundary().start();
+                let buildings = block.buildings
+                    .iter()
+                    .filter_map(|building| {
+                        // Spread the buildings on a block out over the
+                        // densification, in no particular order
+                        let centre = building.footprint.centre() + start;
+                        let share = (growth.noise.value(centre * 0.37) + 1.0) / 2.0;
+                        let founded = growth.founding(block.footprint, share);
+                        let height = growth.height(founded, building.height, time)?;
+
+                        // Equipment only goes up once the roof is done
+                        let mut building = building.clone();
+                        if height < building.height {
+                            building.equipment.clear();
+                        }
+                        building.height = height;
+                        Some(building)
+                    })
+                    .collect();
+
+                Block { buildings, ..block.clone() }
+            })
+            .collect();
+
+        let roads = roads.iter().map(|road| road as &dyn Renderable);
+        let vehicles = self.vehicles
+            .iter()
+            .filter(|vehicle| reached(vehicle.footprint))
+            .map(|vehicle| vehicle as &dyn Renderable);
+        let blocks = blocks.iter().map(|block| block as &dyn Renderable);
+        let waterways = self.waterways.iter().map(|waterway| waterway as &dyn Renderable);
+        let railways = self.railways.iter().map(|railway| railway as &dyn Renderable);
+        let bridges = self.bridges
+            .iter()
+            .filter(|bridge| reached(bridge.deck))
+            .map(|bridge| bridge as &dyn Renderable);
+        let piers = self.piers
+            .iter()
+            .filter(|pier| reached(pier.deck))
+            .map(|pier| pier as &dyn Renderable);
+        let lights = self.lights
+            .iter()
+            .filter(|light| growth.arrival(light.centre) <= time)
+            .map(|light| light as &dyn Renderable);
+
+        self.render_objects(
+            roads.chain(vehicles).chain(blocks).chain(waterways).chain(railways).chain(bridges).chain(piers).chain(lights),
+            &self.conditions,
+        )
+    }
+
+    /// Renders the growth of the city as a time-lapse, one frame of
+    /// albedo to each epoch, ending with the city as it stands
+    pub fn render_growth(&self, animation: Animation) -> Vec<u8> {
+        // Each frame is encoded as soon as it's rendered
+        match &self.growth {
+            Some(growth) => animation.encode(
+                (0..growth.epochs).map(|epoch| self.render_epoch(growth, epoch).albedo.into_rgb8()),
+                self.frame_duration,
+            ),
+            None => animation.encode(std::iter::once(self.render().albedo.into_rgb8()), self.frame_duration),
+        }
+    }
+
+    /// Renders the city in other seasons and weathers, and encodes the
+    /// albedo and roughness (which shows up the puddles) of each. The
+    /// snow and puddles are laid out the same as in the city's own
+    /// conditions, so variants line up with each other. None of them
+    /// change the shape of anything, so the city is drawn and its
+    /// shadows cast once for them all.
+    pub fn render_variants(&self, variants: &[(String, String)]) -> Result<Vec<[Vec<u8>; 2]>, GenerateError> {
+        let unknown = |setting: &str, value: &str| GenerateError::UnknownOption {
+            setting: String::from(setting),
+            value: String::from(value),
+        };
+
+        let conditions = variants
+            .iter()
+            .map(|(season, weather)| Ok(Conditions {
+                season: Season::from_name(season).ok_or_else(|| unknown("render.season", season))?,
+                weather: Weather::from_name(weather).ok_or_else(|| unknown("render.weather", weather))?,
+                ..self.conditions
+            }))
+            .collect::<Result<Vec<Conditions>, GenerateError>>()?;
+
+        if conditions.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let painted = self.paint_objects(self.renderables());
+        let shadows = self.cast_shadows(&painted);
+
+        Ok(conditions
+            .iter()
+            .map(|conditions| {
+                let mut canvas = painted.clone();
+                self.light(&mut canvas, conditions, shadows.as_ref());
+                [
+                    self.encode(&canvas, Channel::Albedo),
+                    self.encode(&canvas, Channel::Roughness),
+                ]
+            })
+            .collect())
+    }
+
+    /// Renders the city once and encodes each of the requested
+    /// channels from it, in the order they were asked for
+    pub fn render_channels(&self, channels: &[Channel]) -> Vec<Vec<u8>> {
+        let canvas = self.render();
+
+        channels
+            .iter()
+            .map(|&channel| self.encode(&canvas, channel))
+            .collect()
     }
 }
 
+/// How a block is to be built on, worked out from its zone and where
+/// it is before any of its buildings are drawn
+struct BlockPlan<'a> {
+    building_config: &'a settings::config::BuildingConfig,
+    palette: &'a Palette,
+    lot_strategy: LotStrategy,
+    courtyard_kind: CourtyardKind,
+    /// The density mask's weight for the block
+    weight: f64,
+    /// How close the block is to the centre, 1 at the centre and 0
+    /// in the corners
+    centrality: f64,
+}
+
 /// This structure represents a grid-like set of rectangles. Some of
 /// the rectangles are the "lines" of the grid, and some are the
 /// "squares". This is redundant data, but it makes things easier to
@@ -240,6 +773,22 @@ impl Builder {
         city.size.y = config.city.height;
         city.image_size.0 = config.image.width;
         city.image_size.1 = config.image.height;
+        city.heightmap_scale = config.image.heightmap_scale;
+        city.normal_strength = config.image.normal_strength;
+        city.lighting = Lighting {
+            sun: Sun::from_degrees(
+                config.lighting.sun_azimuth,
+                config.lighting.sun_elevation,
+            ),
+            shadow_darkness: config.lighting.shadow_darkness,
+            shadow_softness: config.lighting.shadow_softness.to_radians(),
+        };
+        city.frame_duration = config.growth.frame_duration;
+        city.palette = config.colors.palette.clone();
+        city.texture = Texture {
+            detail: config.render.texture_detail,
+            noise: Noise::new(config.city.seed),
+        };
         
         Self {
             config,
@@ -248,6 +797,56 @@ impl Builder {
         }
     }
 
+    /// Shapes the city with a heightmap, this has to happen before
+    /// any of the other generation steps. The heightmap can be in any
+    /// image format we can decode, and is stretched over the city.
+    pub fn with_terrain(mut self, heightmap: &[u8]) -> Result<Self, GenerateError> {
+        let terrain = Terrain::from_bytes(
+            heightmap,
+            self.city.size,
+            self.config.terrain.vertical_scale,
+        ).map_err(|error| GenerateError::BadInputImage {
+            input: String::from("heightmap"),
+            error,
+        })?;
+
+        self.city.terrain = Some(terrain);
+        Ok(self)
+    }
+
+    /// Weighs the density of roads and buildings with a painted mask,
+    /// like the terrain this has to happen before generation starts.
+    pub fn with_mask(mut self, mask: &[u8]) -> Result<Self, GenerateError> {
+        let mask = Mask::from_bytes(
+            mask,
+            self.city.size,
+            self.config.mask.colour_coded,
+        ).map_err(|error| GenerateError::BadInputImage {
+            input: String::from("density mask"),
+            error,
+        })?;
+
+        self.city.mask = Some(mask);
+        Ok(self)
+    }
+
+    /// Turns sampled densities into offsets between successive
+    /// features, thinning them out wherever the weight is low. The
+    /// weight is looked up at the position of each feature.
+    fn weighted_offsets<F>(densities: Vec<f64>, weight: F) -> Vec<f64> where
+        F: Fn(f64) -> f64,
+    {
+        let mut position = 0.0;
+        densities
+            .into_iter()
+            .map(|density| {
+                let offset = (density * weight(position).max(MIN_MASK_WEIGHT)).recip();
+                position += offset;
+                offset
+            })
+            .collect()
+    }
+
     /// This function returns the offset
     async fn get_sample(
         client: &reqwest::Client,
@@ -274,57 +873,147 @@ impl Builder {
             .map_err(|err| GenerateError::StatsRequest(err))
     }
 
+    /// Fills a block with rings of buildings around its edges, and
+    /// returns them along with the courtyard left inside the first
+    /// ring. While there's room, further rings are built inside the
+    /// first, each set back across a yard from the last, and the
+    /// middle can get a building of its own.
     pub fn build_buildings_around<I1, I2, I3>(
         size: Vector2,
+        rings: u32,
+        inner_building: bool,
         mut x_offsets: I1,
         mut y_offsets: I1,
         mut spacings: I2,
         mut stepbacks: I3,
-    ) -> Result<Vec<Rectangle>, GenerateError> where
+    ) -> Result<(Vec<Rectangle>, Option<Rectangle>), GenerateError> where
         I1: Iterator<Item = f64>,
         I2: Iterator<Item = f64>,
         I3: Iterator<Item = f64>,
     {
-        // Clockwise corners
+        let mut buildings = Vec::new();
+        let mut courtyard = None;
+        let mut interior = Rectangle::new(Vector2::default(), size);
+
+        for ring in 0..rings.max(1) {
+            let area = if ring == 0 {
+                interior
+            } else {
+                let yard = spacings.next().unwrap_or(0.0).max(MIN_YARD_BREADTH);
+                let area = interior.inset(yard);
+                if area.width() < MIN_RING_SPAN || area.height() < MIN_RING_SPAN {
+                    break;
+                }
+
+                area
+            };
+
+            let (mut ring_buildings, inside) = Self::build_ring(
+                area,
+                x_offsets.by_ref(),
+                y_offsets.by_ref(),
+                spacings.by_ref(),
+                stepbacks.by_ref(),
+            );
+
+            buildings.append(&mut ring_buildings);
+            interior = inside;
+            if ring == 0 && interior.width() > 0.0 && interior.height() > 0.0 {
+                courtyard = Some(interior);
+            }
+        }
+
+        if inner_building {
+            let yard = spacings.next().unwrap_or(0.0).max(MIN_YARD_BREADTH);
+            let middle = interior.inset(yard);
+            if middle.width() >= MIN_RING_SPAN / 2.0 && middle.height() >= MIN_RING_SPAN / 2.0 {
+                buildings.push(middle);
+            }
+        }
+
+        Ok((buildings, courtyard))
+    }
+
+    /// Builds a single ring of buildings around the inside of an
+    /// area. Each corner gets a lot of its own that meets both
+    /// streets, and the faces are lined with buildings between the
+    /// corner lots, so nothing overlaps at the corners. Returns the
+    /// buildings and the open area inside the ring.
+    fn build_ring<I1, I2, I3>(
+        area: Rectangle,
+        mut x_offsets: I1,
+        mut y_offsets: I1,
+        mut spacings: I2,
+        mut stepbacks: I3,
+    ) -> (Vec<Rectangle>, Rectangle) where
+        I1: Iterator<Item = f64>,
+        I2: Iterator<Item = f64>,
+        I3: Iterator<Item = f64>,
+    {
+        let size = area.dimensions();
+
+        // The deepest a building gets on each face, clockwise from
+        // the top. Opposite faces can at most meet in the middle.
+        let mut depths = [0.0; 4];
+        for (i, depth) in depths.iter_mut().enumerate() {
+            let across = if i % 2 == 0 { size.y } else { size.x };
+            *depth = stepbacks.next().unwrap_or(0.0).min(across / 2.0);
+        }
+        let [top, right, bottom, left] = depths;
+
+        let point = |x: f64, y: f64| Vector2 { x, y };
+
+        // Clockwise corner lots
         let corners = [
-            Vector2{ x: 0.0, y: 0.0 },
-            Vector2{ x: size.x, y: 0.0 },
-            Vector2{ x: size.x, y: size.y },
-            Vector2{ x: 0.0, y: size.y },
+            Rectangle::new(point(0.0, 0.0), point(left, top)),
+            Rectangle::new(point(size.x - right, 0.0), point(size.x, top)),
+            Rectangle::new(point(size.x - right, size.y - bottom), size),
+            Rectangle::new(point(0.0, size.y - bottom), point(left, size.y)),
         ];
 
-        // Clockwise edges
-        let edge_clockwise = [
-            Vector2 {x: 1.0, y: 0.0},
-            Vector2 {x: 0.0, y: 1.0},
-            Vector2 {x: -1.0, y: 0.0},
-            Vector2 {x: 0.0, y: -1.0},
+        // Clockwise faces between the corner lots, as their start,
+        // direction along the face, direction into the block, length
+        // and depth
+        let across_x = size.x - left - right;
+        let across_y = size.y - top - bottom;
+        let faces = [
+            (point(left, 0.0), point(1.0, 0.0), point(0.0, 1.0), across_x, top),
+            (point(size.x, top), point(0.0, 1.0), point(-1.0, 0.0), across_y, right),
+            (point(size.x - right, size.y), point(-1.0, 0.0), point(0.0, -1.0), across_x, bottom),
+            (point(0.0, size.y - bottom), point(0.0, -1.0), point(1.0, 0.0), across_y, left),
         ];
-        
-        let mut buildings = Vec::new();
-        // Move around the edges and build buildings 
-        for i in 0..4 {
-            let (offsets, length, depth_limit) = if i % 2 == 0 {
-                (x_offsets.by_ref(), size.x, size.y)
-            } else {
-                (y_offsets.by_ref(), size.y, size.x)
-            };
 
+        let mut buildings: Vec<Rectangle> = corners
+            .into_iter()
+            .filter(|corner| corner.width() > 0.0 && corner.height() > 0.0)
+            .collect();
+
+        for (i, (start, clockwise, inwards, length, depth)) in faces.into_iter().enumerate() {
+            if length <= 0.0 {
+                continue;
+            }
+
+            let offsets = if i % 2 == 0 { x_offsets.by_ref() } else { y_offsets.by_ref() };
             let mut face_buildings = Self::line_blockface(
                 length,
-                corners[i],
-                edge_clockwise[i],
-                edge_clockwise[(i + 1) % 4],
+                start,
+                clockwise,
+                inwards,
                 offsets,
                 spacings.by_ref(),
                 stepbacks.by_ref(),
-                depth_limit,
+                depth,
             );
 
             buildings.append(&mut face_buildings);
         }
-        
-        Ok(buildings)
+
+        let inside = Rectangle::new(point(left, top), point(size.x - right, size.y - bottom));
+
+        (
+            buildings.into_iter().map(|building| building.translate(area.start())).collect(),
+            inside.translate(area.start()),
+        )
     }
 
     fn  line_blockface<I1, I2, I3>(
@@ -364,6 +1053,10 @@ impl Builder {
         ends.push(length);
 
         for ((bound_start, bound_end), depth) in starts.into_iter().zip(ends).zip(depths) {
+            // The alley at the very start of the face leaves no room
+            if bound_end <= bound_start {
+                continue;
+            }
 
             let building = Rectangle::new(
                 start + clockwise * bound_start,
@@ -376,19 +1069,53 @@ impl Builder {
         buildings
     }
 
-    pub async fn generate_block_buildings(
+    async fn generate_block_buildings(
         client: &reqwest::Client,
-        building_config: &settings::config::BuildingConfig,
-        block: &mut Block
+        plan: &BlockPlan<'_>,
+        block: &mut Block,
     ) -> Result<(), GenerateError> {
+        let BlockPlan { building_config, palette, lot_strategy, courtyard_kind, weight, centrality } = *plan;
         let size = block.buildings_boundary().dimensions();
+        let choice_distribution = Distribution::uniform(0.0, 1.0);
+
+        // Blocks lined along their faces are either left unbuilt as a
+        // whole or not at all, subdivided blocks decide lot by lot
+        if lot_strategy == LotStrategy::Faces {
+            let choice = Self::get_sample(client, 1, &choice_distribution).await?[0];
+            if let Some(kind) = Self::pick_filler(building_config, choice) {
+                let footprint = Rectangle::new(Vector2::default(), size);
+                let filler = Self::generate_filler(client, building_config, kind, footprint).await?;
+                block.fillers.push(filler);
+                return Ok(());
+            }
+        }
 
         let max_buildings = Vector2i {
             x: 2 * (size.x * building_config.density.x.max + 1.0) as u32,
             y: 2 * (size.y * building_config.density.y.max + 1.0) as u32,
         };
 
-        let max_num_buildings = max_buildings.x + max_buildings.y - 4;
+        // Each ring needs its own run of offsets along the faces, and
+        // depths and yards for its corners
+        let rings = building_config.rings.max(1.0) as u32;
+        let max_buildings = Vector2i {
+            x: max_buildings.x * rings,
+            y: max_buildings.y * rings,
+        };
+
+        let max_num_buildings = max_buildings.x + max_buildings.y + 8 * rings + 2;
+
+        // Subdivided blocks have a building on each lot. Every lot
+        // that's split is larger than the smallest lot area, so there
+        // can't be many more lots than that area fits in the block.
+        let max_num_buildings = match lot_strategy {
+            LotStrategy::Faces => max_num_buildings,
+            LotStrategy::Subdivide => {
+                let min_area = building_config.lots.area.min.max(1.0);
+                4 * (size.x * size.y / min_area).ceil() as u32 + 1
+            },
+            LotStrategy::Detached => 1,
+        };
 
         let x_densities = Self::get_sample(
             client,
@@ -414,6 +1141,12 @@ impl Builder {
             &building_config.roof_border,
         );
 
+        let floors = Self::get_sample(
+            client,
+            max_num_buildings,
+            &building_config.floors,
+        );
+
         let spacings = Self::get_sample(
             client,
             max_num_buildings,
@@ -425,48 +1158,209 @@ impl Builder {
             max_num_buildings,
             &building_config.stepbacks,
         );
-        
-        let x_offsets: Vec<f64> = x_densities
-            .await?
+
+        let roof_choices = Self::get_sample(
+            client,
+            max_num_buildings,
+            &choice_distribution,
+        );
+
+        let roof_pitches = Self::get_sample(
+            client,
+            max_num_buildings,
+            &building_config.roof_pitch,
+        );
+
+        let footprint_choices = Self::get_sample(
+            client,
+            max_num_buildings,
+            &choice_distribution,
+        );
+
+        let orientations = Self::get_sample(
+            client,
+            max_num_buildings,
+            &choice_distribution,
+        );
+
+        let wings = Self::get_sample(
+            client,
+            max_num_buildings,
+            &building_config.footprint_wing,
+        );
+
+        let roof_weights: Vec<f64> = building_config.roof_shapes
             .iter()
-            .map(|density| density.recip())
+            .map(|(_, weight)| *weight)
             .collect();
-        
-        let y_offsets: Vec<f64> = y_densities
-            .await?
+
+        let footprint_weights: Vec<f64> = building_config.footprint_shapes
             .iter()
-            .map(|density| density.recip())
+            .map(|(_, weight)| *weight)
+            .collect();
+
+        let footprints: Vec<Footprint> = footprint_choices.await?
+            .into_iter()
+            .zip(orientations.await?)
+            .zip(wings.await?)
+            .map(|((choice, orientation), wing)| Footprint {
+                shape: building_config.footprint_shapes
+                    [stats::weighted_index(&footprint_weights, choice)].0,
+                orientation: (orientation * 8.0).min(7.0) as u8,
+                wing,
+            })
             .collect();
 
-        let building_rectangles = 
-            Self::build_buildings_around(
-                size,
-                x_offsets.into_iter(),
-                y_offsets.into_iter(),
-                spacings.await?.into_iter(),
-                stepbacks.await?.into_iter(),
-            )?;
+        let (building_rectangles, courtyard) = match lot_strategy {
+            LotStrategy::Faces => {
+                let inner_choice = Self::get_sample(client, 1, &choice_distribution);
+
+                let x_offsets = Self::weighted_offsets(x_densities.await?, |_| weight);
+                let y_offsets = Self::weighted_offsets(y_densities.await?, |_| weight);
+
+                let inner_building = inner_choice.await?[0] < building_config.inner_chance;
+                Self::build_buildings_around(
+                    size,
+                    rings,
+                    inner_building,
+                    x_offsets.into_iter(),
+                    y_offsets.into_iter(),
+                    spacings.await?.into_iter(),
+                    stepbacks.await?.into_iter(),
+                )?
+            },
+            LotStrategy::Subdivide | LotStrategy::Detached => {
+                let front_setbacks = Self::get_sample(
+                    client,
+                    max_num_buildings,
+                    &building_config.lots.front_setback,
+                );
+
+                let side_setbacks = Self::get_sample(
+                    client,
+                    max_num_buildings,
+                    &building_config.lots.side_setback,
+                );
+
+                let mut parcels = if lot_strategy == LotStrategy::Detached {
+                    // Suburban lots come laid out already, any other
+                    // block faces the street all round
+                    let mut parcels = std::mem::take(&mut block.parcels);
+                    if parcels.is_empty() {
+                        parcels.push(Parcel {
+                            footprint: Rectangle::new(Vector2::default(), size),
+                            frontage: [true; 4],
+                            yard: courtyard_kind,
+                            driveway: None,
+                        });
+                    }
+
+                    parcels
+                } else {
+                    // Each split looks at the area of the lot being
+                    // split and of both its halves
+                    let max_areas = Self::get_sample(
+                        client,
+                        2 * max_num_buildings,
+                        &building_config.lots.area,
+                    );
+
+                    let splits = Self::get_sample(client, max_num_buildings, &choice_distribution);
+
+                    parcel::subdivide(
+                        Rectangle::new(Vector2::default(), size),
+                        courtyard_kind,
+                        building_config.lots.frontage,
+                        &mut max_areas.await?.into_iter(),
+                        &mut splits.await?.into_iter(),
+                    )
+                };
+
+                let filler_choices = Self::get_sample(
+                    client,
+                    parcels.len() as u32,
+                    &choice_distribution,
+                );
+
+                let lots = parcels
+                    .iter_mut()
+                    .zip(front_setbacks.await?)
+                    .zip(side_setbacks.await?)
+                    .zip(stepbacks.await?)
+                    .zip(filler_choices.await?);
+
+                let mut buildings = Vec::new();
+                for ((((parcel, front), side), depth), choice) in lots {
+                    match Self::pick_filler(building_config, choice) {
+                        Some(kind) => block.fillers.push(
+                            Self::generate_filler(client, building_config, kind, parcel.footprint).await?
+                        ),
+                        None => if let Some(building) = parcel.building(front, side, depth) {
+                            if lot_strategy == LotStrategy::Detached {
+                                parcel.driveway = parcel.driveway(building);
+                            }
+
+                            buildings.push(building);
+                        },
+                    }
+                }
+
+                block.parcels.extend(parcels);
+                (buildings, None)
+            },
+        };
+
+        block.courtyards.extend(courtyard.map(|footprint| Courtyard {
+            footprint,
+            kind: courtyard_kind,
+        }));
 
         block.buildings.extend(
             &mut building_rectangles
                 .into_iter()
                 .zip(roof_edge_breadths.await?.iter())
                 .zip(roof_tints.await?.iter())
-                .map(|((building_rectangle, inset), tint)| {
-                    let red_tint = (0x30 as f64 * tint) as u8;
-                    let green_tint = (0x20 as f64 * tint) as u8;
-                    let roof_color = [
-                        0xB0 + red_tint,
-                        0xB0 + green_tint,
-                        0xB0,
-                        0xFF
-                    ];
+                .zip(floors.await?.iter())
+                .zip(roof_choices.await?.iter())
+                .zip(roof_pitches.await?.iter())
+                .zip(footprints)
+                .map(|((((((building_rectangle, inset), tint), floors), choice), pitch), shape)| {
+                    let roof_color = palette.roof(block.zone, *tint);
+                    let mut roof = Roof {
+                        shape: building_config.roof_shapes
+                            [stats::weighted_index(&roof_weights, *choice)].0,
+                        pitch: *pitch,
+                    };
+
+                    // Narrow lots don't have room for wings, and the
+                    // tiers of a stepped tower each have a flat roof
+                    let size = building_rectangle.dimensions();
+                    let shape = if size.x.min(size.y) < footprint::MIN_SHAPED_SPAN {
+                        Footprint::default()
+                    } else {
+                        shape
+                    };
+
+                    if shape.shape == FootprintShape::Stepped {
+                        roof.shape = RoofShape::Flat;
+                    }
+
+                    // Buildings lose height away from the centre, but
+                    // they all keep at least one floor
+                    let falloff = building_config.height_falloff * (1.0 - centrality);
+                    let floors = (floors * (1.0 - falloff)).round().max(1.0) as u32;
 
                     Building {
                         footprint: building_rectangle,
+                        shape,
                         roof_edge_breadth: *inset,
-                        height: 0.0,
+                        floors,
+                        height: floors as f64 * building_config.storey_height,
+                        elevation: 0.0,
                         roof_color,
+                        finish: Finish::for_roof(block.zone, roof.shape),
+                        roof,
+                        equipment: Vec::new(),
                     }
                 })
     );
@@ -474,68 +1368,1067 @@ impl Builder {
         Ok(())
     }
 
-    pub async fn build_buildings(mut self) -> Result<Self, GenerateError> {
-        let building_config = &self.config.buildings;
-        for block in self.city.blocks.iter_mut() {
-            Self::generate_block_buildings(
-                &self.client,
-                building_config,
-                block
-            ).await?;
-        }
-        
-        Ok(self)
+    /// Picks what fills an unbuilt block or lot, if anything, by
+    /// setting each filler's chance against a random number in [0, 1]
+    fn pick_filler(
+        building_config: &settings::config::BuildingConfig,
+        choice: f64,
+    ) -> Option<FillerKind> {
+        let mut threshold = 0.0;
+        building_config.fillers
+            .iter()
+            .find(|(_, chance)| {
+                threshold += chance.max(0.0);
+                choice < threshold
+            })
+            .map(|(kind, _)| *kind)
     }
 
-    /// This function generates the roads and blocks for the city, but it
-    /// does not initialize any buildings (or parks) on the blocks, they
-    /// are assumed to be flat, empty, concrete
-    pub async fn build_roads(mut self) -> Result<Self, GenerateError> {
-        let road_config = &self.config.roads;
-        let max_roads = Vector2i {
-            x: (self.city.size.x * road_config.density.x.max + 1.0) as u32,
-            y: (self.city.size.y * road_config.density.y.max + 1.0) as u32,
+    /// Fills an unbuilt footprint, parking cars in its bays if it's a
+    /// car park
+    async fn generate_filler(
+        client: &reqwest::Client,
+        building_config: &settings::config::BuildingConfig,
+        kind: FillerKind,
+        footprint: Rectangle,
+    ) -> Result<Filler, GenerateError> {
+        let bays = match kind {
+            FillerKind::Parking => filler::bays(footprint),
+            _ => Vec::new(),
         };
 
-        let max_breadth = max_roads.x + max_roads.y;
+        // The seed, then whether each bay is taken and by what colour
+        // of car
+        let choice_distribution = Distribution::uniform(0.0, 1.0);
+        let samples = Self::get_sample(
+            client,
+            1 + 2 * bays.len() as u32,
+            &choice_distribution,
+        ).await?;
 
-        let x_densities = Self::get_sample(
-            &self.client,
-            max_roads.x,
-            &road_config.density.x,
-        );
+        let seed = (samples[0] * u32::MAX as f64) as u32;
+        let cars = bays
+            .into_iter()
+            .zip(samples[1..].chunks(2))
+            .filter(|(_, bay)| bay[0] < building_config.parking_occupancy)
+            .map(|(bay, samples)| filler::park(bay, VehicleKind::Car.paint(samples[1])))
+            .collect();
 
-        let y_densities = Self::get_sample(
-            &self.client,
-            max_roads.y,
-            &road_config.density.y
-        );
-        
-        let breadths = Self::get_sample(
+        Ok(Filler {
+            kind,
+            footprint,
+            cars,
+            seed,
+            elevation: 0.0,
+        })
+    }
+
+    /// The average steepness of the ground across a block
+    fn block_slope(block: &Block, terrain: &Terrain) -> f64 {
+        let footprint = block.footprint;
+        let step = footprint.dimensions() * (1.0 / (BLOCK_SLOPE_SAMPLES - 1) as f64);
+        let mut total = 0.0;
+        for i in 0..BLOCK_SLOPE_SAMPLES {
+            for j in 0..BLOCK_SLOPE_SAMPLES {
+                let point = footprint.start() + step * Vector2 { x: i as f64, y: j as f64 };
+                total += terrain.slope(point);
+            }
+        }
+
+        total / BLOCK_SLOPE_SAMPLES.pow(2) as f64
+    }
+
+    /// Removes any buildings on a block that sit on a cliff or under a
+    /// road, along with any courtyards, lots and fillers under a road,
+    /// and stands the rest of the buildings and fillers on the
+    /// terrain.
+    fn settle_buildings(
+        block: &mut Block,
+        terrain: Option<&Terrain>,
+        max_slope: f64,
+        roads: &[Road],
+    ) {
+        let boundary_start = block.buildings_boundary().start();
+        let nearby_roads: Vec<&Rectangle> = roads
+            .iter()
+            .map(|road| &road.asphalt)
+            .filter(|asphalt| asphalt.intersection(&block.footprint).is_some())
+            .collect();
+
+        block.courtyards.retain(|courtyard| {
+            let footprint = courtyard.footprint.translate(boundary_start);
+            !nearby_roads.iter().any(|asphalt| asphalt.intersection(&footprint).is_some())
+        });
+
+        block.parcels.retain(|parcel| {
+            let footprint = parcel.footprint.translate(boundary_start);
+            !nearby_roads.iter().any(|asphalt| asphalt.intersection(&footprint).is_some())
+        });
+
+        block.fillers.retain_mut(|filler| {
+            let footprint = filler.footprint.translate(boundary_start);
+            if nearby_roads.iter().any(|asphalt| asphalt.intersection(&footprint).is_some()) {
+                return false;
+            }
+
+            if let Some(terrain) = terrain {
+                filler.elevation = terrain.height(footprint.centre());
+                for car in filler.cars.iter_mut() {
+                    car.elevation = filler.elevation;
+                }
+            }
+
+            true
+        });
+
+        block.buildings.retain_mut(|building| {
+            // Buildings are stored relative to the buildings boundary
+            let footprint = building.footprint.translate(boundary_start);
+            if nearby_roads.iter().any(|asphalt| asphalt.intersection(&footprint).is_some()) {
+                return false;
+            }
+
+            if let Some(terrain) = terrain {
+                if terrain.slope(footprint.centre()) > max_slope {
+                    return false;
+                }
+
+                building.elevation = terrain.height(footprint.centre());
+            }
+
+            true
+        });
+    }
+
+    pub async fn build_buildings(mut self) -> Result<Self, GenerateError> {
+        let terrain = self.city.terrain.as_ref();
+        let max_slope = self.config.terrain.max_slope;
+        for block in self.city.blocks.iter_mut() {
+            // Leave blocks on steep ground unbuilt
+            if let Some(terrain) = terrain {
+                if Self::block_slope(block, terrain) > max_slope {
+                    continue;
+                }
+            }
+
+            // The mask weighs the whole block by its centre, and
+            // black areas of the mask are left empty
+            let weight = match self.city.mask.as_ref() {
+                Some(mask) if self.config.mask.buildings => {
+                    mask.building_weight(block.footprint.centre())
+                },
+                _ => 1.0,
+            };
+
+            if weight < MIN_MASK_WEIGHT {
+                continue;
+            }
+
+            // Landmarks take the whole block
+            if block.landmark.is_some() {
+                continue;
+            }
+
+            // Parks are left unbuilt
+            let building_config = match Self::block_building_config(&self.config, block) {
+                Some(building_config) => building_config,
+                None => continue,
+            };
+
+            let setting = |name: &str| if self.config.zones.mode != "none" {
+                format!("zones.{}.buildings.{}", block.zone.name(), name)
+            } else {
+                format!("buildings.{}", name)
+            };
+
+            // Suburban lots are laid out with their streets
+            let lot_strategy = if block.suburban {
+                LotStrategy::Detached
+            } else {
+                LotStrategy::from_name(&building_config.lots.strategy)
+                    .ok_or_else(|| GenerateError::UnknownOption {
+                        setting: setting("lots.strategy"),
+                        value: building_config.lots.strategy.clone(),
+                    })?
+            };
+
+            let courtyard_kind = CourtyardKind::from_name(&building_config.courtyards)
+                .ok_or_else(|| GenerateError::UnknownOption {
+                    setting: setting("courtyards"),
+                    value: building_config.courtyards.clone(),
+                })?;
+
+            let centre = self.city.size * 0.5;
+            let plan = BlockPlan {
+                building_config,
+                palette: &self.city.palette,
+                lot_strategy,
+                courtyard_kind,
+                weight,
+                centrality: 1.0 - (block.footprint.centre() - centre).mag() / centre.mag(),
+            };
+
+            Self::generate_block_buildings(&self.client, &plan, block).await?;
+
+            Self::settle_buildings(block, terrain, max_slope, &self.city.roads);
+        }
+        
+        Ok(self)
+    }
+
+    /// The building settings a block follows. Zoned blocks follow the
+    /// settings for their zone, and parks don't have any.
+    fn block_building_config<'a>(
+        config: &'a Config,
+        block: &Block,
+    ) -> Option<&'a settings::config::BuildingConfig> {
+        if config.zones.mode != "none" {
+            config.zones.get(block.zone).buildings.as_ref()
+        } else {
+            Some(&config.buildings)
+        }
+    }
+
+    /// Scatters equipment over every flat roof. How much a roof gets
+    /// depends on its area and the zone it's in.
+    pub async fn build_rooftops(mut self) -> Result<Self, GenerateError> {
+        let is_flat = |building: &&Building| building.roof.shape == RoofShape::Flat;
+        let choice_distribution = Distribution::uniform(0.0, 1.0);
+
+        for block in self.city.blocks.iter_mut() {
+            let building_config = match Self::block_building_config(&self.config, block) {
+                Some(building_config) => building_config,
+                None => continue,
+            };
+
+            let flat_roofs = block.buildings.iter().filter(is_flat).count();
+            if flat_roofs == 0 {
+                continue;
+            }
+
+            // Counts are rounded up or down at random, so that small
+            // roofs still get the odd piece of equipment
+            let rolls = Self::get_sample(
+                &self.client,
+                (flat_roofs * building_config.rooftops.len()) as u32,
+                &choice_distribution,
+            ).await?;
+            let mut rolls = rolls.into_iter();
+
+            let counts: Vec<Vec<(RooftopKind, u32)>> = block.buildings
+                .iter()
+                .filter(is_flat)
+                .map(|building| {
+                    let size = building.main_roof().dimensions();
+                    let area = size.x.max(0.0) * size.y.max(0.0);
+                    building_config.rooftops
+                        .iter()
+                        .map(|(kind, density)| {
+                            let expected = area / 1000.0 * density;
+                            (*kind, (expected + rolls.next().unwrap_or(0.0)).floor() as u32)
+                        })
+                        .collect()
+                })
+                .collect();
+
+            let pieces: u32 = counts.iter().flatten().map(|(_, count)| count).sum();
+            if pieces == 0 {
+                continue;
+            }
+
+            let samples = Self::get_sample(
+                &self.client,
+                pieces * (rooftop::PLACEMENT_ATTEMPTS * rooftop::SAMPLES_PER_ATTEMPT) as u32,
+                &choice_distribution,
+            ).await?;
+            let mut samples = samples.into_iter();
+
+            let flat_buildings = block.buildings
+                .iter_mut()
+                .filter(|building| building.roof.shape == RoofShape::Flat);
+
+            for (building, counts) in flat_buildings.zip(counts) {
+                building.equipment = rooftop::scatter(
+                    building.main_roof(),
+                    &counts,
+                    building.elevation + building.height,
+                    &mut samples,
+                );
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Assigns every block a land use. Zones are weighed against each
+    /// other by their weight, and unless they're assigned at random,
+    /// by how close the block is to where the zone likes to be.
+    pub async fn build_zones(mut self) -> Result<Self, GenerateError> {
+        let zones_config = &self.config.zones;
+        let mode = zones_config.mode.as_str();
+        let mask = match (mode, self.city.mask.as_ref()) {
+            // Every block starts out residential
+            ("none", _) => return Ok(self),
+            ("random", _) | ("distance", _) => None,
+            ("mask", Some(mask)) => Some(mask),
+            ("mask", None) => return Err(GenerateError::MissingInputImage {
+                input: String::from("density mask"),
+                needed_by: String::from("\"mask\" zones mode"),
+            }),
+            (mode, _) => return Err(GenerateError::UnknownOption {
+                setting: String::from("zones.mode"),
+                value: String::from(mode),
+            }),
+        };
+
+        let centre = self.city.size * 0.5;
+        let centrality = |point: Vector2| match (mode, mask) {
+            ("distance", _) => Some(1.0 - (point - centre).mag() / centre.mag()),
+            (_, Some(mask)) => Some(mask.zone_weight(point)),
+            _ => None,
+        };
+
+        let choice_distribution = Distribution::uniform(0.0, 1.0);
+        let choices = Self::get_sample(
             &self.client,
-            max_breadth,
-            &road_config.breadth
+            self.city.blocks.len() as u32,
+            &choice_distribution,
+        ).await?;
+
+        let spread = zones_config.spread.max(f64::EPSILON);
+        for (block, choice) in self.city.blocks.iter_mut().zip(choices) {
+            // The suburbs are all houses
+            if block.suburban {
+                continue;
+            }
+
+            let block_centrality = centrality(block.footprint.centre());
+            let weights: Vec<f64> = zones_config.zones
+                .iter()
+                .map(|zone_config| {
+                    let affinity = block_centrality.map_or(1.0, |centrality| {
+                        (-((centrality - zone_config.centrality) / spread).powi(2)).exp()
+                    });
+
+                    zone_config.weight * affinity
+                })
+                .collect();
+
+            block.zone = zones_config.zones[stats::weighted_index(&weights, choice)].zone;
+        }
+
+        Ok(self)
+    }
+
+    /// Reserves blocks for landmarks. Each landmark goes on a block
+    /// picked at random from those it fits on, or where none is big
+    /// enough, on a pair of neighbouring blocks merged into one by
+    /// taking out the road between them. Landmarks that don't fit
+    /// anywhere are left out.
+    pub async fn build_landmarks(mut self) -> Result<Self, GenerateError> {
+        let landmarks_config = &self.config.landmarks;
+        let total: u32 = landmarks_config.landmarks
+            .iter()
+            .map(|landmark_config| landmark_config.count.max(0.0) as u32)
+            .sum();
+
+        if total == 0 {
+            return Ok(self);
+        }
+
+        let choice_distribution = Distribution::uniform(0.0, 1.0);
+        let choices = Self::get_sample(&self.client, total, &choice_distribution).await?;
+        let mut choices = choices.into_iter();
+
+        let sidewalk_breadth = self.config.city.sidewalk_breadth;
+        let max_slope = self.config.terrain.max_slope;
+        let pick = |count: usize, choice: f64| ((choice * count as f64) as usize).min(count - 1);
+
+        for landmark_config in landmarks_config.landmarks.iter() {
+            let size = Vector2 {
+                x: landmark_config.length,
+                y: landmark_config.breadth,
+            };
+
+            let fits = |footprint: &Rectangle| {
+                let boundary = footprint.inset(sidewalk_breadth).dimensions();
+                size.x <= boundary.x.max(boundary.y) && size.y <= boundary.x.min(boundary.y)
+            };
+
+            for _ in 0..landmark_config.count.max(0.0) as u32 {
+                let choice = choices.next().unwrap_or(0.5);
+                let terrain = self.city.terrain.as_ref();
+                let free = |block: &Block| {
+                    block.zone != Zone::Park
+                        && !block.suburban
+                        && block.landmark.is_none()
+                        && terrain.is_none_or(|terrain| Self::block_slope(block, terrain) <= max_slope)
+                };
+
+                let singles: Vec<usize> = self.city.blocks
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, block)| free(block) && fits(&block.footprint))
+                    .map(|(index, _)| index)
+                    .collect();
+
+                let index = if !singles.is_empty() {
+                    singles[pick(singles.len(), choice)]
+                } else {
+                    let superblocks: Vec<(usize, usize, Rectangle, Rectangle)> = self
+                        .superblocks(landmarks_config.max_merge_gap)
+                        .into_iter()
+                        .filter(|&(first, second, merged, _)| {
+                            free(&self.city.blocks[first])
+                                && free(&self.city.blocks[second])
+                                && fits(&merged)
+                        })
+                        .collect();
+
+                    if superblocks.is_empty() {
+                        continue;
+                    }
+
+                    let (first, second, merged, gap) = superblocks[pick(superblocks.len(), choice)];
+                    Self::cut_roads(&mut self.city.roads, gap);
+                    self.city.blocks[first].footprint = merged;
+                    self.city.blocks.remove(second);
+                    first
+                };
+
+                let elevation = self.city.terrain
+                    .as_ref()
+                    .map_or(0.0, |terrain| terrain.height(self.city.blocks[index].footprint.centre()));
+
+                let block = &mut self.city.blocks[index];
+                let boundary = block.buildings_boundary().dimensions();
+                let dimensions = if boundary.x >= boundary.y { size } else { size.transpose() };
+                let start = (boundary - dimensions) * 0.5;
+
+                block.zone = landmark_config.kind.zone();
+                block.landmark = Some(Landmark {
+                    kind: landmark_config.kind,
+                    footprint: Rectangle::new(start, start + dimensions),
+                    elevation,
+                });
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Finds the pairs of neighbouring blocks that line up exactly
+    /// across a road no wider than the gap, and don't have water
+    /// between them. Returns the indices of each pair (in order), the
+    /// superblock they'd make, and the stretch of road between them.
+    fn superblocks(&self, max_gap: f64) -> Vec<(usize, usize, Rectangle, Rectangle)> {
+        // Blocks on either side of a road line up to within this
+        let tolerance = 0.5;
+        let blocks = &self.city.blocks;
+        let mut superblocks = Vec::new();
+
+        for (first, a) in blocks.iter().enumerate() {
+            for (second, b) in blocks.iter().enumerate().skip(first + 1) {
+                let (a, b) = (a.footprint, b.footprint);
+                let gap = [(a, b), (b, a)]
+                    .into_iter()
+                    .find_map(|(left, right)| {
+                        let along_x = (left.start().y - right.start().y).abs() < tolerance
+                            && (left.end().y - right.end().y).abs() < tolerance
+                            && right.start().x > left.end().x;
+                        let along_y = (left.start().x - right.start().x).abs() < tolerance
+                            && (left.end().x - right.end().x).abs() < tolerance
+                            && right.start().y > left.end().y;
+
+                        if along_x {
+                            Some(Rectangle::new(
+                                Vector2 { x: left.end().x, y: left.start().y },
+                                Vector2 { x: right.start().x, y: left.end().y },
+                            ))
+                        } else if along_y {
+                            Some(Rectangle::new(
+                                Vector2 { x: left.start().x, y: left.end().y },
+                                Vector2 { x: left.end().x, y: right.start().y },
+                            ))
+                        } else {
+                            None
+                        }
+                    });
+
+                let gap = match gap {
+                    Some(gap) if gap.width().min(gap.height()) <= max_gap => gap,
+                    _ => continue,
+                };
+
+                // Neither water nor a railway can be built over, the
+                // bed of a railway is kept like a channel with its
+                // verges for quays
+                let beds = self.city.railways.iter().map(|railway| &railway.bed);
+                let blocked = self.city.waterways.iter().chain(beds).any(|waterway| {
+                    waterway.channel
+                        .iter()
+                        .chain(waterway.quays.iter())
+                        .any(|slice| slice.intersection(&gap).is_some())
+                });
+
+                if blocked {
+                    continue;
+                }
+
+                let merged = Rectangle::new(
+                    Vector2 {
+                        x: a.start().x.min(b.start().x),
+                        y: a.start().y.min(b.start().y),
+                    },
+                    Vector2 {
+                        x: a.end().x.max(b.end().x),
+                        y: a.end().y.max(b.end().y),
+                    },
+                );
+
+                superblocks.push((first, second, merged, gap));
+            }
+        }
+
+        superblocks
+    }
+
+    /// Takes the stretch of road crossing a gap out of every road,
+    /// leaving whatever's left of each road either side of it
+    fn cut_roads(roads: &mut Vec<Road>, gap: Rectangle) {
+        for road in std::mem::take(roads) {
+            if road.asphalt.intersection(&gap).is_none() {
+                roads.push(road);
+                continue;
+            }
+
+            let (start, end) = (road.asphalt.start(), road.asphalt.end());
+            let pieces = if road.asphalt.height() >= road.asphalt.width() {
+                [
+                    (start, Vector2 { x: end.x, y: gap.start().y.min(end.y) }),
+                    (Vector2 { x: start.x, y: gap.end().y.max(start.y) }, end),
+                ]
+            } else {
+                [
+                    (start, Vector2 { x: gap.start().x.min(end.x), y: end.y }),
+                    (Vector2 { x: gap.end().x.max(start.x), y: start.y }, end),
+                ]
+            };
+
+            for (piece_start, piece_end) in pieces {
+                if piece_start.x < piece_end.x && piece_start.y < piece_end.y {
+                    roads.push(Road {
+                        asphalt: Rectangle::new(piece_start, piece_end),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Samples the channel of a meandering river flowing along x. The
+    /// centreline is a sum of sine waves, with each successive
+    /// harmonic contributing less to the overall shape.
+    async fn river_channel(
+        client: &reqwest::Client,
+        water_config: &settings::config::WaterConfig,
+        size: Vector2,
+    ) -> Result<Vec<Rectangle>, GenerateError> {
+        let amplitudes = Self::get_sample(
+            client,
+            MEANDER_HARMONICS,
+            &water_config.meander_amplitude,
         );
 
-        let size = self.city.size;
+        let wavelengths = Self::get_sample(
+            client,
+            MEANDER_HARMONICS,
+            &water_config.meander_wavelength,
+        );
 
-        let x_offsets = x_densities.await?
+        let phase_distribution = Distribution::uniform(0.0, TAU);
+        let phases = Self::get_sample(
+            client,
+            MEANDER_HARMONICS,
+            &phase_distribution,
+        );
+
+        let breadth = Self::get_sample(client, 1, &water_config.breadth);
+
+        // Keep the river roughly in the middle half of the city
+        let middle_distribution = Distribution::uniform(size.y * 0.25, size.y * 0.75);
+        let middle = Self::get_sample(client, 1, &middle_distribution);
+
+        let harmonics: Vec<(f64, f64, f64)> = amplitudes
+            .await?
             .into_iter()
-            .map(|density| density.recip());
+            .zip(wavelengths.await?)
+            .zip(phases.await?)
+            .map(|((amplitude, wavelength), phase)| (amplitude, wavelength, phase))
+            .collect();
+
+        let half_breadth = breadth.await?[0] / 2.0;
+        let middle = middle.await?[0];
+
+        let slice_count = (size.x / WATER_SLICE_LENGTH).ceil() as u32;
+        let channel = (0..slice_count)
+            .map(|i| {
+                let x = i as f64 * WATER_SLICE_LENGTH;
+                let centre = middle + harmonics
+                    .iter()
+                    .enumerate()
+                    .map(|(k, (amplitude, wavelength, phase))| {
+                        amplitude / (k + 1) as f64 * (TAU * x / wavelength + phase).sin()
+                    })
+                    .sum::<f64>();
+
+                Rectangle::new(
+                    Vector2 { x, y: centre - half_breadth },
+                    Vector2 {
+                        x: f64::min(x + WATER_SLICE_LENGTH, size.x),
+                        y: centre + half_breadth,
+                    },
+                )
+            })
+            .collect();
+
+        Ok(channel)
+    }
+
+    /// Samples the channels of some straight canals flowing along x.
+    /// Each canal is a single slice spanning the whole city.
+    async fn canal_channels(
+        client: &reqwest::Client,
+        water_config: &settings::config::WaterConfig,
+        size: Vector2,
+    ) -> Result<Vec<Vec<Rectangle>>, GenerateError> {
+        let count = water_config.canal_count.max(0.0) as u32;
+        let breadths = Self::get_sample(client, count, &water_config.breadth);
+        let centre_distribution = Distribution::uniform(0.0, size.y);
+        let centres = Self::get_sample(client, count, &centre_distribution);
+
+        let channels = centres
+            .await?
+            .into_iter()
+            .zip(breadths.await?)
+            .map(|(centre, breadth)| vec![Rectangle::new(
+                Vector2 { x: 0.0, y: centre - breadth / 2.0 },
+                Vector2 { x: size.x, y: centre + breadth / 2.0 },
+            )])
+            .collect();
+
+        Ok(channels)
+    }
+
+    /// Samples the sea along one edge of the city, in a frame where
+    /// that edge runs along x and the sea lies towards y = 0. This
+    /// returns the slices of sea along with the piers jutting into it.
+    async fn coast_channel(
+        client: &reqwest::Client,
+        coast_config: &settings::config::CoastConfig,
+        noise: Noise,
+        length: f64,
+    ) -> Result<(Vec<Rectangle>, Vec<Rectangle>), GenerateError> {
+        let max_piers = (length * coast_config.pier_density.max + 1.0) as u32;
+
+        let depth = Self::get_sample(client, 1, &coast_config.depth);
+        let pier_densities = Self::get_sample(
+            client,
+            max_piers,
+            &coast_config.pier_density,
+        );
+
+        let pier_lengths = Self::get_sample(
+            client,
+            max_piers,
+            &coast_config.pier_length,
+        );
+
+        let pier_breadths = Self::get_sample(
+            client,
+            max_piers,
+            &coast_config.pier_breadth,
+        );
+
+        let depth = depth.await?[0];
+        let shoreline = |x: f64| {
+            let displacement = noise.fractal(
+                Vector2 { x: x / coast_config.wavelength, y: 0.0 },
+                SHORE_OCTAVES,
+            );
+
+            f64::max(depth + coast_config.roughness * displacement, 0.0)
+        };
+
+        let slice_count = (length / WATER_SLICE_LENGTH).ceil() as u32;
+        let channel = (0..slice_count)
+            .map(|i| {
+                let x = i as f64 * WATER_SLICE_LENGTH;
+                Rectangle::new(
+                    Vector2 { x, y: 0.0 },
+                    Vector2 {
+                        x: f64::min(x + WATER_SLICE_LENGTH, length),
+                        y: shoreline(x + WATER_SLICE_LENGTH / 2.0),
+                    },
+                )
+            })
+            .collect();
 
-        let y_offsets = y_densities.await?
+        let pier_offsets = pier_densities
+            .await?
             .into_iter()
             .map(|density| density.recip());
-            
-        let GridPartition {
-            lines: road_rectangles,
-            rectangles: block_rectangles
-        } = GridPartition::new(
-            x_offsets,
-            y_offsets,
-            breadths.await?.into_iter(),
-            size,
-        )?;
+
+        let mut piers = Vec::new();
+        let mut offset = 0.0;
+        let pier_sizes = pier_lengths.await?.into_iter().zip(pier_breadths.await?);
+        for (delta_offset, (pier_length, breadth)) in pier_offsets.zip(pier_sizes) {
+            offset += delta_offset;
+            if offset >= length {
+                break;
+            }
+
+            let shore = shoreline(offset);
+            piers.push(Rectangle::new(
+                Vector2 { x: offset - breadth / 2.0, y: shore },
+                Vector2 {
+                    x: offset + breadth / 2.0,
+                    y: f64::max(shore - pier_length, 0.0),
+                },
+            ));
+        }
+
+        Ok((channel, piers))
+    }
+
+    /// Generates the sea along every edge of the city marked as coast
+    /// in the settings.
+    async fn build_coast(&mut self) -> Result<(), GenerateError> {
+        let coast_config = &self.config.coast;
+        let size = self.city.size;
+
+        // Each edge is generated in its own frame, then moved into
+        // place. The edges are north, south, west then east.
+        let edges = [
+            (coast_config.north, true, size.x),
+            (coast_config.south, true, size.x),
+            (coast_config.west, false, size.y),
+            (coast_config.east, false, size.y),
+        ];
+
+        if !edges.iter().any(|(enabled, _, _)| *enabled) {
+            return Ok(());
+        }
+
+        let seed_distribution = Distribution::uniform(0.0, u32::MAX as f64);
+        let seed = Self::get_sample(&self.client, 1, &seed_distribution).await?[0] as u32;
+
+        for (index, (enabled, flows_along_x, length)) in edges.into_iter().enumerate() {
+            if !enabled {
+                continue;
+            }
+
+            let noise = Noise::new(seed.wrapping_add(index as u32));
+            let (channel, piers) = Self::coast_channel(
+                &self.client,
+                coast_config,
+                noise,
+                length,
+            ).await?;
+
+            let to_city = |rectangle: Rectangle| match index {
+                0 => rectangle,
+                1 => rectangle.mirror_y(size.y),
+                2 => rectangle.transpose(),
+                _ => rectangle.transpose().mirror_x(size.x),
+            };
+
+            self.city.waterways.push(Waterway {
+                channel: channel.into_iter().map(to_city).collect(),
+                quays: Vec::new(),
+                quay_breadth: self.config.water.quay_breadth,
+                flows_along_x,
+                bridged: false,
+            });
+
+            self.city.piers.extend(piers.into_iter().map(|deck| Pier {
+                deck: to_city(deck),
+                edge_breadth: coast_config.pier_border,
+            }));
+        }
+
+        Ok(())
+    }
+
+    /// This function carves the rivers, canals and coast into the
+    /// city. It has to run before the roads are generated, because
+    /// the roads and blocks get clipped against the water as they're
+    /// built.
+    pub async fn build_water(mut self) -> Result<Self, GenerateError> {
+        let water_config = &self.config.water;
+        let flows_along_x = water_config.flows_along_x;
+
+        // The channels are generated as if the water flows along x
+        let size = if flows_along_x {
+            self.city.size
+        } else {
+            self.city.size.transpose()
+        };
+
+        let channels = match water_config.kind.as_str() {
+            "none" => Vec::new(),
+            "river" => vec![
+                Self::river_channel(&self.client, water_config, size).await?
+            ],
+            "canals" => Self::canal_channels(&self.client, water_config, size).await?,
+            kind => return Err(GenerateError::UnknownOption {
+                setting: String::from("water.kind"),
+                value: String::from(kind),
+            }),
+        };
+
+        self.city.waterways = channels
+            .into_iter()
+            .map(|channel| Waterway {
+                channel: channel
+                    .into_iter()
+                    .map(|slice| if flows_along_x { slice } else { slice.transpose() })
+                    .collect(),
+                quays: Vec::new(),
+                quay_breadth: water_config.quay_breadth,
+                flows_along_x,
+                bridged: true,
+            })
+            .collect();
+
+        self.build_coast().await?;
+
+        Ok(self)
+    }
+
+    /// Cuts the road and block rectangles against every waterway in
+    /// the city. Roads over rivers and canals become bridges (roads
+    /// into the sea are dropped), and blocks are
+    /// clipped back to the banks with quays filling the gap.
+    fn carve_water(
+        &mut self,
+        mut roads: Vec<Rectangle>,
+        mut blocks: Vec<Rectangle>,
+    ) -> (Vec<Rectangle>, Vec<Rectangle>) {
+        let railing_breadth = self.config.water.railing_breadth;
+        for waterway in self.city.waterways.iter_mut() {
+            let mut dry_roads = Vec::new();
+            for road in roads {
+                let (dry, spans) = waterway.split_road(&road);
+                dry_roads.extend(dry);
+
+                // Roads just stop at the shore if there's no bridging
+                if waterway.bridged {
+                    self.city.bridges.extend(spans.into_iter().map(|deck| Bridge {
+                        deck,
+                        railing_breadth,
+                    }));
+                }
+            }
+
+            // Bridges over earlier waterways stop at the shore too,
+            // or a river's bridges would run on out to sea
+            if !waterway.bridged {
+                let bridges = std::mem::take(&mut self.city.bridges);
+                for bridge in bridges {
+                    let (dry, _) = waterway.split_road(&bridge.deck);
+                    self.city.bridges.extend(dry.into_iter().map(|deck| Bridge {
+                        deck,
+                        ..bridge
+                    }));
+                }
+            }
+
+            let mut dry_blocks = Vec::new();
+            for block in blocks {
+                let (dry, quays) = waterway.clip_block(&block);
+                dry_blocks.extend(dry);
+                waterway.quays.extend(quays);
+            }
+
+            roads = dry_roads;
+            blocks = dry_blocks;
+        }
+
+        (roads, blocks)
+    }
+
+    /// Bends the grid's roads along the contours of the terrain, if
+    /// there is any, then cuts the roads and blocks at the water.
+    /// Bending comes first so no slice can wander back into water
+    /// that carving cleared, and slices that wander into a block are
+    /// cut back to the street.
+    fn lay_grid(
+        &mut self,
+        roads: Vec<Rectangle>,
+        blocks: Vec<Rectangle>,
+    ) -> (Vec<Rectangle>, Vec<Rectangle>) {
+        let roads = match self.city.terrain.as_ref() {
+            Some(terrain) => {
+                let max_bend = self.config.terrain.max_bend;
+                roads
+                    .iter()
+                    .flat_map(|road| Self::bend_road(road, terrain, max_bend))
+                    .filter_map(|slice| Self::clip_to_street(slice, &blocks))
+                    .collect()
+            },
+            None => roads,
+        };
+
+        self.carve_water(roads, blocks)
+    }
+
+    /// Cuts a slice of road back out of any block it overlaps,
+    /// keeping the largest piece left beside each block. A slice that
+    /// lies wholly inside a block is dropped.
+    fn clip_to_street(slice: Rectangle, blocks: &[Rectangle]) -> Option<Rectangle> {
+        let mut slice = slice;
+        for block in blocks {
+            if slice.intersection(block).is_none() {
+                continue;
+            }
+
+            let (start, end) = (slice.start(), slice.end());
+            let (block_start, block_end) = (block.start(), block.end());
+            let pieces = [
+                (start, Vector2 { x: block_start.x.min(end.x), y: end.y }),
+                (Vector2 { x: block_end.x.max(start.x), y: start.y }, end),
+                (start, Vector2 { x: end.x, y: block_start.y.min(end.y) }),
+                (Vector2 { x: start.x, y: block_end.y.max(start.y) }, end),
+            ];
+
+            slice = pieces
+                .into_iter()
+                .filter(|(start, end)| start.x < end.x && start.y < end.y)
+                .map(|(start, end)| Rectangle::new(start, end))
+                .reduce(|best, piece| {
+                    if piece.width() * piece.height() > best.width() * best.height() { piece } else { best }
+                })?;
+        }
+
+        Some(slice)
+    }
+
+    /// Bends a straight road sideways so that it climbs as evenly as
+    /// it can between its ends, which makes it wind along the
+    /// contours of steep ground. The ends stay where they are so the
+    /// road still meets whatever is at either end.
+    fn bend_road(road: &Rectangle, terrain: &Terrain, max_bend: f64) -> Vec<Rectangle> {
+        // Work in a frame where the road runs along y
+        let along_y = road.height() >= road.width();
+        let to_frame = |point: Vector2| if along_y { point } else { point.transpose() };
+        let frame = if along_y { *road } else { road.transpose() };
+
+        let height_at = |point: Vector2| terrain.height(to_frame(point));
+        let grade_across = |point: Vector2| to_frame(terrain.gradient(to_frame(point))).x;
+
+        let centre = frame.centre().x;
+        let (start, end) = (frame.start().y, frame.end().y);
+        let length = end - start;
+        let start_height = height_at(Vector2 { x: centre, y: start });
+        let end_height = height_at(Vector2 { x: centre, y: end });
+
+        let slice_count = (length / ROAD_SLICE_LENGTH).ceil() as u32;
+        let mut previous_shift = 0.0;
+        let mut slices = Vec::new();
+        for i in 0..slice_count {
+            let slice_start = start + i as f64 * ROAD_SLICE_LENGTH;
+            let slice_end = f64::min(slice_start + ROAD_SLICE_LENGTH, end);
+            let middle = Vector2 { x: centre, y: (slice_start + slice_end) / 2.0 };
+            let progress = (middle.y - start) / length;
+
+            // Slide across to where the ground is at the height an
+            // evenly climbing road would be at
+            let target = start_height + (end_height - start_height) * progress;
+            let grade = grade_across(middle);
+            let shift = if grade.abs() > MIN_BEND_GRADE {
+                ((target - height_at(middle)) / grade).clamp(-max_bend, max_bend)
+            } else {
+                0.0
+            };
+
+            // Taper off towards the ends, and never turn more than 45
+            // degrees in one slice so the road stays in one piece
+            let shift = (shift * (PI * progress).sin()).clamp(
+                previous_shift - ROAD_SLICE_LENGTH,
+                previous_shift + ROAD_SLICE_LENGTH,
+            );
+            previous_shift = shift;
+
+            let slice = Rectangle::new(
+                Vector2 { x: frame.start().x + shift, y: slice_start },
+                Vector2 { x: frame.end().x + shift, y: slice_end },
+            );
+
+            slices.push(if along_y { slice } else { slice.transpose() });
+        }
+
+        slices
+    }
+
+    /// This function generates the roads and blocks for the city, but it
+    /// does not initialize any buildings (or parks) on the blocks, they
+    /// are assumed to be flat, empty, concrete
+    pub async fn build_roads(mut self) -> Result<Self, GenerateError> {
+        let size = self.city.size;
+
+        // The grid covers the whole city, or just its core when it's
+        // ringed by suburbs
+        let core = match self.config.city.layout.as_str() {
+            "grid" => Some(Rectangle::new(Vector2::default(), size)),
+            "suburban" => None,
+            "mixed" => {
+                let core_size = size * self.config.city.core.clamp(0.0, 1.0);
+                let start = (size - core_size) * 0.5;
+                Some(Rectangle::new(start, start + core_size))
+            },
+            layout => return Err(GenerateError::UnknownOption {
+                setting: String::from("city.layout"),
+                value: String::from(layout),
+            }),
+        };
+
+        let (grid_roads, grid_blocks) = match core {
+            Some(core) => self.build_grid(core).await?,
+            None => (Vec::new(), Vec::new()),
+        };
+
+        // The suburbs fill whatever the grid leaves, on each side of
+        // the core
+        let suburb_areas = match core {
+            None => vec![Rectangle::new(Vector2::default(), size)],
+            Some(core) => [
+                (Vector2::default(), Vector2 { x: size.x, y: core.start().y }),
+                (Vector2 { x: 0.0, y: core.end().y }, size),
+                (Vector2 { x: 0.0, y: core.start().y }, Vector2 { x: core.start().x, y: core.end().y }),
+                (Vector2 { x: core.end().x, y: core.start().y }, Vector2 { x: size.x, y: core.end().y }),
+            ]
+                .into_iter()
+                .map(|(start, end)| Rectangle::new(start, end))
+                .filter(|area| area.width().min(area.height()) > 2.0 * self.config.suburbs.lot_depth)
+                .collect(),
+        };
+
+        let mut suburb_streets = Vec::new();
+        let mut suburb_lots = Vec::new();
+        for area in suburb_areas {
+            let suburb = self.build_suburb(area, &grid_roads).await?;
+            suburb_streets.extend(suburb.streets);
+            suburb_lots.extend(suburb.lots);
+        }
+
+        let (mut road_rectangles, block_rectangles) = self.lay_grid(grid_roads, grid_blocks);
+
+        // Suburban streets already wind, so they're only cut at the
+        // water, and lots that reach into the water are left out
+        let (suburb_streets, _) = self.carve_water(suburb_streets, Vec::new());
+        road_rectangles.extend(suburb_streets);
+        let waterways = &self.city.waterways;
+        suburb_lots.retain(|lot| waterways.iter().all(|waterway| waterway.is_clear_of(&lot.footprint)));
 
         self.city.roads = road_rectangles
             .into_iter()
@@ -548,9 +2441,550 @@ impl Builder {
                 footprint: block_rectangle,
                 buildings: Vec::new(),
                 sidewalk_breadth: self.config.city.sidewalk_breadth,
+                zone: Zone::Residential,
+                courtyards: Vec::new(),
+                parcels: Vec::new(),
+                landmark: None,
+                fillers: Vec::new(),
+                suburban: false,
             })
             .collect();
 
+        // Each suburban lot is a block of its own, already laid out
+        // as a single parcel facing the street
+        self.city.blocks.extend(suburb_lots.into_iter().map(|lot| Block {
+            footprint: lot.footprint,
+            buildings: Vec::new(),
+            sidewalk_breadth: 0.0,
+            zone: Zone::Residential,
+            courtyards: Vec::new(),
+            parcels: vec![Parcel {
+                footprint: Rectangle::new(Vector2::default(), lot.footprint.dimensions()),
+                frontage: lot.frontage,
+                yard: CourtyardKind::Garden,
+                driveway: None,
+            }],
+            landmark: None,
+            fillers: Vec::new(),
+            suburban: true,
+        }));
+
+        Ok(self)
+    }
+
+    /// Partitions an area of the city into a grid of roads and
+    /// blocks, returning the rectangles of each
+    async fn build_grid(&self, area: Rectangle) -> Result<(Vec<Rectangle>, Vec<Rectangle>), GenerateError> {
+        let road_config = &self.config.roads;
+        let size = area.dimensions();
+        let max_roads = Vector2i {
+            x: (size.x * road_config.density.x.max + 1.0) as u32,
+            y: (size.y * road_config.density.y.max + 1.0) as u32,
+        };
+
+        let max_breadth = max_roads.x + max_roads.y;
+
+        let x_densities = Self::get_sample(
+            &self.client,
+            max_roads.x,
+            &road_config.density.x,
+        );
+
+        let y_densities = Self::get_sample(
+            &self.client,
+            max_roads.y,
+            &road_config.density.y
+        );
+        
+        let breadths = Self::get_sample(
+            &self.client,
+            max_breadth,
+            &road_config.breadth
+        );
+
+        // Vertical roads are weighed by the mask down their whole
+        // length, and horizontal ones across their whole length
+        let (start, end) = (area.start(), area.end());
+        let mask = self.city.mask.as_ref().filter(|_| self.config.mask.roads);
+        let column_weight = |x: f64| mask.map_or(1.0, |mask| mask.road_weight_along(
+            Vector2 { x: start.x + x, y: start.y },
+            Vector2 { x: start.x + x, y: end.y },
+        ));
+
+        let row_weight = |y: f64| mask.map_or(1.0, |mask| mask.road_weight_along(
+            Vector2 { x: start.x, y: start.y + y },
+            Vector2 { x: end.x, y: start.y + y },
+        ));
+
+        let x_offsets = Self::weighted_offsets(x_densities.await?, column_weight);
+        let y_offsets = Self::weighted_offsets(y_densities.await?, row_weight);
+            
+        let GridPartition {
+            lines: road_rectangles,
+            rectangles: block_rectangles
+        } = GridPartition::new(
+            x_offsets.into_iter(),
+            y_offsets.into_iter(),
+            breadths.await?.into_iter(),
+            size,
+        )?;
+
+        Ok((
+            road_rectangles.into_iter().map(|road| road.translate(start)).collect(),
+            block_rectangles.into_iter().map(|block| block.translate(start)).collect(),
+        ))
+    }
+
+    /// Lays out the streets and lots of a suburb over an area, with
+    /// the roads given running on into it wherever they meet its edge
+    async fn build_suburb(
+        &self,
+        area: Rectangle,
+        entries: &[Rectangle],
+    ) -> Result<suburb::Suburb, GenerateError> {
+        let suburb_config = &self.config.suburbs;
+        let layout = SuburbLayout {
+            collector_breadth: suburb_config.collector_breadth,
+            street_breadth: suburb_config.street_breadth,
+            collector_spacing: suburb_config.collector_spacing,
+            loop_chance: suburb_config.loop_chance,
+            bulb_radius: suburb_config.bulb_radius,
+            lot_depth: suburb_config.lot_depth,
+            max_bow: suburb_config.max_bow,
+        };
+
+        let count = suburb::collectors(area, &layout);
+        let amplitudes = Self::get_sample(&self.client, count, &suburb_config.curve_amplitude);
+        let wavelengths = Self::get_sample(&self.client, count, &suburb_config.curve_wavelength);
+        let phase_distribution = Distribution::uniform(0.0, TAU);
+        let phases = Self::get_sample(&self.client, count, &phase_distribution);
+
+        // Every lot takes a width, and there can't be many more lots
+        // than fit in the area, plus those along the collectors. Each
+        // side street takes two choices, and there's at most one
+        // every lot depth along either side of each collector.
+        let length = area.width().max(area.height());
+        let min_width = suburb_config.lot_width.min.max(suburb::MIN_LOT_WIDTH);
+        let depth = suburb_config.lot_depth.max(1.0);
+        let max_lots = 2.0 * area.width() * area.height() / (min_width * depth)
+            + 2.0 * count as f64 * length / min_width;
+        let widths = Self::get_sample(&self.client, max_lots.ceil() as u32 + 1, &suburb_config.lot_width);
+
+        let max_branches = 2 * count * (length / depth).ceil() as u32;
+        let choice_distribution = Distribution::uniform(0.0, 1.0);
+        let choices = Self::get_sample(&self.client, 2 * max_branches + 2, &choice_distribution);
+
+        let curves: Vec<(f64, f64, f64)> = amplitudes.await?
+            .into_iter()
+            .zip(wavelengths.await?)
+            .zip(phases.await?)
+            .map(|((amplitude, wavelength), phase)| (amplitude, wavelength, phase))
+            .collect();
+
+        Ok(suburb::lay_out(
+            area,
+            &layout,
+            &curves,
+            entries,
+            &mut widths.await?.into_iter(),
+            &mut choices.await?.into_iter(),
+        ))
+    }
+
+    /// Cuts railway lines through the city, each in a band of its
+    /// own. A corridor claims whatever blocks it runs through the same
+    /// way a river does, leaving a verge between the tracks and the
+    /// blocks, and crosses water on bridges. Roads cross it at grade,
+    /// or on a bridge if they're broad or cross at a station, and
+    /// roads running along the corridor are taken up.
+    pub async fn build_rail(mut self) -> Result<Self, GenerateError> {
+        let rail_config = &self.config.rail;
+        let count = rail_config.lines.max(0.0) as u32;
+        if count == 0 {
+            return Ok(self);
+        }
+
+        // The corridors are laid out as if they run along x
+        let flows_along_x = rail_config.flows_along_x;
+        let size = if flows_along_x {
+            self.city.size
+        } else {
+            self.city.size.transpose()
+        };
+
+        let amplitudes = Self::get_sample(&self.client, count, &rail_config.curve_amplitude);
+        let wavelengths = Self::get_sample(&self.client, count, &rail_config.curve_wavelength);
+        let choice_distribution = Distribution::uniform(0.0, 1.0);
+        let choices = Self::get_sample(&self.client, 3 * count, &choice_distribution);
+
+        let tracks = rail_config.tracks.max(1.0) as u32;
+        let spacing = rail_config.station_spacing.max(rail_config.station_length);
+        let length = rail_config.station_length.max(0.0);
+        let band = size.y / count as f64;
+        let to_world = |rectangle: Rectangle| if flows_along_x { rectangle } else { rectangle.transpose() };
+
+        let choices = choices.await?;
+        let lines = amplitudes.await?
+            .into_iter()
+            .zip(wavelengths.await?)
+            .zip(choices.chunks(3));
+
+        let mut railways = Vec::new();
+        for (index, ((amplitude, wavelength), choices)) in lines.enumerate() {
+            let &[middle, phase, first_station] = choices else {
+                continue;
+            };
+
+            // Keep each line in the middle half of its band, and its
+            // stations clear of the edges of the city
+            let middle = band * (index as f64 + 0.25 + 0.5 * middle);
+            let mut stations = Vec::new();
+            let mut start = spacing * first_station;
+            while length > 0.0 && start + length <= size.x {
+                stations.push((start, start + length));
+                start += spacing;
+            }
+
+            let (slices, platforms) = rail::corridor(
+                size.x,
+                middle,
+                (amplitude, wavelength, phase * TAU),
+                tracks,
+                &stations,
+            );
+
+            let stations = platforms
+                .into_iter()
+                .filter(|platforms| !platforms.is_empty())
+                .map(|platforms| {
+                    let area = platforms
+                        .iter()
+                        .copied()
+                        .reduce(|bounds, platform| Rectangle::new(
+                            Vector2 {
+                                x: bounds.start().x.min(platform.start().x),
+                                y: bounds.start().y.min(platform.start().y),
+                            },
+                            Vector2 {
+                                x: bounds.end().x.max(platform.end().x),
+                                y: bounds.end().y.max(platform.end().y),
+                            },
+                        ))
+                        .unwrap_or_else(|| unreachable!("Stations with no platforms are left out"));
+
+                    let area = to_world(area);
+                    let elevation = self.city.terrain
+                        .as_ref()
+                        .map_or(0.0, |terrain| terrain.height(area.centre()));
+
+                    Station {
+                        area,
+                        platforms: platforms.into_iter().map(to_world).collect(),
+                        flows_along_x,
+                        elevation,
+                    }
+                })
+                .collect();
+
+            railways.push(Railway {
+                bed: Waterway {
+                    channel: slices.into_iter().map(to_world).collect(),
+                    quays: Vec::new(),
+                    quay_breadth: rail_config.verge_breadth,
+                    flows_along_x,
+                    bridged: true,
+                },
+                tracks,
+                crossings: Vec::new(),
+                stations,
+                bridges: Vec::new(),
+            });
+        }
+
+        let railing_breadth = self.config.water.railing_breadth;
+        for railway in railways.iter_mut() {
+            railway.bridge_over(&self.city.waterways, railing_breadth);
+
+            let roads = std::mem::take(&mut self.city.roads);
+            for road in roads {
+                let (dry, spans) = railway.bed.split_road(&road.asphalt);
+                self.city.roads.extend(dry.into_iter().map(|asphalt| Road { asphalt }));
+
+                let breadth = road.asphalt.width().min(road.asphalt.height());
+                let along_line = (road.asphalt.width() >= road.asphalt.height()) == flows_along_x;
+                for span in spans {
+                    let at_station = railway.stations
+                        .iter()
+                        .any(|station| station.area.intersection(&span).is_some());
+
+                    if along_line {
+                        continue;
+                    } else if at_station || breadth >= rail_config.bridge_breadth {
+                        self.city.bridges.push(Bridge { deck: span, railing_breadth });
+                    } else {
+                        railway.crossings.push(span);
+                    }
+                }
+            }
+
+            // Suburban lots the line runs through are taken whole
+            let blocks = std::mem::take(&mut self.city.blocks);
+            for block in blocks {
+                let (pieces, verges) = railway.bed.clip_block(&block.footprint);
+                if verges.is_empty() && pieces.len() == 1 {
+                    self.city.blocks.push(block);
+                    continue;
+                }
+
+                railway.bed.quays.extend(verges);
+                if block.suburban {
+                    continue;
+                }
+
+                self.city.blocks.extend(pieces.into_iter().map(|footprint| Block {
+                    footprint,
+                    buildings: Vec::new(),
+                    sidewalk_breadth: block.sidewalk_breadth,
+                    zone: block.zone,
+                    courtyards: Vec::new(),
+                    parcels: Vec::new(),
+                    landmark: None,
+                    fillers: Vec::new(),
+                    suburban: false,
+                }));
+            }
+        }
+
+        self.city.railways = railways;
+        Ok(self)
+    }
+
+    /// Fills the lanes of every road with traffic keeping to one side
+    /// of the road. Vehicles bunch up in queues behind intersections
+    /// and thin out between them.
+    pub async fn build_traffic(mut self) -> Result<Self, GenerateError> {
+        let traffic_config = &self.config.traffic;
+        let side = TrafficSide::from_name(&traffic_config.side)
+            .ok_or_else(|| GenerateError::UnknownOption {
+                setting: String::from("traffic.side"),
+                value: traffic_config.side.clone(),
+            })?;
+
+        let weights: Vec<f64> = traffic_config.vehicles
+            .iter()
+            .map(|(_, weight)| weight.max(0.0))
+            .collect();
+        let choice_distribution = Distribution::uniform(0.0, 1.0);
+
+        let roads = &self.city.roads;
+        let mut vehicles = Vec::new();
+        for road in roads.iter() {
+            let along_x = traffic::runs_along_x(road.asphalt);
+            let crossings: Vec<Rectangle> = roads
+                .iter()
+                .filter(|other| traffic::runs_along_x(other.asphalt) != along_x)
+                .filter_map(|other| other.asphalt.intersection(&road.asphalt))
+                .collect();
+
+            let lanes: Vec<_> = traffic::lanes(road.asphalt, side)
+                .into_iter()
+                .map(|lane| {
+                    let slots = lane.slots(&crossings);
+                    (lane, slots)
+                })
+                .collect();
+
+            let slot_count: usize = lanes.iter().map(|(_, slots)| slots.len()).sum();
+            if slot_count == 0 {
+                continue;
+            }
+
+            // Whether each slot is taken, by what and in what colour
+            let samples = Self::get_sample(
+                &self.client,
+                3 * slot_count as u32,
+                &choice_distribution,
+            ).await?;
+            let mut samples = samples.chunks(3);
+
+            for (lane, slots) in lanes {
+                let mut free_from = 0.0;
+                for slot in slots.iter() {
+                    let Some(&[taken, kind, paint]) = samples.next() else {
+                        break;
+                    };
+
+                    let chance = traffic::Lane::occupancy(
+                        slot,
+                        traffic_config.density,
+                        traffic_config.queueing,
+                    );
+                    if slot.start < free_from || taken >= chance {
+                        continue;
+                    }
+
+                    // Long vehicles that don't fit before the next
+                    // intersection give way to a car
+                    let kind = traffic_config.vehicles[stats::weighted_index(&weights, kind)].0;
+                    let placed = lane
+                        .place(slot, kind, kind.paint(paint))
+                        .or_else(|| lane.place(slot, VehicleKind::Car, VehicleKind::Car.paint(paint)));
+
+                    if let Some((mut vehicle, free)) = placed {
+                        if let Some(terrain) = self.city.terrain.as_ref() {
+                            vehicle.elevation = terrain.height(vehicle.footprint.centre());
+                        }
+
+                        vehicles.push(vehicle);
+                        free_from = free;
+                    }
+                }
+            }
+        }
+
+        self.city.vehicles = vehicles;
+        Ok(self)
+    }
+
+    /// Draws the intervals between street lamps along a line of the
+    /// given length, returning where each lamp goes
+    async fn lamp_positions(
+        client: &reqwest::Client,
+        spacing: &Distribution,
+        length: f64,
+    ) -> Result<Vec<f64>, GenerateError> {
+        let count = (length / spacing.min.max(1.0)).ceil() as u32 + 1;
+        let spacings = Self::get_sample(client, count, spacing).await?;
+
+        let mut position = 0.0;
+        Ok(spacings
+            .into_iter()
+            .map(|spacing| {
+                position += spacing.max(1.0);
+                position - spacing.max(1.0) / 2.0
+            })
+            .take_while(|&position| position < length)
+            .collect())
+    }
+
+    /// Lights the city for night: street lamps along the roads,
+    /// windows and rooftop lights on the buildings at the rate of
+    /// their zone, and the headlights and taillights of the traffic.
+    /// By day nothing is lit.
+    pub async fn build_lights(mut self) -> Result<Self, GenerateError> {
+        let render_config = &self.config.render;
+        let time = TimeOfDay::from_name(&render_config.time)
+            .ok_or_else(|| GenerateError::UnknownOption {
+                setting: String::from("render.time"),
+                value: render_config.time.clone(),
+            })?;
+
+        self.city.time = time;
+        self.city.night_brightness = render_config.night_brightness;
+        if time == TimeOfDay::Day {
+            return Ok(self);
+        }
+
+        let size = self.city.size;
+        let xs = Self::lamp_positions(&self.client, &render_config.lamp_spacing, size.x).await?;
+        let ys = Self::lamp_positions(&self.client, &render_config.lamp_spacing, size.y).await?;
+        let roads: Vec<Rectangle> = self.city.roads.iter().map(|road| road.asphalt).collect();
+        let bounds = Rectangle::new(Vector2::default(), size);
+        let lamp_color = self.city.palette.color(Surface::Lamps);
+        let mut lights = night::street_lamps(bounds, &roads, &xs, &ys, render_config.lamp_radius, lamp_color);
+
+        // Each building draws how many lights it has, then whether
+        // each is on the roof, which wing it's on, and where
+        let samples_per_building = 1 + 4 * MAX_BUILDING_LIGHTS;
+        let choice_distribution = Distribution::uniform(0.0, 1.0);
+        for block in self.city.blocks.iter() {
+            let rate = render_config.windows
+                .iter()
+                .find(|(zone, _)| *zone == block.zone)
+                .map_or(0.0, |(_, rate)| *rate);
+            if rate <= 0.0 || block.buildings.is_empty() {
+                continue;
+            }
+
+            let samples = Self::get_sample(
+                &self.client,
+                (block.buildings.len() * samples_per_building) as u32,
+                &choice_distribution,
+            ).await?;
+
+            let start = block.buildings_boundary().start();
+            for (building, choices) in block.buildings.iter().zip(samples.chunks(samples_per_building)) {
+                // Every floor has its own row of windows to light
+                let floor_area = building.footprint.width() * building.footprint.height() * building.floors as f64;
+                let count = ((floor_area / 100.0 * rate + choices[0]).floor() as usize).min(MAX_BUILDING_LIGHTS);
+                let wings = building.shape.wings(building.footprint.dimensions());
+
+                for spot in choices[1..].chunks(4).take(count) {
+                    let on_roof = building.roof.shape == RoofShape::Flat && spot[0] < render_config.rooftop_share;
+                    let light = if on_roof {
+                        night::rooftop(building.main_roof().translate(start), spot[2], spot[3])
+                    } else {
+                        let index = ((spot[1] * wings.len() as f64) as usize).min(wings.len() - 1);
+                        let wing = wings[index].0.translate(building.footprint.start() + start);
+                        night::window(wing, spot[2])
+                    };
+
+                    lights.push(light);
+                }
+            }
+        }
+
+        for vehicle in self.city.vehicles.iter() {
+            lights.extend(night::vehicle_lights(vehicle));
+        }
+
+        self.city.lights = lights;
+        Ok(self)
+    }
+
+    /// Sets the season and weather the city is rendered in. These
+    /// only change how things look, never what's built.
+    pub async fn build_weather(mut self) -> Result<Self, GenerateError> {
+        let render_config = &self.config.render;
+        let season = Season::from_name(&render_config.season)
+            .ok_or_else(|| GenerateError::UnknownOption {
+                setting: String::from("render.season"),
+                value: render_config.season.clone(),
+            })?;
+        let weather = Weather::from_name(&render_config.weather)
+            .ok_or_else(|| GenerateError::UnknownOption {
+                setting: String::from("render.weather"),
+                value: render_config.weather.clone(),
+            })?;
+
+        // Offset from the textures' seed so snow doesn't settle along
+        // the grain of the surfaces beneath it
+        self.city.conditions = Conditions {
+            season,
+            weather,
+            snow_cover: render_config.snow_cover,
+            puddles: render_config.puddles,
+            noise: Noise::new(self.config.city.seed.wrapping_add(1)),
+        };
+
+        Ok(self)
+    }
+
+    /// Works out how the city spread out from its middle, so its
+    /// growth can be played back as a time-lapse
+    pub async fn build_growth(mut self) -> Result<Self, GenerateError> {
+        let growth_config = &self.config.growth;
+
+        let size = self.city.size;
+        self.city.growth = Some(Growth {
+            epochs: growth_config.epochs.max(1),
+            centre: size * 0.5,
+            reach: size.mag() / 2.0,
+            densification: growth_config.densification,
+            irregularity: growth_config.irregularity,
+            noise: Noise::new(self.config.city.seed.wrapping_add(2)),
+        });
+
         Ok(self)
     }
 
@@ -558,3 +2992,193 @@ impl Builder {
         self.city
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn block_filling_never_overlaps() {
+        let size = Vector2 { x: 120.0, y: 90.0 };
+        let (buildings, courtyard) = Builder::build_buildings_around(
+            size,
+            2,
+            true,
+            std::iter::repeat(12.0),
+            std::iter::repeat(9.0),
+            std::iter::repeat(2.0),
+            std::iter::repeat(15.0),
+        ).unwrap();
+
+        let block = Rectangle::new(Vector2::default(), size);
+        let courtyard = courtyard.unwrap();
+        assert!(buildings.len() > 8);
+        for (i, first) in buildings.iter().enumerate() {
+            let inside = first.intersection(&block).unwrap();
+            assert!((inside.dimensions() - first.dimensions()).mag() < 1e-9);
+            for second in buildings.iter().skip(i + 1) {
+                if let Some(overlap) = first.intersection(second) {
+                    assert!(overlap.width() < 1e-9 || overlap.height() < 1e-9);
+                }
+            }
+        }
+
+        // The second ring and the inner building sit in the courtyard
+        assert!(buildings.iter().filter(|building| building.intersection(&courtyard).is_some()).count() > 4);
+    }
+
+    #[test]
+    fn bent_roads_stay_out_of_water_and_blocks() {
+        let config: Config = Settings::default().try_into().unwrap();
+        let mut builder = Builder::new(config);
+        builder.config.terrain.max_bend = 20.0;
+
+        // Steep ground rising to the east, with a canal running east
+        // to west across the middle
+        let size = Vector2 { x: 200.0, y: 200.0 };
+        let ramp = image::ImageBuffer::from_fn(21, 21, |x, y| {
+            let (x, y) = (x as f64 / 20.0, y as f64 / 20.0);
+            image::Luma([((0.5 * x + 0.4 * y * y) * u16::MAX as f64) as u16])
+        });
+        let mut heightmap = Vec::new();
+        image::DynamicImage::ImageLuma16(ramp)
+            .write_to(&mut std::io::Cursor::new(&mut heightmap), image::ImageOutputFormat::Png)
+            .unwrap();
+        builder.city.terrain = Some(Terrain::from_bytes(&heightmap, size, 60.0).unwrap());
+        builder.city.waterways.push(Waterway {
+            channel: vec![Rectangle::new(Vector2 { x: 0.0, y: 95.0 }, Vector2 { x: 200.0, y: 105.0 })],
+            quays: Vec::new(),
+            quay_breadth: 4.0,
+            flows_along_x: true,
+            bridged: true,
+        });
+
+        let roads = vec![
+            Rectangle::new(Vector2 { x: 45.0, y: 0.0 }, Vector2 { x: 55.0, y: 200.0 }),
+            Rectangle::new(Vector2 { x: 145.0, y: 0.0 }, Vector2 { x: 155.0, y: 200.0 }),
+        ];
+        let blocks = vec![
+            Rectangle::new(Vector2 { x: 55.0, y: 0.0 }, Vector2 { x: 145.0, y: 200.0 }),
+            Rectangle::new(Vector2 { x: 0.0, y: 0.0 }, Vector2 { x: 45.0, y: 200.0 }),
+        ];
+
+        let (roads, blocks) = builder.lay_grid(roads, blocks);
+        assert!(roads.iter().any(|road| ![45.0, 145.0].contains(&road.start().x)));
+        let waterway = &builder.city.waterways[0];
+        for road in roads.iter() {
+            assert!(waterway.channel.iter().all(|water| road.intersection(water).is_none()));
+            assert!(blocks.iter().all(|block| road.intersection(block).is_none()));
+        }
+    }
+
+    /// A plain block over a rectangle, for tests that only care where
+    /// blocks are
+    fn block(start: (f64, f64), end: (f64, f64)) -> Block {
+        Block {
+            footprint: Rectangle::new(Vector2 { x: start.0, y: start.1 }, Vector2 { x: end.0, y: end.1 }),
+            buildings: Vec::new(),
+            sidewalk_breadth: 2.0,
+            zone: Zone::Residential,
+            courtyards: Vec::new(),
+            parcels: Vec::new(),
+            landmark: None,
+            fillers: Vec::new(),
+            suburban: false,
+        }
+    }
+
+    fn corners(rectangle: &Rectangle) -> (f64, f64, f64, f64) {
+        (rectangle.start().x, rectangle.start().y, rectangle.end().x, rectangle.end().y)
+    }
+
+    #[test]
+    fn superblocks_only_merge_across_narrow_dry_roads() {
+        let config: Config = Settings::default().try_into().unwrap();
+        let mut builder = Builder::new(config);
+
+        // A 10m road east of the first block, a 40m avenue south of
+        // it, and a 10m road crossed by a canal south of the second
+        builder.city.blocks = vec![
+            block((0.0, 0.0), (90.0, 80.0)),
+            block((100.0, 0.0), (190.0, 80.0)),
+            block((0.0, 120.0), (90.0, 200.0)),
+            block((100.0, 90.0), (190.0, 170.0)),
+        ];
+        builder.city.waterways.push(Waterway {
+            channel: vec![Rectangle::new(Vector2 { x: 100.0, y: 82.0 }, Vector2 { x: 190.0, y: 88.0 })],
+            quays: Vec::new(),
+            quay_breadth: 1.0,
+            flows_along_x: true,
+            bridged: false,
+        });
+
+        let superblocks = builder.superblocks(30.0);
+        assert_eq!(superblocks.len(), 1);
+        let (first, second, merged, gap) = superblocks[0];
+        assert_eq!((first, second), (0, 1));
+        assert_eq!(corners(&merged), (0.0, 0.0, 190.0, 80.0));
+        assert_eq!(corners(&gap), (90.0, 0.0, 100.0, 80.0));
+
+        // The avenue is within a wider gap
+        assert!(builder.superblocks(45.0).iter().any(|&(first, second, ..)| (first, second) == (0, 2)));
+    }
+
+    #[test]
+    fn superblocks_never_merge_across_a_railway() {
+        let config: Config = Settings::default().try_into().unwrap();
+        let mut builder = Builder::new(config);
+
+        // A two track line running east cuts a block in two, leaving
+        // pieces close enough to merge were it not for the tracks
+        let (slices, _) = rail::corridor(200.0, 100.0, (0.0, 800.0, 0.0), 2, &[]);
+        let mut railway = Railway {
+            bed: Waterway {
+                channel: slices,
+                quays: Vec::new(),
+                quay_breadth: 3.0,
+                flows_along_x: true,
+                bridged: true,
+            },
+            tracks: 2,
+            crossings: Vec::new(),
+            stations: Vec::new(),
+            bridges: Vec::new(),
+        };
+
+        let (pieces, verges) = railway.bed.clip_block(&Rectangle::new(
+            Vector2 { x: 20.0, y: 40.0 },
+            Vector2 { x: 180.0, y: 160.0 },
+        ));
+        assert_eq!(pieces.len(), 2);
+        railway.bed.quays.extend(verges);
+
+        builder.city.blocks = pieces.into_iter().map(|piece| block(
+            (piece.start().x, piece.start().y),
+            (piece.end().x, piece.end().y),
+        )).collect();
+        assert!(!builder.superblocks(30.0).is_empty());
+
+        builder.city.railways.push(railway);
+        assert!(builder.superblocks(30.0).is_empty());
+    }
+
+    #[test]
+    fn merging_cuts_the_road_between_the_blocks() {
+        let road = |start: (f64, f64), end: (f64, f64)| Road {
+            asphalt: Rectangle::new(Vector2 { x: start.0, y: start.1 }, Vector2 { x: end.0, y: end.1 }),
+        };
+
+        // A road running south between the blocks and on past them,
+        // and the street along their south side
+        let mut roads = vec![
+            road((90.0, 0.0), (100.0, 200.0)),
+            road((0.0, 80.0), (190.0, 90.0)),
+        ];
+        let gap = Rectangle::new(Vector2 { x: 90.0, y: 0.0 }, Vector2 { x: 100.0, y: 80.0 });
+        Builder::cut_roads(&mut roads, gap);
+
+        let asphalt: Vec<_> = roads.iter().map(|road| corners(&road.asphalt)).collect();
+        assert_eq!(asphalt, vec![(90.0, 80.0, 100.0, 200.0), (0.0, 80.0, 190.0, 90.0)]);
+        assert!(roads.iter().all(|road| road.asphalt.intersection(&gap).is_none()));
+    }
+}
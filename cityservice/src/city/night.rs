@@ -0,0 +1,274 @@
+use super::channels::Canvas;
+use super::geom::*;
+use super::objects::Renderable;
+use super::traffic::Vehicle;
+
+/// The warm light spilling out of windows, and the paler light of
+/// lamps on roofs
+const WINDOW_COLOR: [u8; 3] = [0xFF, 0xC4, 0x78];
+const ROOFTOP_COLOR: [u8; 3] = [0xFF, 0xE4, 0xB4];
+
+const HEADLIGHT_COLOR: [u8; 3] = [0xFF, 0xF4, 0xDC];
+const TAILLIGHT_COLOR: [u8; 3] = [0xFF, 0x28, 0x18];
+
+/// How far the beam of a headlight reaches ahead of a vehicle, and
+/// how broad it is, in meters
+const BEAM_LENGTH: f64 = 9.0;
+const BEAM_BREADTH: f64 = 1.0;
+
+/// How far the glow of a taillight trails behind a vehicle
+const TAIL_LENGTH: f64 = 1.6;
+
+/// The radius of the glow of a window, and of a light on a roof
+const WINDOW_RADIUS: f64 = 1.8;
+const ROOFTOP_RADIUS: f64 = 1.0;
+
+/// Night doesn't just darken things, it shifts them towards blue
+pub const NIGHT_TINT: [f64; 3] = [0.75, 0.85, 1.3];
+
+/// When the city is rendered
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeOfDay {
+    Day,
+    /// Everything is dark but for the lights
+    Night,
+}
+
+impl TimeOfDay {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "day" => Some(TimeOfDay::Day),
+            "night" => Some(TimeOfDay::Night),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TimeOfDay::Day => "day",
+            TimeOfDay::Night => "night",
+        }
+    }
+}
+
+/// A glow of light, only seen at night
+#[derive(Clone, Copy, Debug)]
+pub struct Light {
+    pub centre: Vector2,
+    /// The glow is an ellipse, these are its radii along each axis
+    pub radii: Vector2,
+    pub color: [u8; 3],
+    /// How bright the middle of the glow is, in [0, 1]
+    pub strength: f64,
+}
+
+impl Renderable for Light {
+    fn render(&self, offset: Vector2, scale: Vector2, canvas: &mut Canvas) {
+        let centre = (self.centre + offset) * scale;
+        canvas.glow(centre, self.radii * scale, self.color, self.strength);
+    }
+}
+
+/// Lights up a street lamp, with a bright spot for the lamp itself
+/// over the pool of light it casts
+fn lamp(centre: Vector2, radius: f64, color: [u8; 3]) -> [Light; 2] {
+    [
+        Light {
+            centre,
+            radii: Vector2 { x: radius, y: radius },
+            color,
+            strength: 0.6,
+        },
+        Light {
+            centre,
+            radii: Vector2 { x: 0.8, y: 0.8 },
+            color,
+            strength: 1.0,
+        },
+    ]
+}
+
+/// Lines the curbs of roads with street lamps. Curbs running along x
+/// get lamps at the positions along x, and those along y at the
+/// positions along y, with the lamps on the far side of a road
+/// standing halfway between those on the near side. Curbs that face
+/// onto another road, where roads meet or are laid in slices, are
+/// left unlit. The lamps glow in the given colour.
+pub fn street_lamps(
+    bounds: Rectangle,
+    roads: &[Rectangle],
+    xs: &[f64],
+    ys: &[f64],
+    radius: f64,
+    color: [u8; 3],
+) -> Vec<Light> {
+    let midpoints = |positions: &[f64]| -> Vec<f64> {
+        positions.windows(2).map(|pair| (pair[0] + pair[1]) / 2.0).collect()
+    };
+    let (x_midpoints, y_midpoints) = (midpoints(xs), midpoints(ys));
+    let on_road = |point: Vector2| roads.iter().any(|road| road.contains(point));
+
+    let mut lamps = Vec::new();
+    for road in roads {
+        let (start, end) = (road.start(), road.end());
+        let curbs = [
+            (xs, start.y, -1.0, true),
+            (&x_midpoints[..], end.y, 1.0, true),
+            (ys, start.x, -1.0, false),
+            (&y_midpoints[..], end.x, 1.0, false),
+        ];
+
+        for (positions, curb, outwards, along_x) in curbs {
+            let (from, to) = if along_x { (start.x, end.x) } else { (start.y, end.y) };
+            for &position in positions.iter().filter(|&&position| position >= from && position < to) {
+                let point = if along_x {
+                    Vector2 { x: position, y: curb }
+                } else {
+                    Vector2 { x: curb, y: position }
+                };
+
+                let step = if along_x {
+                    Vector2 { x: 0.0, y: outwards * 0.5 }
+                } else {
+                    Vector2 { x: outwards * 0.5, y: 0.0 }
+                };
+
+                if bounds.contains(point + step) && !on_road(point + step) {
+                    lamps.extend(lamp(point, radius, color));
+                }
+            }
+        }
+    }
+
+    lamps
+}
+
+/// The light from a window, somewhere around the edge of a wing given
+/// by a share of the way round it
+pub fn window(wing: Rectangle, share: f64) -> Light {
+    let (width, height) = (wing.width(), wing.height());
+    let along = share.clamp(0.0, 1.0) * 2.0 * (width + height);
+    let (start, end) = (wing.start(), wing.end());
+
+    // Round the top, down the right, back along the bottom and up
+    // the left
+    let centre = if along < width {
+        Vector2 { x: start.x + along, y: start.y }
+    } else if along < width + height {
+        Vector2 { x: end.x, y: start.y + along - width }
+    } else if along < 2.0 * width + height {
+        Vector2 { x: end.x - (along - width - height), y: end.y }
+    } else {
+        Vector2 { x: start.x, y: end.y - (along - 2.0 * width - height) }
+    };
+
+    Light {
+        centre,
+        radii: Vector2 { x: WINDOW_RADIUS, y: WINDOW_RADIUS },
+        color: WINDOW_COLOR,
+        strength: 0.7,
+    }
+}
+
+/// A light on a roof, at the shares across and down it
+pub fn rooftop(roof: Rectangle, across: f64, down: f64) -> Light {
+    Light {
+        centre: roof.start() + Vector2 {
+            x: across.clamp(0.0, 1.0) * roof.width(),
+            y: down.clamp(0.0, 1.0) * roof.height(),
+        },
+        radii: Vector2 { x: ROOFTOP_RADIUS, y: ROOFTOP_RADIUS },
+        color: ROOFTOP_COLOR,
+        strength: 0.9,
+    }
+}
+
+/// The beams of a vehicle's headlights streaking out ahead of it, and
+/// the red glow of its taillights behind
+pub fn vehicle_lights(vehicle: &Vehicle) -> Vec<Light> {
+    let heading = vehicle.heading;
+    let footprint = vehicle.footprint;
+    let along_x = heading.x != 0.0;
+    let (length, width) = if along_x {
+        (footprint.width(), footprint.height())
+    } else {
+        (footprint.height(), footprint.width())
+    };
+
+    // Across the vehicle, a quarter of its width either side of the
+    // middle
+    let across = if along_x { Vector2 { x: 0.0, y: 1.0 } } else { Vector2 { x: 1.0, y: 0.0 } };
+    let beam_radii = |reach: f64| if along_x {
+        Vector2 { x: reach, y: BEAM_BREADTH / 2.0 }
+    } else {
+        Vector2 { x: BEAM_BREADTH / 2.0, y: reach }
+    };
+
+    let mut lights = Vec::new();
+    for side in [-0.25, 0.25] {
+        let corner = footprint.centre() + across * (side * width);
+        lights.push(Light {
+            centre: corner + heading * (length / 2.0 + BEAM_LENGTH / 2.0),
+            radii: beam_radii(BEAM_LENGTH / 2.0),
+            color: HEADLIGHT_COLOR,
+            strength: 0.8,
+        });
+        lights.push(Light {
+            centre: corner - heading * (length / 2.0),
+            radii: beam_radii(TAIL_LENGTH / 2.0),
+            color: TAILLIGHT_COLOR,
+            strength: 0.9,
+        });
+    }
+
+    lights
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::traffic::VehicleKind;
+    use super::super::palette::Surface;
+
+    #[test]
+    fn lamps_stand_on_outside_curbs_only() {
+        let bounds = Rectangle::new(Vector2 { x: 0.0, y: 0.0 }, Vector2 { x: 200.0, y: 200.0 });
+        let roads = [
+            Rectangle::new(Vector2 { x: 0.0, y: 95.0 }, Vector2 { x: 200.0, y: 105.0 }),
+            Rectangle::new(Vector2 { x: 95.0, y: 0.0 }, Vector2 { x: 105.0, y: 200.0 }),
+        ];
+        let positions: Vec<f64> = (0..8).map(|i| 10.0 + i as f64 * 25.0).collect();
+        let lamps = street_lamps(bounds, &roads, &positions, &positions, 6.0, [0xFF; 3]);
+
+        assert!(!lamps.is_empty());
+        for lamp in lamps.iter() {
+            let on_curb = roads.iter().any(|road| {
+                let (start, end) = (road.start(), road.end());
+                [start.x, end.x].contains(&lamp.centre.x) || [start.y, end.y].contains(&lamp.centre.y)
+            });
+            assert!(on_curb);
+
+            // Nothing in the middle of the intersection
+            let middle = Rectangle::new(Vector2 { x: 95.5, y: 95.5 }, Vector2 { x: 104.5, y: 104.5 });
+            assert!(!middle.contains(lamp.centre));
+        }
+    }
+
+    #[test]
+    fn headlights_lead_and_taillights_trail() {
+        let vehicle = Vehicle::new(
+            VehicleKind::Car,
+            Vector2 { x: 50.0, y: 20.0 },
+            Vector2 { x: -1.0, y: 0.0 },
+            Surface::CarWhite,
+        );
+
+        for light in vehicle_lights(&vehicle) {
+            if light.color == HEADLIGHT_COLOR {
+                assert!(light.centre.x < vehicle.footprint.start().x);
+            } else {
+                assert!(light.centre.x >= vehicle.footprint.end().x);
+            }
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use super::geom::Vector2;
+
+/// Deterministic value noise. The same seed always gives the same
+/// field, so anything built from it lines up between renders.
+#[derive(Clone, Copy, Debug)]
+pub struct Noise {
+    seed: u32,
+}
+
+impl Noise {
+    pub fn new(seed: u32) -> Self {
+        Self { seed }
+    }
+
+    /// Hashes a lattice point to a value in [0, 1)
+    fn lattice(&self, x: i64, y: i64) -> f64 {
+        // This is a small integer hash in the style of the ones
+        // commonly used for shader noise, it doesn't need to be
+        // cryptographically strong, just well mixed.
+        let mut hash = (x as u64)
+            .wrapping_mul(0x9E37_79B9_7F4A_7C15)
+            ^ (y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+            ^ (self.seed as u64).wrapping_mul(0x1656_67B1_9E37_79F9);
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(0xFF51_AFD7_ED55_8CCD);
+        hash ^= hash >> 33;
+
+        (hash >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Smoothly interpolated noise in [-1, 1] with features roughly
+    /// one unit apart.
+    pub fn value(&self, point: Vector2) -> f64 {
+        let x0 = point.x.floor();
+        let y0 = point.y.floor();
+        let (ix, iy) = (x0 as i64, y0 as i64);
+
+        // Smoothstep the fractional parts so the lattice doesn't show
+        let fade = |t: f64| t * t * (3.0 - 2.0 * t);
+        let tx = fade(point.x - x0);
+        let ty = fade(point.y - y0);
+
+        let top = self.lattice(ix, iy) * (1.0 - tx) + self.lattice(ix + 1, iy) * tx;
+        let bottom = self.lattice(ix, iy + 1) * (1.0 - tx) + self.lattice(ix + 1, iy + 1) * tx;
+
+        (top * (1.0 - ty) + bottom * ty) * 2.0 - 1.0
+    }
+
+    /// Sums several octaves of noise, each at twice the frequency and
+    /// half the amplitude of the last. The result stays in [-1, 1].
+    pub fn fractal(&self, point: Vector2, octaves: u32) -> f64 {
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        let mut normalization = 0.0;
+        for octave in 0..octaves {
+            // Offset each octave so they don't all share a lattice point
+            let shift = Vector2 { x: 17.0 * octave as f64, y: 31.0 * octave as f64 };
+            total += amplitude * self.value(point * frequency + shift);
+            normalization += amplitude;
+            amplitude /= 2.0;
+            frequency *= 2.0;
+        }
+
+        if normalization > 0.0 {
+            total / normalization
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn deterministic() {
+        let point = Vector2 { x: 3.7, y: -12.2 };
+        assert_eq!(Noise::new(4).fractal(point, 4), Noise::new(4).fractal(point, 4));
+    }
+
+    #[test]
+    fn in_range() {
+        let noise = Noise::new(11);
+        for i in 0..1000 {
+            let point = Vector2 { x: i as f64 * 0.37, y: i as f64 * -0.11 };
+            let value = noise.fractal(point, 3);
+            assert!((-1.0..=1.0).contains(&value));
+        }
+    }
+}
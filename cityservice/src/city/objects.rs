@@ -1,16 +1,57 @@
-use image::GenericImageView;
-use image::GenericImage;
-
+use super::channels::{Canvas, Label, Material};
+use super::filler::Filler;
+use super::footprint::{Footprint, FootprintShape};
 use super::geom::*;
+use super::landmark::Landmark;
+use super::palette::Surface;
+use super::parcel::Parcel;
+use super::roof::{self, Roof, RoofShape};
+use super::rooftop::Equipment;
+use super::texture::Finish;
+use super::zone::Zone;
+
+/// How much darker ridge and hip lines are than the roof around them
+const RIDGE_SHADE: f64 = 0.6;
+
+/// The directions looked in for the edge of a shaped roof, a pixel
+/// is on the parapet if the roof drops away in any of them
+const EDGE_DIRECTIONS: [Vector2; 8] = [
+    Vector2 { x: 1.0, y: 0.0 },
+    Vector2 { x: -1.0, y: 0.0 },
+    Vector2 { x: 0.0, y: 1.0 },
+    Vector2 { x: 0.0, y: -1.0 },
+    Vector2 { x: 1.0, y: 1.0 },
+    Vector2 { x: 1.0, y: -1.0 },
+    Vector2 { x: -1.0, y: 1.0 },
+    Vector2 { x: -1.0, y: -1.0 },
+];
 
 pub trait Renderable {
-    fn render(&self, offset: Vector2, scale: Vector2, image: &mut image::DynamicImage);
+    /// Draws the object into every channel of the canvas at once:
+    /// its colour, what it's made of, and how far it stands above
+    /// the ground.
+    fn render(&self, offset: Vector2, scale: Vector2, canvas: &mut Canvas);
 }
 
+#[derive(Clone)]
 pub struct Block {
     pub footprint: Rectangle,
     pub buildings: Vec<Building>,
     pub sidewalk_breadth: f64,
+    pub zone: Zone,
+    /// The open ground left inside the buildings, stored relative to
+    /// the buildings boundary like the buildings are
+    pub courtyards: Vec<Courtyard>,
+    /// The lots the block is cut into, if it's subdivided, stored
+    /// relative to the buildings boundary
+    pub parcels: Vec<Parcel>,
+    /// A landmark taking up the whole block, in place of buildings
+    pub landmark: Option<Landmark>,
+    /// Whatever fills the block, or some of its lots, instead of
+    /// buildings, stored relative to the buildings boundary
+    pub fillers: Vec<Filler>,
+    /// Whether the block is a single house lot in the suburbs
+    pub suburban: bool,
 }
 
 impl Block {
@@ -20,78 +61,570 @@ impl Block {
 }
 
 impl Renderable for Block {
-    fn render(&self, offset: Vector2, scale: Vector2, image: &mut image::DynamicImage) {
+    fn render(&self, offset: Vector2, scale: Vector2, canvas: &mut Canvas) {
         let region = self
             .footprint
             .translate(offset)
-            .scale(scale)
-            .interior_int_coords();
-        for pixel in region {
-            let concrete_color = image::Rgba::from([0xA0, 0xA0, 0xA0, 0xFF]);
-            image.put_pixel(pixel.x, pixel.y, concrete_color);
-        }
+            .scale(scale);
+        let (ground_color, ground_material) = if self.zone == Zone::Park {
+            (canvas.palette.rgba(Surface::Grass), Material::Grass)
+        } else {
+            (canvas.palette.rgba(Surface::Pavement), Material::Concrete)
+        };
+
+        canvas.paint(region, ground_color, ground_material);
 
         let buildings_offset = offset + self.buildings_boundary().start();
 
+        for courtyard in self.courtyards.iter() {
+            courtyard.render(buildings_offset, scale, canvas);
+        }
+
+        for parcel in self.parcels.iter() {
+            parcel.render(buildings_offset, scale, canvas);
+        }
+
+        for filler in self.fillers.iter() {
+            filler.render(buildings_offset, scale, canvas);
+        }
+
         for building in self.buildings.iter() {
-            building.render(buildings_offset, scale, image);
+            building.render(buildings_offset, scale, canvas);
+        }
+
+        if let Some(landmark) = &self.landmark {
+            landmark.render(buildings_offset, scale, canvas);
+        }
+    }
+}
+
+/// What the open ground inside a block is laid out as
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CourtyardKind {
+    /// Lawns and planting, the backyards of houses
+    Garden,
+    /// Loading yards and car parks
+    Paved,
+}
+
+impl CourtyardKind {
+    /// Reads a kind from its name in cityscript
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "garden" => Some(CourtyardKind::Garden),
+            "paved" => Some(CourtyardKind::Paved),
+            _ => None,
         }
     }
 }
 
+/// The open ground inside a ring of buildings
+#[derive(Clone, Debug)]
+pub struct Courtyard {
+    pub footprint: Rectangle,
+    pub kind: CourtyardKind,
+}
+
+impl Renderable for Courtyard {
+    fn render(&self, offset: Vector2, scale: Vector2, canvas: &mut Canvas) {
+        let region = self
+            .footprint
+            .translate(offset)
+            .scale(scale);
+
+        let (color, material) = match self.kind {
+            CourtyardKind::Garden => (canvas.palette.rgba(Surface::Garden), Material::Grass),
+            CourtyardKind::Paved => (canvas.palette.rgba(Surface::Paving), Material::Concrete),
+        };
+
+        canvas.paint(region, color, material);
+    }
+}
+
 #[derive(Debug)]
 pub struct Road {
     pub asphalt: Rectangle,
 }
 
 impl Renderable for Road {
-    fn render(&self, offset: Vector2, scale: Vector2, image: &mut image::DynamicImage) {
+    fn render(&self, offset: Vector2, scale: Vector2, canvas: &mut Canvas) {
         let region = self
             .asphalt
             .translate(offset)
-            .scale(scale)
-            .interior_int_coords();
+            .scale(scale);
         
-        for pixel in region {
-            let asphalt_color = image::Rgba::from([0x20, 0x20, 0x20, 0xFF]);
-            image.put_pixel(pixel.x, pixel.y, asphalt_color);
-        }
+        let asphalt_color = canvas.palette.rgba(Surface::Asphalt);
+        canvas.paint(region, asphalt_color, Material::Asphalt);
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Building {
+    /// The lot the building fills, its wings all lie within this
     pub footprint: Rectangle,
+    /// The outline of the building within its footprint
+    pub shape: Footprint,
     pub roof_edge_breadth: f64,
+    pub floors: u32,
+    /// The height from the ground to the roof
     pub height: f64,
+    /// The height of the ground the building stands on
+    pub elevation: f64,
     pub roof_color: [u8; 4],
+    pub roof: Roof,
+    /// What the roof is covered in
+    pub finish: Finish,
+    /// What stands on the roof, if it's flat
+    pub equipment: Vec<Equipment>,
+}
+
+impl Building {
+    /// The flat part of the roof of the tallest and largest wing,
+    /// inside the parapet, in the same frame as the footprint
+    pub fn main_roof(&self) -> Rectangle {
+        let area = |wing: &Rectangle| wing.width() * wing.height();
+        let (wing, _) = self.shape
+            .wings(self.footprint.dimensions())
+            .into_iter()
+            .reduce(|best, wing| {
+                if (wing.1, area(&wing.0)) > (best.1, area(&best.0)) { wing } else { best }
+            })
+            .unwrap_or_else(|| unreachable!("Every footprint has a wing"));
+
+        wing.translate(self.footprint.start()).inset(self.roof_edge_breadth)
+    }
+
+    /// The colour of a point (in meters) on a flat roof, patterned by
+    /// its finish. Courses and seams run along the roof's length.
+    fn finished(&self, canvas: &Canvas, color: [u8; 4], point: Vector2) -> image::Rgba<u8> {
+        let along_x = self.footprint.width() >= self.footprint.height();
+        let shade = canvas.texture.finish(self.finish, point, along_x);
+        let [red, green, blue, alpha] = color;
+        let [red, green, blue] = [red, green, blue]
+            .map(|channel| (channel as f64 * shade).round().clamp(0.0, u8::MAX as f64) as u8);
+        image::Rgba::from([red, green, blue, alpha])
+    }
+
+    /// Paints a flat roof over a shaped footprint pixel by pixel, the
+    /// parapet follows the outline of the wings, and runs around the
+    /// edge of each tier of a stepped tower
+    fn render_shaped_flat(&self, offset: Vector2, scale: Vector2, canvas: &mut Canvas) {
+        let bounds = self.footprint.translate(offset);
+        let wings = self.shape.wings(self.footprint.dimensions());
+        let roof_edge_color = canvas.palette.rgba(Surface::RoofEdge);
+        let roof_color = canvas.texture.vary(self.roof_color, bounds.centre());
+
+        for pixel in bounds.scale(scale).interior_int_coords() {
+            let point = Vector2 {
+                x: (pixel.x as f64 + 0.5) / scale.x,
+                y: (pixel.y as f64 + 0.5) / scale.y,
+            } - bounds.start();
+
+            let tier = match Footprint::tier_at(&wings, point) {
+                Some(tier) => tier,
+                None => continue,
+            };
+
+            let on_edge = EDGE_DIRECTIONS.iter().any(|&direction| {
+                let beyond = point + direction * self.roof_edge_breadth;
+                Footprint::tier_at(&wings, beyond).is_none_or(|other| other < tier)
+            });
+
+            if on_edge {
+                canvas.paint_pixel(pixel.x, pixel.y, roof_edge_color, Material::Concrete);
+                canvas.label_pixel(pixel.x, pixel.y, Label::Building);
+            } else {
+                let color = self.finished(canvas, roof_color, point + bounds.start());
+                canvas.paint_pixel(pixel.x, pixel.y, color, Material::Roof);
+            }
+
+            canvas.heights.raise(pixel.x, pixel.y, self.elevation + self.height * tier);
+        }
+    }
 }
 
 impl Renderable for Building {
-    fn render(&self, offset: Vector2, scale: Vector2, image: &mut image::DynamicImage) {
+    fn render(&self, offset: Vector2, scale: Vector2, canvas: &mut Canvas) {
         let roof_with_edge = self
             .footprint
             .translate(offset)
             .scale(scale);
 
-        let roof = self.footprint.translate(offset)
-            .inset(self.roof_edge_breadth)
-            .scale(scale);
+        let eaves = self.elevation + self.height;
+        let footprint_centre = self.footprint.translate(offset).centre();
+
+        if self.roof.shape == RoofShape::Flat {
+            if self.shape.shape == FootprintShape::Box {
+                let roof = self.footprint.translate(offset)
+                    .inset(self.roof_edge_breadth)
+                    .scale(scale);
+
+                // The edge is a concrete parapet around the roof proper
+                let roof_edge_color = canvas.palette.rgba(Surface::RoofEdge);
+                canvas.paint(roof_with_edge, roof_edge_color, Material::Concrete);
+
+                let roof_color = canvas.texture.vary(self.roof_color, footprint_centre);
+                for pixel in roof.interior_int_coords() {
+                    let point = Vector2 {
+                        x: (pixel.x as f64 + 0.5) / scale.x,
+                        y: (pixel.y as f64 + 0.5) / scale.y,
+                    };
+                    let color = self.finished(canvas, roof_color, point);
+                    canvas.paint_pixel(pixel.x, pixel.y, color, Material::Roof);
+                }
+                canvas.label(roof_with_edge, Label::Building);
+                canvas.raise(roof_with_edge, eaves);
+            } else {
+                self.render_shaped_flat(offset, scale, canvas);
+            }
+
+            for equipment in self.equipment.iter() {
+                equipment.render(offset, scale, canvas);
+            }
+            return;
+        }
+
+        // Pitched roofs are worked out pixel by pixel, each face is
+        // shaded by how squarely it faces the sun. Each wing has a
+        // roof of its own, and where wings meet the higher roof wins,
+        // which leaves a valley between them.
+        let start = self.footprint.translate(offset).start();
+        let wings = self.shape.wings(self.footprint.dimensions());
+        let local = |x: f64, y: f64| Vector2 {
+            x: (x + 0.5) / scale.x,
+            y: (y + 0.5) / scale.y,
+        } - start;
+
+        let surface = |point: Vector2| {
+            wings
+                .iter()
+                .enumerate()
+                .filter(|(_, (wing, _))| wing.contains(point))
+                .map(|(index, (wing, _))| {
+                    let (rise, face) = self.roof.surface(wing.dimensions(), point - wing.start());
+                    // Every face of every wing is numbered apart
+                    (rise, index * roof::FACES + face)
+                })
+                .reduce(|best, other| if other.0 > best.0 { other } else { best })
+        };
+
+        let roof_color = canvas.texture.vary(self.roof_color, footprint_centre);
+
+        // Far enough to measure the slope without crossing a ridge
+        let step = 0.01;
+        let dx = Vector2 { x: step, y: 0.0 };
+        let dy = Vector2 { x: 0.0, y: step };
 
-        
         for pixel in roof_with_edge.interior_int_coords() {
-            let roof_edge_color = image::Rgba::from([0x50, 0x50, 0x50, 0xFF]);
-            if pixel.y < image.dimensions().1 && pixel.x < image.dimensions().0 {
-                image.put_pixel(pixel.x, pixel.y, roof_edge_color);
+            let (x, y) = (pixel.x as f64, pixel.y as f64);
+            let point = local(x, y);
+            let (rise, face) = match surface(point) {
+                Some(surface) => surface,
+                None => continue,
+            };
+
+            // Off the edge of the wing the roof is taken as level
+            let rise_at = |point: Vector2| surface(point).map_or(rise, |(rise, _)| rise);
+            let gradient = Vector2 {
+                x: (rise_at(point + dx) - rise_at(point - dx)) / (2.0 * step),
+                y: (rise_at(point + dy) - rise_at(point - dy)) / (2.0 * step),
+            };
+
+            let on_ridge = [local(x + 1.0, y), local(x, y + 1.0)]
+                .into_iter()
+                .filter_map(surface)
+                .any(|(_, neighbour_face)| neighbour_face != face);
+
+            // Courses of tiles and seams run across the slope
+            let along_x = gradient.x.abs() > gradient.y.abs();
+            let mut shade = canvas.sun.shade(gradient)
+                * canvas.texture.finish(self.finish, point + start, along_x);
+            if on_ridge {
+                shade *= RIDGE_SHADE;
             }
+
+            let [red, green, blue, alpha] = roof_color;
+            let lit = |channel: u8| (channel as f64 * shade).round().min(u8::MAX as f64) as u8;
+            let color = image::Rgba::from([lit(red), lit(green), lit(blue), alpha]);
+
+            canvas.paint_pixel(pixel.x, pixel.y, color, Material::Roof);
+            canvas.heights.raise(pixel.x, pixel.y, eaves + rise);
         }
+    }
+}
+
+/// A river, canal or stretch of sea. The channel is stored as a run
+/// of thin slices laid end to end along the direction of flow (or
+/// along the shore), this lets a meandering river be described with
+/// the same axis aligned rectangles as everything else.
+#[derive(Debug)]
+pub struct Waterway {
+    pub channel: Vec<Rectangle>,
+    pub quays: Vec<Rectangle>,
+    pub quay_breadth: f64,
+    pub flows_along_x: bool,
+    /// Whether roads cross this water on bridges, or just stop at
+    /// the bank (as they do at the coast)
+    pub bridged: bool,
+}
+
+impl Waterway {
+    /// Moves the waterway into a frame where it always flows along
+    /// x, the clipping code below only has to handle that case.
+    fn to_flow_frame(&self, rectangle: &Rectangle) -> Rectangle {
+        if self.flows_along_x {
+            *rectangle
+        } else {
+            rectangle.transpose()
+        }
+    }
+
+    /// The channel slices widened by the quays on either bank, in the
+    /// flow frame.
+    fn embanked_channel(&self) -> impl Iterator<Item = Rectangle> + '_ {
+        self.channel.iter().map(|slice| {
+            let slice = self.to_flow_frame(slice);
+            Rectangle::new(
+                slice.start() - Vector2 { x: 0.0, y: self.quay_breadth },
+                slice.end() + Vector2 { x: 0.0, y: self.quay_breadth },
+            )
+        })
+    }
 
-        for pixel in roof.interior_int_coords() {
-            let roof_color = image::Rgba::from(self.roof_color);
-            if pixel.y < image.dimensions().1 && pixel.x < image.dimensions().0 {
-                image.put_pixel(pixel.x, pixel.y, roof_color);
+    /// Whether a rectangle stays clear of the water and its quays
+    pub fn is_clear_of(&self, rectangle: &Rectangle) -> bool {
+        let frame = self.to_flow_frame(rectangle);
+        self.embanked_channel().all(|embanked| embanked.intersection(&frame).is_none())
+    }
+
+    /// Clips a block so that nothing is built in (or right next to)
+    /// the water. This returns the dry pieces of the block, and the
+    /// quays filling the space between those pieces and the water.
+    pub fn clip_block(&self, block: &Rectangle) -> (Vec<Rectangle>, Vec<Rectangle>) {
+        let block_frame = self.to_flow_frame(block);
+        let overlapping: Vec<(Rectangle, Rectangle)> = self
+            .embanked_channel()
+            .zip(self.channel.iter().map(|slice| self.to_flow_frame(slice)))
+            .filter(|(embanked, _)| embanked.intersection(&block_frame).is_some())
+            .collect();
+
+        if overlapping.is_empty() {
+            return (vec![*block], Vec::new());
+        }
+
+        let north_edge = overlapping
+            .iter()
+            .map(|(embanked, _)| embanked.start().y)
+            .fold(f64::INFINITY, f64::min)
+            .max(block_frame.start().y);
+
+        let south_edge = overlapping
+            .iter()
+            .map(|(embanked, _)| embanked.end().y)
+            .fold(f64::NEG_INFINITY, f64::max)
+            .min(block_frame.end().y);
+
+        let mut pieces = Vec::new();
+        if north_edge > block_frame.start().y {
+            pieces.push(Rectangle::new(
+                block_frame.start(),
+                Vector2 { x: block_frame.end().x, y: north_edge },
+            ));
+        }
+
+        if south_edge < block_frame.end().y {
+            pieces.push(Rectangle::new(
+                Vector2 { x: block_frame.start().x, y: south_edge },
+                block_frame.end(),
+            ));
+        }
+
+        // Fill everything between the dry pieces and the water with
+        // stone, slice by slice.
+        let mut quays = Vec::new();
+        for (_, slice) in overlapping {
+            let north_quay = Rectangle::new(
+                Vector2 { x: slice.start().x, y: north_edge },
+                Vector2 { x: slice.end().x, y: slice.start().y },
+            );
+
+            let south_quay = Rectangle::new(
+                Vector2 { x: slice.start().x, y: slice.end().y },
+                Vector2 { x: slice.end().x, y: south_edge },
+            );
+
+            // The quays shouldn't cross each other if the water
+            // doesn't reach all the way across this part of the block
+            if slice.start().y > north_edge {
+                quays.extend(north_quay.intersection(&block_frame));
+            }
+
+            if slice.end().y < south_edge {
+                quays.extend(south_quay.intersection(&block_frame));
             }
         }
+
+        let from_flow_frame = |rectangle: Rectangle| self.to_flow_frame(&rectangle);
+        (
+            pieces.into_iter().map(from_flow_frame).collect(),
+            quays.into_iter().map(from_flow_frame).collect(),
+        )
+    }
+
+    /// Splits a road into the pieces that stay on land, and the spans
+    /// that have to cross the water. The spans run along the longer
+    /// side of the road, so a road running beside the river gets a
+    /// long bridge rather than being drowned.
+    pub fn split_road(&self, road: &Rectangle) -> (Vec<Rectangle>, Vec<Rectangle>) {
+        let road_frame = self.to_flow_frame(road);
+        let along_x = road_frame.width() >= road_frame.height();
+
+        // Project the wet parts of the road onto its long axis
+        let mut wet: Vec<(f64, f64)> = self
+            .embanked_channel()
+            .filter_map(|embanked| embanked.intersection(&road_frame))
+            .map(|overlap| if along_x {
+                (overlap.start().x, overlap.end().x)
+            } else {
+                (overlap.start().y, overlap.end().y)
+            })
+            .collect();
+
+        if wet.is_empty() {
+            return (vec![*road], Vec::new());
+        }
+
+        wet.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut spans: Vec<(f64, f64)> = Vec::new();
+        for (start, end) in wet {
+            match spans.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => spans.push((start, end)),
+            }
+        }
+
+        let (road_start, road_end) = if along_x {
+            (road_frame.start().x, road_frame.end().x)
+        } else {
+            (road_frame.start().y, road_frame.end().y)
+        };
+
+        // Cut a piece of the road out along its long axis
+        let cut = |from: f64, to: f64| if along_x {
+            Rectangle::new(
+                Vector2 { x: from, y: road_frame.start().y },
+                Vector2 { x: to, y: road_frame.end().y },
+            )
+        } else {
+            Rectangle::new(
+                Vector2 { x: road_frame.start().x, y: from },
+                Vector2 { x: road_frame.end().x, y: to },
+            )
+        };
+
+        let mut dry = Vec::new();
+        let mut bridges = Vec::new();
+        let mut dry_start = road_start;
+        for (start, end) in spans {
+            if start > dry_start {
+                dry.push(cut(dry_start, start));
+            }
+
+            bridges.push(cut(start, end));
+            dry_start = end;
+        }
+
+        if dry_start < road_end {
+            dry.push(cut(dry_start, road_end));
+        }
+
+        let from_flow_frame = |rectangle: Rectangle| self.to_flow_frame(&rectangle);
+        (
+            dry.into_iter().map(from_flow_frame).collect(),
+            bridges.into_iter().map(from_flow_frame).collect(),
+        )
+    }
+}
+
+impl Renderable for Waterway {
+    fn render(&self, offset: Vector2, scale: Vector2, canvas: &mut Canvas) {
+        let water = (canvas.palette.rgba(Surface::Water), Material::Water);
+        let quay = (canvas.palette.rgba(Surface::Quay), Material::Concrete);
+
+        let regions = self.channel
+            .iter()
+            .map(|slice| (slice, water))
+            .chain(self.quays.iter().map(|rectangle| (rectangle, quay)));
+
+        for (rectangle, (color, material)) in regions {
+            let region = rectangle
+                .translate(offset)
+                .scale(scale);
+
+            canvas.paint(region, color, material);
+        }
+    }
+}
+
+/// The part of a road that crosses a waterway
+#[derive(Debug)]
+pub struct Bridge {
+    pub deck: Rectangle,
+    pub railing_breadth: f64,
+}
+
+impl Renderable for Bridge {
+    fn render(&self, offset: Vector2, scale: Vector2, canvas: &mut Canvas) {
+        let deck = self
+            .deck
+            .translate(offset)
+            .scale(scale);
+
+        let railing_color = canvas.palette.rgba(Surface::BridgeRailing);
+        let deck_color = canvas.palette.rgba(Surface::BridgeDeck);
+
+        // The railings run along the long sides of the deck
+        let inset = self.railing_breadth;
+        let roadway = if self.deck.width() >= self.deck.height() {
+            Rectangle::new(
+                self.deck.start() + Vector2 { x: 0.0, y: inset },
+                self.deck.end() - Vector2 { x: 0.0, y: inset },
+            )
+        } else {
+            Rectangle::new(
+                self.deck.start() + Vector2 { x: inset, y: 0.0 },
+                self.deck.end() - Vector2 { x: inset, y: 0.0 },
+            )
+        }.translate(offset).scale(scale);
+
+        canvas.paint(deck, railing_color, Material::Concrete);
+        canvas.paint(roadway, deck_color, Material::Asphalt);
+    }
+}
+
+/// A pier or dock jutting out from the shore into the sea
+#[derive(Debug)]
+pub struct Pier {
+    pub deck: Rectangle,
+    pub edge_breadth: f64,
+}
+
+impl Renderable for Pier {
+    fn render(&self, offset: Vector2, scale: Vector2, canvas: &mut Canvas) {
+        let deck_with_edge = self
+            .deck
+            .translate(offset)
+            .scale(scale);
+
+        let deck = self.deck.translate(offset)
+            .inset(self.edge_breadth)
+            .scale(scale);
+
+        let edge_color = canvas.palette.rgba(Surface::PierEdge);
+        let deck_color = canvas.palette.rgba(Surface::PierDeck);
+
+        canvas.paint(deck_with_edge, edge_color, Material::Concrete);
+        canvas.paint(deck, deck_color, Material::Concrete);
     }
 }
 
@@ -111,3 +644,61 @@ pub struct Park {
     pub trees: Vec<Tree>,
 }
 */
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn canal() -> Waterway {
+        Waterway {
+            channel: vec![Rectangle::new(
+                Vector2 { x: 0.0, y: 40.0 },
+                Vector2 { x: 100.0, y: 60.0 },
+            )],
+            quays: Vec::new(),
+            quay_breadth: 5.0,
+            flows_along_x: true,
+            bridged: true,
+        }
+    }
+
+    #[test]
+    fn road_across_canal_gets_bridge() {
+        let road = Rectangle::new(
+            Vector2 { x: 10.0, y: 0.0 },
+            Vector2 { x: 20.0, y: 100.0 },
+        );
+
+        let (dry, bridges) = canal().split_road(&road);
+        assert_eq!(dry.len(), 2);
+        assert_eq!(bridges.len(), 1);
+        assert_eq!(bridges[0].start().y, 35.0);
+        assert_eq!(bridges[0].end().y, 65.0);
+    }
+
+    #[test]
+    fn dry_road_untouched() {
+        let road = Rectangle::new(
+            Vector2 { x: 0.0, y: 0.0 },
+            Vector2 { x: 100.0, y: 10.0 },
+        );
+
+        let (dry, bridges) = canal().split_road(&road);
+        assert_eq!(dry.len(), 1);
+        assert!(bridges.is_empty());
+    }
+
+    #[test]
+    fn block_clipped_to_banks() {
+        let block = Rectangle::new(
+            Vector2 { x: 0.0, y: 20.0 },
+            Vector2 { x: 50.0, y: 80.0 },
+        );
+
+        let (pieces, quays) = canal().clip_block(&block);
+        assert_eq!(pieces.len(), 2);
+        assert_eq!(pieces[0].end().y, 35.0);
+        assert_eq!(pieces[1].start().y, 65.0);
+        assert_eq!(quays.len(), 2);
+    }
+}
@@ -0,0 +1,576 @@
+use image::Rgba;
+
+use super::stats;
+use super::zone::Zone;
+
+/// The names of the slots in each zone's roof palette, as they
+/// appear in cityscript
+pub const ROOF_SLOTS: [&str; 4] = ["1", "2", "3", "4"];
+
+/// Blueprints run from deep blue on the darkest surfaces to near
+/// white on the lightest
+const BLUEPRINT_DEEP: [f64; 3] = [0x12 as f64, 0x34 as f64, 0x6C as f64];
+const BLUEPRINT_PALE: [f64; 3] = [0xE4 as f64, 0xEE as f64, 0xFA as f64];
+
+/// How far pastels are washed out towards white, after their colour
+/// is brought up
+const PASTEL_WASH: f64 = 0.5;
+const PASTEL_SATURATION: f64 = 1.3;
+
+/// Dark mode keeps a little of each colour, dimmed over a faint blue
+const DARK_SATURATION: f64 = 0.6;
+const DARK_BRIGHTNESS: f64 = 0.45;
+const DARK_FLOOR: [f64; 3] = [4.0, 6.0, 12.0];
+
+/// Everything in the city that's drawn in a colour of its own
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Surface {
+    /// The sidewalks and open ground of blocks, and anything left
+    /// uncovered
+    Pavement,
+    Asphalt,
+    /// Parks
+    Grass,
+    /// Garden courtyards and backyards
+    Garden,
+    /// Paved yards and driveways
+    Paving,
+    Water,
+    Quay,
+    BridgeDeck,
+    BridgeRailing,
+    PierDeck,
+    PierEdge,
+    /// The parapets around flat roofs
+    RoofEdge,
+    ParkingAsphalt,
+    ParkingLines,
+    Earth,
+    Weeds,
+    Hoarding,
+    Slab,
+    Columns,
+    Crane,
+    Ballast,
+    Verge,
+    Rails,
+    Sleepers,
+    Platforms,
+    PlatformEdges,
+    Canopies,
+    Hvac,
+    Fans,
+    Hubs,
+    SkylightFrames,
+    Skylights,
+    Bulkheads,
+    Tanks,
+    Lids,
+    SolarRacks,
+    SolarPanels,
+    Stands,
+    RunningTrack,
+    Pitch,
+    PitchLines,
+    Copper,
+    Slate,
+    Stone,
+    TrainShed,
+    Terminus,
+    Mall,
+    Windscreens,
+    Units,
+    Containers,
+    Snow,
+    /// Puddles reflect an overcast sky
+    Puddles,
+    // The colours leaves turn in autumn, from the first to turn to the
+    // last
+    AutumnOlive,
+    AutumnGold,
+    AutumnOrange,
+    AutumnRusset,
+    /// The glow of street lamps at night
+    Lamps,
+    // The colours cars come in
+    CarWhite,
+    CarBlack,
+    CarSilver,
+    CarGrey,
+    CarRed,
+    CarBlue,
+    CarGreen,
+    CarBeige,
+    // The liveries buses come in
+    BusRed,
+    BusYellow,
+    BusBlue,
+    // The colours of the cabs on trucks
+    TruckWhite,
+    TruckBlue,
+    TruckRed,
+    TruckGrey,
+}
+
+impl Surface {
+    pub const ALL: [Surface; 72] = [
+        Surface::Pavement,
+        Surface::Asphalt,
+        Surface::Grass,
+        Surface::Garden,
+        Surface::Paving,
+        Surface::Water,
+        Surface::Quay,
+        Surface::BridgeDeck,
+        Surface::BridgeRailing,
+        Surface::PierDeck,
+        Surface::PierEdge,
+        Surface::RoofEdge,
+        Surface::ParkingAsphalt,
+        Surface::ParkingLines,
+        Surface::Earth,
+        Surface::Weeds,
+        Surface::Hoarding,
+        Surface::Slab,
+        Surface::Columns,
+        Surface::Crane,
+        Surface::Ballast,
+        Surface::Verge,
+        Surface::Rails,
+        Surface::Sleepers,
+        Surface::Platforms,
+        Surface::PlatformEdges,
+        Surface::Canopies,
+        Surface::Hvac,
+        Surface::Fans,
+        Surface::Hubs,
+        Surface::SkylightFrames,
+        Surface::Skylights,
+        Surface::Bulkheads,
+        Surface::Tanks,
+        Surface::Lids,
+        Surface::SolarRacks,
+        Surface::SolarPanels,
+        Surface::Stands,
+        Surface::RunningTrack,
+        Surface::Pitch,
+        Surface::PitchLines,
+        Surface::Copper,
+        Surface::Slate,
+        Surface::Stone,
+        Surface::TrainShed,
+        Surface::Terminus,
+        Surface::Mall,
+        Surface::Windscreens,
+        Surface::Units,
+        Surface::Containers,
+        Surface::Snow,
+        Surface::Puddles,
+        Surface::AutumnOlive,
+        Surface::AutumnGold,
+        Surface::AutumnOrange,
+        Surface::AutumnRusset,
+        Surface::Lamps,
+        Surface::CarWhite,
+        Surface::CarBlack,
+        Surface::CarSilver,
+        Surface::CarGrey,
+        Surface::CarRed,
+        Surface::CarBlue,
+        Surface::CarGreen,
+        Surface::CarBeige,
+        Surface::BusRed,
+        Surface::BusYellow,
+        Surface::BusBlue,
+        Surface::TruckWhite,
+        Surface::TruckBlue,
+        Surface::TruckRed,
+        Surface::TruckGrey,
+    ];
+
+    /// The name of the surface's colour in cityscript, under colors
+    pub fn name(self) -> &'static str {
+        match self {
+            Surface::Pavement => "pavement",
+            Surface::Asphalt => "asphalt",
+            Surface::Grass => "grass",
+            Surface::Garden => "garden",
+            Surface::Paving => "paving",
+            Surface::Water => "water",
+            Surface::Quay => "quay",
+            Surface::BridgeDeck => "bridges.deck",
+            Surface::BridgeRailing => "bridges.railing",
+            Surface::PierDeck => "piers.deck",
+            Surface::PierEdge => "piers.edge",
+            Surface::RoofEdge => "roofs.edge",
+            Surface::ParkingAsphalt => "parking.asphalt",
+            Surface::ParkingLines => "parking.lines",
+            Surface::Earth => "vacant.earth",
+            Surface::Weeds => "vacant.weeds",
+            Surface::Hoarding => "construction.fence",
+            Surface::Slab => "construction.slab",
+            Surface::Columns => "construction.columns",
+            Surface::Crane => "construction.crane",
+            Surface::Ballast => "rail.ballast",
+            Surface::Verge => "rail.verge",
+            Surface::Rails => "rail.rails",
+            Surface::Sleepers => "rail.sleepers",
+            Surface::Platforms => "stations.platforms",
+            Surface::PlatformEdges => "stations.edges",
+            Surface::Canopies => "stations.canopies",
+            Surface::Hvac => "rooftops.hvac",
+            Surface::Fans => "rooftops.fans",
+            Surface::Hubs => "rooftops.hubs",
+            Surface::SkylightFrames => "rooftops.frames",
+            Surface::Skylights => "rooftops.skylights",
+            Surface::Bulkheads => "rooftops.bulkheads",
+            Surface::Tanks => "rooftops.tanks",
+            Surface::Lids => "rooftops.lids",
+            Surface::SolarRacks => "rooftops.racks",
+            Surface::SolarPanels => "rooftops.panels",
+            Surface::Stands => "landmarks.stands",
+            Surface::RunningTrack => "landmarks.track",
+            Surface::Pitch => "landmarks.pitch",
+            Surface::PitchLines => "landmarks.lines",
+            Surface::Copper => "landmarks.copper",
+            Surface::Slate => "landmarks.slate",
+            Surface::Stone => "landmarks.stone",
+            Surface::TrainShed => "landmarks.shed",
+            Surface::Terminus => "landmarks.terminus",
+            Surface::Mall => "landmarks.mall",
+            Surface::Windscreens => "vehicles.glass",
+            Surface::Units => "vehicles.units",
+            Surface::Containers => "vehicles.containers",
+            Surface::Snow => "snow",
+            Surface::Puddles => "puddles",
+            Surface::AutumnOlive => "autumn.olive",
+            Surface::AutumnGold => "autumn.gold",
+            Surface::AutumnOrange => "autumn.orange",
+            Surface::AutumnRusset => "autumn.russet",
+            Surface::Lamps => "lamps",
+            Surface::CarWhite => "vehicles.paint.car.white",
+            Surface::CarBlack => "vehicles.paint.car.black",
+            Surface::CarSilver => "vehicles.paint.car.silver",
+            Surface::CarGrey => "vehicles.paint.car.grey",
+            Surface::CarRed => "vehicles.paint.car.red",
+            Surface::CarBlue => "vehicles.paint.car.blue",
+            Surface::CarGreen => "vehicles.paint.car.green",
+            Surface::CarBeige => "vehicles.paint.car.beige",
+            Surface::BusRed => "vehicles.paint.bus.red",
+            Surface::BusYellow => "vehicles.paint.bus.yellow",
+            Surface::BusBlue => "vehicles.paint.bus.blue",
+            Surface::TruckWhite => "vehicles.paint.truck.white",
+            Surface::TruckBlue => "vehicles.paint.truck.blue",
+            Surface::TruckRed => "vehicles.paint.truck.red",
+            Surface::TruckGrey => "vehicles.paint.truck.grey",
+        }
+    }
+
+    /// The colour of the surface as it looks from the air
+    fn realistic(self) -> [u8; 3] {
+        match self {
+            Surface::Pavement => [0xA0, 0xA0, 0xA0],
+            Surface::Asphalt => [0x20, 0x20, 0x20],
+            Surface::Grass => [0x50, 0x80, 0x48],
+            Surface::Garden => [0x5C, 0x84, 0x4C],
+            Surface::Paving => [0xB4, 0xB0, 0xA8],
+            Surface::Water => [0x28, 0x48, 0x68],
+            Surface::Quay => [0x78, 0x70, 0x68],
+            Surface::BridgeDeck => [0x30, 0x30, 0x30],
+            Surface::BridgeRailing => [0x90, 0x90, 0x90],
+            Surface::PierDeck => [0x8C, 0x78, 0x60],
+            Surface::PierEdge => [0x58, 0x50, 0x48],
+            Surface::RoofEdge => [0x50, 0x50, 0x50],
+            Surface::ParkingAsphalt => [0x38, 0x38, 0x3A],
+            Surface::ParkingLines => [0xD8, 0xD8, 0xD0],
+            Surface::Earth => [0x8A, 0x74, 0x5A],
+            Surface::Weeds => [0x6C, 0x78, 0x44],
+            Surface::Hoarding => [0x3C, 0x5A, 0x8C],
+            Surface::Slab => [0xB8, 0xB4, 0xAC],
+            Surface::Columns => [0x98, 0x94, 0x8C],
+            Surface::Crane => [0xE8, 0xB8, 0x20],
+            Surface::Ballast => [0x7C, 0x74, 0x6C],
+            Surface::Verge => [0x94, 0x8C, 0x78],
+            Surface::Rails => [0x8C, 0x8C, 0x90],
+            Surface::Sleepers => [0x4C, 0x40, 0x34],
+            Surface::Platforms => [0xB8, 0xB4, 0xAC],
+            Surface::PlatformEdges => [0xE8, 0xC8, 0x20],
+            Surface::Canopies => [0x6C, 0x74, 0x7C],
+            Surface::Hvac => [0xB4, 0xB8, 0xBC],
+            Surface::Fans => [0x30, 0x30, 0x34],
+            Surface::Hubs => [0x88, 0x88, 0x8C],
+            Surface::SkylightFrames => [0x98, 0x98, 0x98],
+            Surface::Skylights => [0x68, 0x80, 0x90],
+            Surface::Bulkheads => [0x8C, 0x88, 0x84],
+            Surface::Tanks => [0x5A, 0x44, 0x30],
+            Surface::Lids => [0x8A, 0x6A, 0x4A],
+            Surface::SolarRacks => [0x90, 0x90, 0x94],
+            Surface::SolarPanels => [0x22, 0x2C, 0x4C],
+            Surface::Stands => [0x98, 0x98, 0x9C],
+            Surface::RunningTrack => [0xA8, 0x54, 0x44],
+            Surface::Pitch => [0x4C, 0x88, 0x40],
+            Surface::PitchLines => [0xE8, 0xE8, 0xE8],
+            Surface::Copper => [0x6E, 0x93, 0x84],
+            Surface::Slate => [0x5C, 0x60, 0x6A],
+            Surface::Stone => [0x9A, 0x90, 0x80],
+            Surface::TrainShed => [0x8C, 0xA4, 0xB0],
+            Surface::Terminus => [0xB0, 0xA0, 0x88],
+            Surface::Mall => [0xC8, 0xC8, 0xC4],
+            Surface::Windscreens => [0x28, 0x30, 0x38],
+            Surface::Units => [0xB0, 0xB0, 0xB0],
+            Surface::Containers => [0xD4, 0xD0, 0xC8],
+            Surface::Snow => [0xEC, 0xF0, 0xF4],
+            Surface::Puddles => [0x60, 0x68, 0x74],
+            Surface::AutumnOlive => [0x8C, 0x8C, 0x3C],
+            Surface::AutumnGold => [0xC0, 0x98, 0x30],
+            Surface::AutumnOrange => [0xC0, 0x64, 0x24],
+            Surface::AutumnRusset => [0x8C, 0x3C, 0x20],
+            Surface::Lamps => [0xFF, 0xB0, 0x60],
+            Surface::CarWhite => [0xE8, 0xE8, 0xE8],
+            Surface::CarBlack => [0x20, 0x20, 0x24],
+            Surface::CarSilver => [0x9A, 0x9C, 0xA0],
+            Surface::CarGrey => [0x5C, 0x60, 0x66],
+            Surface::CarRed => [0x8C, 0x1C, 0x1C],
+            Surface::CarBlue => [0x1C, 0x34, 0x6C],
+            Surface::CarGreen => [0x3C, 0x58, 0x3C],
+            Surface::CarBeige => [0xC8, 0xB4, 0x8C],
+            Surface::BusRed => [0xC8, 0x28, 0x20],
+            Surface::BusYellow => [0xE0, 0xB0, 0x18],
+            Surface::BusBlue => [0x2C, 0x70, 0xA8],
+            Surface::TruckWhite => [0xE8, 0xE8, 0xE8],
+            Surface::TruckBlue => [0x1C, 0x34, 0x6C],
+            Surface::TruckRed => [0x8C, 0x1C, 0x1C],
+            Surface::TruckGrey => [0x3C, 0x3C, 0x40],
+        }
+    }
+}
+
+/// The roofs of each zone as they look from the air, as colours and
+/// their weights
+fn realistic_roofs(zone: Zone) -> [([u8; 3], f64); 4] {
+    match zone {
+        // Grey shingle, sandy tiles, terracotta and dark slate
+        Zone::Residential => [
+            ([0xB0, 0xB0, 0xB0], 3.0),
+            ([0xC8, 0xBC, 0xA0], 2.0),
+            ([0xA8, 0x6A, 0x50], 2.0),
+            ([0x6C, 0x70, 0x78], 1.0),
+        ],
+        // Cool glass and steel
+        Zone::Commercial => [
+            ([0x90, 0x98, 0xA8], 3.0),
+            ([0xA8, 0xB0, 0xC0], 2.0),
+            ([0xB8, 0xB8, 0xB8], 1.0),
+            ([0x70, 0x78, 0x84], 1.0),
+        ],
+        // Pale corrugated metal, and the odd rusty shed
+        Zone::Industrial => [
+            ([0x98, 0x9C, 0x98], 3.0),
+            ([0xB0, 0xB4, 0xB0], 2.0),
+            ([0x8C, 0x90, 0x94], 1.0),
+            ([0xA8, 0x7C, 0x60], 0.5),
+        ],
+        // Sandstone and copper
+        Zone::Civic => [
+            ([0xB8, 0xB0, 0x90], 2.0),
+            ([0xC8, 0xC0, 0xA0], 1.0),
+            ([0x7C, 0xA0, 0x8C], 1.0),
+            ([0x8C, 0x8C, 0x8C], 1.0),
+        ],
+        // Park pavilions, should anything ever be built in one
+        Zone::Park => [
+            ([0x70, 0x90, 0x70], 1.0),
+            ([0x80, 0xA0, 0x80], 1.0),
+            ([0x80, 0x80, 0x80], 0.0),
+            ([0x80, 0x80, 0x80], 0.0),
+        ],
+    }
+}
+
+/// A preset of every colour in the city, each made over from how it
+/// looks from the air
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Theme {
+    Realistic,
+    /// Shades of blue, lightest where the city is lightest
+    Blueprint,
+    /// Bright colours washed out towards white
+    Pastel,
+    /// Dim and muted
+    Dark,
+}
+
+impl Theme {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "realistic" => Some(Theme::Realistic),
+            "blueprint" => Some(Theme::Blueprint),
+            "pastel" => Some(Theme::Pastel),
+            "dark" => Some(Theme::Dark),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Theme::Realistic => "realistic",
+            Theme::Blueprint => "blueprint",
+            Theme::Pastel => "pastel",
+            Theme::Dark => "dark",
+        }
+    }
+
+    /// Makes over a realistic colour to suit the theme
+    pub fn recolor(self, color: [u8; 3]) -> [u8; 3] {
+        let channels = color.map(|channel| channel as f64);
+        let luma = channels[0] * 0.3 + channels[1] * 0.59 + channels[2] * 0.11;
+        let saturate = |by: f64| channels.map(|channel| luma + (channel - luma) * by);
+
+        let recolored = match self {
+            Theme::Realistic => channels,
+            Theme::Blueprint => {
+                let lightness = (luma / u8::MAX as f64).powf(1.4);
+                [0, 1, 2].map(|i| BLUEPRINT_DEEP[i] + (BLUEPRINT_PALE[i] - BLUEPRINT_DEEP[i]) * lightness)
+            },
+            Theme::Pastel => saturate(PASTEL_SATURATION)
+                .map(|channel| channel + (u8::MAX as f64 - channel) * PASTEL_WASH),
+            Theme::Dark => {
+                let muted = saturate(DARK_SATURATION);
+                [0, 1, 2].map(|i| DARK_FLOOR[i] + muted[i] * DARK_BRIGHTNESS)
+            },
+        };
+
+        recolored.map(|channel| channel.round().clamp(0.0, u8::MAX as f64) as u8)
+    }
+
+    pub fn palette(self) -> Palette {
+        let mut palette = Palette {
+            theme: self,
+            colors: [[0; 3]; Surface::ALL.len()],
+            roofs: [[([0; 3], 0.0); ROOF_SLOTS.len()]; Zone::ALL.len()],
+        };
+
+        for surface in Surface::ALL {
+            palette.set(surface, self.recolor(surface.realistic()));
+        }
+
+        for zone in Zone::ALL {
+            for (slot, (color, weight)) in realistic_roofs(zone).into_iter().enumerate() {
+                palette.set_roof(zone, slot, self.recolor(color), weight);
+            }
+        }
+
+        palette
+    }
+}
+
+/// The colours everything in the city is drawn in
+#[derive(Clone, Debug)]
+pub struct Palette {
+    /// The theme the colours started out from
+    pub theme: Theme,
+    colors: [[u8; 3]; Surface::ALL.len()],
+    roofs: [[([u8; 3], f64); ROOF_SLOTS.len()]; Zone::ALL.len()],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Theme::Realistic.palette()
+    }
+}
+
+impl Palette {
+    pub fn color(&self, surface: Surface) -> [u8; 3] {
+        self.colors[surface as usize]
+    }
+
+    /// The colour of a surface, ready to paint with
+    pub fn rgba(&self, surface: Surface) -> Rgba<u8> {
+        let [red, green, blue] = self.color(surface);
+        Rgba([red, green, blue, 0xFF])
+    }
+
+    /// The colour of a surface, lightened or darkened by a factor
+    pub fn shaded(&self, surface: Surface, shade: f64) -> Rgba<u8> {
+        let [red, green, blue] = self.color(surface)
+            .map(|channel| (channel as f64 * shade).round().clamp(0.0, u8::MAX as f64) as u8);
+        Rgba([red, green, blue, 0xFF])
+    }
+
+    pub fn set(&mut self, surface: Surface, color: [u8; 3]) {
+        self.colors[surface as usize] = color;
+    }
+
+    /// The colours and weights of a zone's roof palette
+    pub fn roofs(&self, zone: Zone) -> &[([u8; 3], f64)] {
+        &self.roofs[zone as usize]
+    }
+
+    pub fn set_roof(&mut self, zone: Zone, slot: usize, color: [u8; 3], weight: f64) {
+        self.roofs[zone as usize][slot] = (color, weight);
+    }
+
+    /// Picks a roof colour from a zone's palette by weight, the choice
+    /// is in [0, 1]
+    pub fn roof(&self, zone: Zone, choice: f64) -> [u8; 4] {
+        let roofs = self.roofs(zone);
+        let weights: Vec<f64> = roofs.iter().map(|(_, weight)| *weight).collect();
+        let ([red, green, blue], _) = roofs[stats::weighted_index(&weights, choice.clamp(0.0, 1.0))];
+        [red, green, blue, 0xFF]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::settings::Settings;
+    use super::super::settings::config::ColorConfig;
+
+    #[test]
+    fn colours_set_after_a_theme_win() {
+        let mut settings = Settings::default();
+        settings.update(
+            "let colors.asphalt be #102030\n\
+             let colors.theme be blueprint\n\
+             let colors.water be rgb(1,2,3)\n\
+             let colors.grass be olive\n\
+             let colors.roofs.civic.2.color be 0xA0B0C0",
+        ).unwrap();
+
+        let palette = ColorConfig::try_from(&settings).unwrap().palette;
+        assert_eq!(palette.theme, Theme::Blueprint);
+        assert_eq!(palette.color(Surface::Asphalt), Theme::Blueprint.recolor(Surface::Asphalt.realistic()));
+        assert_eq!(palette.color(Surface::Water), [1, 2, 3]);
+        assert_eq!(palette.color(Surface::Grass), [0x80, 0x80, 0x00]);
+        assert_eq!(palette.roofs(Zone::Civic)[1].0, [0xA0, 0xB0, 0xC0]);
+
+        assert!(Settings::default().update("let colors.theme be sepia").is_err());
+        assert!(Settings::default().update("let colors.water be #12345").is_err());
+    }
+
+    #[test]
+    fn roof_weights_must_give_some_roof_a_chance() {
+        let mut settings = Settings::default();
+        settings.update("let colors.roofs.civic.2.weight be -1.0").unwrap();
+        assert!(ColorConfig::try_from(&settings).is_err());
+
+        let mut settings = Settings::default();
+        for slot in ROOF_SLOTS {
+            settings.update(&format!("let colors.roofs.civic.{}.weight be 0.0", slot)).unwrap();
+        }
+        assert!(ColorConfig::try_from(&settings).is_err());
+
+        let mut settings = Settings::default();
+        settings.update("let colors.roofs.civic.1.weight be 0.0").unwrap();
+        assert!(ColorConfig::try_from(&settings).is_ok());
+    }
+
+    #[test]
+    fn roofs_without_weight_are_never_picked() {
+        let palette = Theme::Realistic.palette();
+        for step in 0..=100 {
+            let [red, green, blue, _] = palette.roof(Zone::Park, step as f64 / 100.0);
+            assert_ne!([red, green, blue], [0x80, 0x80, 0x80]);
+        }
+    }
+}
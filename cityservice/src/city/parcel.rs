@@ -0,0 +1,338 @@
+use super::channels::{Canvas, Material};
+use super::geom::*;
+use super::objects::{Courtyard, CourtyardKind, Renderable};
+use super::palette::Surface;
+
+/// Lots are never cut closer to an edge than this share of the way
+/// across
+const MIN_SPLIT: f64 = 0.3;
+
+/// The smallest span a building left on a lot can have, in meters
+const MIN_BUILDING_SPAN: f64 = 3.0;
+
+/// The breadth of the line left between neighbouring lots, in meters
+const LOT_LINE_BREADTH: f64 = 0.3;
+
+/// The breadth of a driveway, and the room left between it and the
+/// side of the lot
+const DRIVEWAY_BREADTH: f64 = 3.0;
+const DRIVEWAY_MARGIN: f64 = 0.5;
+
+/// How far a driveway beside a house runs back from its front, to
+/// where the garage would be
+const DRIVEWAY_DEPTH: f64 = 6.0;
+
+/// How a block is cut up into buildings
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LotStrategy {
+    /// Buildings are lined up along each face of the block in rings
+    Faces,
+    /// The block is split in two again and again into lots that all
+    /// keep some street frontage, with a building on each
+    Subdivide,
+    /// The whole block is a single lot, with a house set back behind
+    /// a lawn and a driveway up to it, as in the suburbs
+    Detached,
+}
+
+impl LotStrategy {
+    /// Reads a strategy from its name in cityscript
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "faces" => Some(LotStrategy::Faces),
+            "subdivide" => Some(LotStrategy::Subdivide),
+            "detached" => Some(LotStrategy::Detached),
+            _ => None,
+        }
+    }
+}
+
+/// A lot within a block, holding a single building
+#[derive(Clone, Debug)]
+pub struct Parcel {
+    /// Stored relative to the buildings boundary of its block
+    pub footprint: Rectangle,
+    /// Which sides face the street, clockwise from the top
+    pub frontage: [bool; 4],
+    /// What the ground around the building is laid out as
+    pub yard: CourtyardKind,
+    /// The paved way in from the street, on lots that have one
+    pub driveway: Option<Rectangle>,
+}
+
+impl Parcel {
+    /// The building the lot holds, set back from the street on the
+    /// sides with frontage and from the neighbours on the rest. The
+    /// building reaches no deeper than the given depth from the
+    /// street. There's no building if the setbacks leave no room.
+    pub fn building(&self, front: f64, side: f64, depth: f64) -> Option<Rectangle> {
+        let [top, right, bottom, left] = self.frontage
+            .map(|street| if street { front } else { side });
+
+        let mut start = self.footprint.start() + Vector2 { x: left, y: top };
+        let mut end = self.footprint.end() - Vector2 { x: right, y: bottom };
+
+        // Deep lots leave a backyard behind the building
+        match (self.frontage[0], self.frontage[2]) {
+            (true, false) => end.y = end.y.min(start.y + depth),
+            (false, true) => start.y = start.y.max(end.y - depth),
+            _ => (),
+        }
+
+        match (self.frontage[3], self.frontage[1]) {
+            (true, false) => end.x = end.x.min(start.x + depth),
+            (false, true) => start.x = start.x.max(end.x - depth),
+            _ => (),
+        }
+
+        let size = end - start;
+        if size.x < MIN_BUILDING_SPAN || size.y < MIN_BUILDING_SPAN {
+            return None;
+        }
+
+        Some(Rectangle::new(start, end))
+    }
+
+    /// The driveway in from the street to a building on the lot. It
+    /// runs alongside the building if there's room, back to where
+    /// the garage would be, and otherwise straight up to the front.
+    pub fn driveway(&self, building: Rectangle) -> Option<Rectangle> {
+        // Work in a frame where the street runs along the top, flipping
+        // the lot about its own corner
+        let side = self.frontage.iter().position(|&street| street)?;
+        let size = self.footprint.dimensions();
+        let to_frame = |rectangle: Rectangle| {
+            let rectangle = rectangle.translate(self.footprint.start() * -1.0);
+            match side {
+                0 => rectangle,
+                1 => rectangle.transpose().mirror_y(size.x),
+                2 => rectangle.mirror_y(size.y),
+                _ => rectangle.transpose(),
+            }
+        };
+        let from_frame = |rectangle: Rectangle| {
+            let rectangle = match side {
+                0 => rectangle,
+                1 => rectangle.mirror_y(size.x).transpose(),
+                2 => rectangle.mirror_y(size.y),
+                _ => rectangle.transpose(),
+            };
+            rectangle.translate(self.footprint.start())
+        };
+
+        let lot = to_frame(self.footprint);
+        let building = to_frame(building);
+        let room_left = building.start().x - lot.start().x;
+        let room_right = lot.end().x - building.end().x;
+        let room = DRIVEWAY_BREADTH + 2.0 * DRIVEWAY_MARGIN;
+
+        let (start_x, end_y) = if room_right >= room {
+            (building.end().x + DRIVEWAY_MARGIN, building.start().y + DRIVEWAY_DEPTH)
+        } else if room_left >= room {
+            (building.start().x - DRIVEWAY_MARGIN - DRIVEWAY_BREADTH, building.start().y + DRIVEWAY_DEPTH)
+        } else if building.width() >= room {
+            (building.start().x + DRIVEWAY_MARGIN, building.start().y)
+        } else {
+            return None;
+        };
+
+        let end_y = end_y.min(lot.end().y);
+        if end_y <= lot.start().y {
+            return None;
+        }
+
+        Some(from_frame(Rectangle::new(
+            Vector2 { x: start_x, y: lot.start().y },
+            Vector2 { x: start_x + DRIVEWAY_BREADTH, y: end_y },
+        )))
+    }
+
+    /// Cuts the lot in two across the given axis, a share of the way
+    /// along it. Both halves have to keep some frontage and be at
+    /// least the minimum frontage across.
+    fn split(&self, along_x: bool, share: f64, min_frontage: f64) -> Option<(Parcel, Parcel)> {
+        let (start, end) = (self.footprint.start(), self.footprint.end());
+        let [top, right, bottom, left] = self.frontage;
+
+        let (first, second) = if along_x {
+            let cut = start.x + self.footprint.width() * share;
+            (
+                (Rectangle::new(start, Vector2 { x: cut, y: end.y }), [top, false, bottom, left]),
+                (Rectangle::new(Vector2 { x: cut, y: start.y }, end), [top, right, bottom, false]),
+            )
+        } else {
+            let cut = start.y + self.footprint.height() * share;
+            (
+                (Rectangle::new(start, Vector2 { x: end.x, y: cut }), [top, right, false, left]),
+                (Rectangle::new(Vector2 { x: start.x, y: cut }, end), [false, right, bottom, left]),
+            )
+        };
+
+        let valid = |(footprint, frontage): &(Rectangle, [bool; 4])| {
+            let across = if along_x { footprint.width() } else { footprint.height() };
+            across >= min_frontage && frontage.iter().any(|&street| street)
+        };
+
+        if !valid(&first) || !valid(&second) {
+            return None;
+        }
+
+        let parcel = |(footprint, frontage)| Parcel {
+            footprint,
+            frontage,
+            yard: self.yard,
+            driveway: None,
+        };
+
+        Some((parcel(first), parcel(second)))
+    }
+}
+
+/// Splits an area with street on every side into lots, taking the
+/// largest area each lot may have and random numbers in [0, 1] for
+/// where to cut it from the samples as it goes. Lots are cut across
+/// their longer side where they can be, and across the other side
+/// where that would leave a half without street frontage. A lot that
+/// can't be cut either way is left as it is.
+pub fn subdivide(
+    area: Rectangle,
+    yard: CourtyardKind,
+    min_frontage: f64,
+    max_areas: &mut impl Iterator<Item = f64>,
+    splits: &mut impl Iterator<Item = f64>,
+) -> Vec<Parcel> {
+    let mut parcels = Vec::new();
+    let mut unsplit = vec![Parcel {
+        footprint: area,
+        frontage: [true; 4],
+        yard,
+        driveway: None,
+    }];
+
+    while let Some(parcel) = unsplit.pop() {
+        let max_area = max_areas.next().unwrap_or(f64::INFINITY);
+        if parcel.footprint.width() * parcel.footprint.height() <= max_area {
+            parcels.push(parcel);
+            continue;
+        }
+
+        let share = MIN_SPLIT + (1.0 - 2.0 * MIN_SPLIT) * splits.next().unwrap_or(0.5);
+        let longer_x = parcel.footprint.width() >= parcel.footprint.height();
+        let halves = parcel
+            .split(longer_x, share, min_frontage)
+            .or_else(|| parcel.split(!longer_x, share, min_frontage));
+
+        match halves {
+            Some((first, second)) => {
+                unsplit.push(first);
+                unsplit.push(second);
+            },
+            None => parcels.push(parcel),
+        }
+    }
+
+    parcels
+}
+
+impl Renderable for Parcel {
+    fn render(&self, offset: Vector2, scale: Vector2, canvas: &mut Canvas) {
+        // The yard stops short of the lot lines, so the block's
+        // ground shows through between neighbours
+        let yard = Courtyard {
+            footprint: self.footprint.inset(LOT_LINE_BREADTH / 2.0),
+            kind: self.yard,
+        };
+
+        yard.render(offset, scale, canvas);
+
+        if let Some(driveway) = self.driveway {
+            let region = driveway.translate(offset).scale(scale);
+            let paving = canvas.palette.rgba(Surface::Paving);
+            canvas.paint(region, paving, Material::Concrete);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn block() -> Rectangle {
+        Rectangle::new(Vector2 { x: 0.0, y: 0.0 }, Vector2 { x: 100.0, y: 60.0 })
+    }
+
+    #[test]
+    fn every_lot_keeps_frontage() {
+        let mut max_areas = std::iter::repeat(300.0);
+        let mut splits = (0..).map(|i| (i as f64 * 0.618_034).fract());
+        let parcels = subdivide(block(), CourtyardKind::Garden, 6.0, &mut max_areas, &mut splits);
+
+        assert!(parcels.len() > 4);
+        let total: f64 = parcels
+            .iter()
+            .map(|parcel| parcel.footprint.width() * parcel.footprint.height())
+            .sum();
+        assert!((total - 6000.0).abs() < 1e-6);
+
+        for parcel in parcels.iter() {
+            assert!(parcel.frontage.iter().any(|&street| street));
+
+            // Frontage is only ever claimed on the block's own edges
+            let footprint = parcel.footprint;
+            let on_edge = [
+                footprint.start().y == 0.0,
+                footprint.end().x == 100.0,
+                footprint.end().y == 60.0,
+                footprint.start().x == 0.0,
+            ];
+            for (street, edge) in parcel.frontage.iter().zip(on_edge) {
+                assert!(!street || edge);
+            }
+        }
+    }
+
+    #[test]
+    fn buildings_leave_backyards() {
+        let parcel = Parcel {
+            footprint: Rectangle::new(Vector2 { x: 0.0, y: 0.0 }, Vector2 { x: 10.0, y: 40.0 }),
+            frontage: [true, false, false, false],
+            yard: CourtyardKind::Garden,
+            driveway: None,
+        };
+
+        let building = parcel.building(3.0, 1.0, 15.0).unwrap();
+        assert!((building.start() - Vector2 { x: 1.0, y: 3.0 }).mag() < 1e-9);
+        assert!((building.end() - Vector2 { x: 9.0, y: 18.0 }).mag() < 1e-9);
+        assert!(parcel.building(3.0, 4.0, 15.0).is_none());
+    }
+
+    #[test]
+    fn driveways_run_in_from_the_street() {
+        let footprint = Rectangle::new(Vector2 { x: 10.0, y: 20.0 }, Vector2 { x: 30.0, y: 50.0 });
+        for side in 0..4 {
+            let mut frontage = [false; 4];
+            frontage[side] = true;
+            let parcel = Parcel {
+                footprint,
+                frontage,
+                yard: CourtyardKind::Garden,
+                driveway: None,
+            };
+
+            let building = parcel.building(6.0, 1.5, 12.0).unwrap();
+            let driveway = parcel.driveway(building).unwrap();
+            let inside = driveway.intersection(&footprint).unwrap();
+            assert!((inside.dimensions() - driveway.dimensions()).mag() < 1e-9);
+            assert!(driveway.intersection(&building).is_none());
+
+            // The driveway meets the street
+            let on_street = [
+                driveway.start().y == footprint.start().y,
+                driveway.end().x == footprint.end().x,
+                driveway.end().y == footprint.end().y,
+                driveway.start().x == footprint.start().x,
+            ];
+            assert!(on_street[side]);
+        }
+    }
+}
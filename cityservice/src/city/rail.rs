@@ -0,0 +1,341 @@
+use std::f64::consts::TAU;
+
+use super::channels::{Canvas, Label, Material};
+use super::geom::*;
+use super::objects::{Bridge, Renderable, Waterway};
+use super::palette::Surface;
+
+/// The length of the slices a corridor is laid out in, short enough
+/// for a gentle curve to look smooth
+pub const RAIL_SLICE_LENGTH: f64 = 4.0;
+
+/// The distance between the centres of neighbouring tracks
+pub const TRACK_SPACING: f64 = 4.5;
+
+/// How far the ballast reaches out beyond the outermost tracks
+const BALLAST_SHOULDER: f64 = 2.2;
+
+/// The distance between the rails of a track. This is standard gauge.
+pub const GAUGE: f64 = 1.435;
+
+/// Rails are drawn wider than they are, so they still show up at
+/// coarse resolutions
+pub const RAIL_BREADTH: f64 = 0.3;
+
+/// The length of a sleeper across the track, its breadth along it,
+/// and the spacing between them
+const SLEEPER_LENGTH: f64 = 2.6;
+const SLEEPER_BREADTH: f64 = 0.4;
+const SLEEPER_SPACING: f64 = 1.0;
+
+/// The breadth of the platforms either side of the tracks at a
+/// station, and how far they stand above the rails
+pub const PLATFORM_BREADTH: f64 = 6.0;
+pub const PLATFORM_HEIGHT: f64 = 1.0;
+
+/// The height of the canopies over the platforms
+const CANOPY_HEIGHT: f64 = 4.5;
+
+/// The breadth of the ballast bed for a number of tracks, without any
+/// platforms
+pub fn bed_breadth(tracks: u32) -> f64 {
+    (tracks.max(1) - 1) as f64 * TRACK_SPACING + 2.0 * BALLAST_SHOULDER
+}
+
+/// Paints a thin strip, at least a pixel across, so rails and
+/// sleepers don't drop out between pixels
+fn paint_strip(canvas: &mut Canvas, region: Rectangle, color: image::Rgba<u8>, material: Material) {
+    let (start, mut end) = (region.start(), region.end());
+    end.x = end.x.max(start.x.floor() + 1.0);
+    end.y = end.y.max(start.y.floor() + 1.0);
+    canvas.paint(Rectangle::new(start, end), color, material);
+}
+
+/// A station along a railway, with platforms either side of the
+/// tracks
+#[derive(Debug)]
+pub struct Station {
+    /// The bounds of the station, across the whole corridor
+    pub area: Rectangle,
+    /// The slices of platform, following the curve of the tracks
+    pub platforms: Vec<Rectangle>,
+    pub flows_along_x: bool,
+    /// The height of the ground it's on
+    pub elevation: f64,
+}
+
+impl Renderable for Station {
+    fn render(&self, offset: Vector2, scale: Vector2, canvas: &mut Canvas) {
+        let platform_color = canvas.palette.rgba(Surface::Platforms);
+        let edge_color = canvas.palette.rgba(Surface::PlatformEdges);
+        let canopy_color = canvas.palette.rgba(Surface::Canopies);
+
+        // Work in a frame where the tracks run along x
+        let to_frame = |rectangle: Rectangle| if self.flows_along_x { rectangle } else { rectangle.transpose() };
+        let middle = to_frame(self.area).centre().y;
+
+        for platform in self.platforms.iter() {
+            let region = platform.translate(offset);
+            canvas.paint(region.scale(scale), platform_color, Material::Concrete);
+            canvas.raise(region.scale(scale), self.elevation + PLATFORM_HEIGHT);
+
+            // A safety line along the edge facing the tracks, and a
+            // canopy down the middle
+            let frame = to_frame(*platform);
+            let track_side = if frame.centre().y < middle { frame.end().y - 0.6 } else { frame.start().y };
+            let edge = Rectangle::new(
+                Vector2 { x: frame.start().x, y: track_side },
+                Vector2 { x: frame.end().x, y: track_side + 0.6 },
+            );
+            let canopy = Rectangle::new(
+                Vector2 { x: frame.start().x, y: frame.start().y + frame.height() * 0.25 },
+                Vector2 { x: frame.end().x, y: frame.end().y - frame.height() * 0.25 },
+            );
+
+            let edge = to_frame(edge).translate(offset).scale(scale);
+            paint_strip(canvas, edge, edge_color, Material::Concrete);
+
+            let canopy = to_frame(canopy).translate(offset).scale(scale);
+            canvas.paint(canopy, canopy_color, Material::Metal);
+            canvas.raise(canopy, self.elevation + CANOPY_HEIGHT);
+        }
+    }
+}
+
+/// A railway line running through the city. The corridor is cut out
+/// the same way as a waterway's channel, as slices along the line,
+/// with the verges either side taking the place of the quays.
+#[derive(Debug)]
+pub struct Railway {
+    pub bed: Waterway,
+    pub tracks: u32,
+    /// Where roads cross the tracks at grade
+    pub crossings: Vec<Rectangle>,
+    pub stations: Vec<Station>,
+    /// The slices of the corridor carried over water, their railings
+    /// run along the line
+    pub bridges: Vec<Bridge>,
+}
+
+impl Railway {
+    /// Carries the line over the waterways on bridges, wherever a
+    /// slice of the corridor runs into the water or its quays
+    pub fn bridge_over(&mut self, waterways: &[Waterway], railing_breadth: f64) {
+        self.bridges = self.bed.channel
+            .iter()
+            .filter(|slice| waterways.iter().any(|waterway| !waterway.is_clear_of(slice)))
+            .map(|&deck| Bridge { deck, railing_breadth })
+            .collect();
+    }
+
+    /// Paints the tracks over part of a slice of the bed, with the
+    /// sleepers and ballast under them unless it's a crossing
+    fn render_tracks(
+        &self,
+        slice: Rectangle,
+        part: Rectangle,
+        bed: bool,
+        offset: Vector2,
+        scale: Vector2,
+        canvas: &mut Canvas,
+    ) {
+        let along_x = self.bed.flows_along_x;
+        let to_frame = |rectangle: Rectangle| if along_x { rectangle } else { rectangle.transpose() };
+        let from_frame = |rectangle: Rectangle| to_frame(rectangle).translate(offset).scale(scale);
+        let (slice, part) = (to_frame(slice), to_frame(part));
+
+        let rail_color = canvas.palette.rgba(Surface::Rails);
+        let sleeper_color = canvas.palette.rgba(Surface::Sleepers);
+        let middle = slice.centre().y;
+        let across = |from: f64, to: f64| Rectangle::new(
+            Vector2 { x: part.start().x, y: middle + from },
+            Vector2 { x: part.end().x, y: middle + to },
+        );
+
+        for track in 0..self.tracks {
+            let centre = (track as f64 - (self.tracks - 1) as f64 / 2.0) * TRACK_SPACING;
+
+            // Sleepers sit at fixed spacings along the line, so they
+            // carry on evenly from one slice to the next
+            if bed {
+                let mut along = (part.start().x / SLEEPER_SPACING).ceil() * SLEEPER_SPACING;
+                while along + SLEEPER_BREADTH <= part.end().x {
+                    let sleeper = Rectangle::new(
+                        Vector2 { x: along, y: middle + centre - SLEEPER_LENGTH / 2.0 },
+                        Vector2 { x: along + SLEEPER_BREADTH, y: middle + centre + SLEEPER_LENGTH / 2.0 },
+                    );
+                    paint_strip(canvas, from_frame(sleeper), sleeper_color, Material::Concrete);
+                    along += SLEEPER_SPACING;
+                }
+            }
+
+            for side in [-1.0, 1.0] {
+                let rail = centre + side * GAUGE / 2.0;
+                let rail = across(rail - RAIL_BREADTH / 2.0, rail + RAIL_BREADTH / 2.0);
+                paint_strip(canvas, from_frame(rail), rail_color, Material::Metal);
+            }
+        }
+    }
+}
+
+impl Renderable for Railway {
+    fn render(&self, offset: Vector2, scale: Vector2, canvas: &mut Canvas) {
+        let ballast = canvas.palette.rgba(Surface::Ballast);
+        let verge = canvas.palette.rgba(Surface::Verge);
+        let asphalt = canvas.palette.rgba(Surface::Asphalt);
+
+        for rectangle in self.bed.quays.iter() {
+            let region = rectangle.translate(offset).scale(scale);
+            canvas.paint(region, verge, Material::Concrete);
+            canvas.label(region, Label::Rail);
+        }
+
+        for slice in self.bed.channel.iter() {
+            let region = slice.translate(offset).scale(scale);
+            canvas.paint(region, ballast, Material::Concrete);
+            canvas.label(region, Label::Rail);
+            self.render_tracks(*slice, *slice, true, offset, scale, canvas);
+        }
+
+        // The ballast carries on over bridges, between railings
+        // either side of the line
+        let railing = canvas.palette.rgba(Surface::BridgeRailing);
+        for bridge in self.bridges.iter() {
+            let inset = if self.bed.flows_along_x {
+                Vector2 { x: 0.0, y: bridge.railing_breadth }
+            } else {
+                Vector2 { x: bridge.railing_breadth, y: 0.0 }
+            };
+            let deck = Rectangle::new(bridge.deck.start() + inset, bridge.deck.end() - inset);
+
+            canvas.paint(bridge.deck.translate(offset).scale(scale), railing, Material::Concrete);
+            canvas.paint(deck.translate(offset).scale(scale), ballast, Material::Concrete);
+            self.render_tracks(bridge.deck, bridge.deck, true, offset, scale, canvas);
+        }
+
+        // Roads crossing at grade are paved right up to the rails
+        for crossing in self.crossings.iter() {
+            canvas.paint(crossing.translate(offset).scale(scale), asphalt, Material::Asphalt);
+            for slice in self.bed.channel.iter() {
+                if let Some(part) = slice.intersection(crossing) {
+                    self.render_tracks(*slice, part, false, offset, scale, canvas);
+                }
+            }
+        }
+
+        for station in self.stations.iter() {
+            station.render(offset, scale, canvas);
+        }
+    }
+}
+
+/// Lays out the slices of a corridor running along x the whole length
+/// of the city, curving gently about the middle. The corridor widens
+/// to take platforms wherever it passes through one of the stations,
+/// given as spans along x. Returns the slices, and the platforms of
+/// each station.
+pub fn corridor(
+    length: f64,
+    middle: f64,
+    (amplitude, wavelength, phase): (f64, f64, f64),
+    tracks: u32,
+    stations: &[(f64, f64)],
+) -> (Vec<Rectangle>, Vec<Vec<Rectangle>>) {
+    let half_bed = bed_breadth(tracks) / 2.0;
+    let slice_count = (length / RAIL_SLICE_LENGTH).ceil() as u32;
+
+    let mut slices = Vec::new();
+    let mut platforms = vec![Vec::new(); stations.len()];
+    for i in 0..slice_count {
+        let x = i as f64 * RAIL_SLICE_LENGTH;
+        let end = f64::min(x + RAIL_SLICE_LENGTH, length);
+        let centre = middle + amplitude * (TAU * x / wavelength.max(1.0) + phase).sin();
+
+        let station = stations
+            .iter()
+            .position(|&(start, stop)| x >= start && end <= stop);
+        let half_breadth = match station {
+            Some(_) => half_bed + PLATFORM_BREADTH,
+            None => half_bed,
+        };
+
+        slices.push(Rectangle::new(
+            Vector2 { x, y: centre - half_breadth },
+            Vector2 { x: end, y: centre + half_breadth },
+        ));
+
+        if let Some(station) = station {
+            platforms[station].push(Rectangle::new(
+                Vector2 { x, y: centre - half_breadth },
+                Vector2 { x: end, y: centre - half_bed },
+            ));
+            platforms[station].push(Rectangle::new(
+                Vector2 { x, y: centre + half_bed },
+                Vector2 { x: end, y: centre + half_breadth },
+            ));
+        }
+    }
+
+    (slices, platforms)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn corridor_widens_for_platforms() {
+        let stations = [(100.0, 160.0)];
+        let (slices, platforms) = corridor(400.0, 200.0, (10.0, 800.0, 0.0), 2, &stations);
+
+        assert_eq!(slices.len(), 100);
+        assert_eq!(platforms[0].len(), 2 * 15);
+        for slice in slices.iter() {
+            let in_station = slice.start().x >= 100.0 && slice.end().x <= 160.0;
+            let breadth = bed_breadth(2) + if in_station { 2.0 * PLATFORM_BREADTH } else { 0.0 };
+            assert!((slice.height() - breadth).abs() < 1e-9);
+        }
+
+        // The platforms stay inside the corridor, clear of the ballast
+        for platform in platforms[0].iter() {
+            let slice = slices.iter().find(|slice| slice.start().x == platform.start().x).unwrap();
+            let inside = platform.intersection(slice).unwrap();
+            assert!((inside.dimensions() - platform.dimensions()).mag() < 1e-9);
+            assert!((platform.height() - PLATFORM_BREADTH).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn lines_bridge_waterways() {
+        // A line running east across a canal flowing south
+        let (slices, _) = corridor(200.0, 50.0, (0.0, 800.0, 0.0), 2, &[]);
+        let mut railway = Railway {
+            bed: Waterway {
+                channel: slices,
+                quays: Vec::new(),
+                quay_breadth: 3.0,
+                flows_along_x: true,
+                bridged: true,
+            },
+            tracks: 2,
+            crossings: Vec::new(),
+            stations: Vec::new(),
+            bridges: Vec::new(),
+        };
+        let canal = Waterway {
+            channel: vec![Rectangle::new(Vector2 { x: 90.0, y: 0.0 }, Vector2 { x: 110.0, y: 100.0 })],
+            quays: Vec::new(),
+            quay_breadth: 2.0,
+            flows_along_x: false,
+            bridged: true,
+        };
+
+        railway.bridge_over(&[canal], 0.5);
+
+        // The bridge spans the water and its quays, and nothing else
+        let start = railway.bridges.iter().map(|bridge| bridge.deck.start().x).fold(f64::INFINITY, f64::min);
+        let end = railway.bridges.iter().map(|bridge| bridge.deck.end().x).fold(0.0, f64::max);
+        assert_eq!((start, end), (88.0, 112.0));
+        assert_eq!(railway.bridges.len(), 6);
+    }
+}
@@ -0,0 +1,140 @@
+use super::geom::Vector2;
+
+/// How far in from the eaves a mansard's steep lower slopes reach, as
+/// a share of the building's short span
+const MANSARD_BREAK: f64 = 0.2;
+
+/// How much steeper a mansard's lower slopes are than its pitch
+const MANSARD_STEEPNESS: f64 = 3.0;
+
+/// Faces of a roof are numbered from zero up to below this
+pub const FACES: usize = 5;
+
+/// The shape of a building's roof
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoofShape {
+    Flat,
+    /// Two slopes meeting at a ridge along the length of the building
+    Gabled,
+    /// Slopes on all four sides, meeting at a ridge and hip lines
+    Hipped,
+    /// A single slope across the building
+    Shed,
+    /// Steep slopes on all four sides around a flat top
+    Mansard,
+}
+
+impl RoofShape {
+    pub const ALL: [RoofShape; 5] = [
+        RoofShape::Flat,
+        RoofShape::Gabled,
+        RoofShape::Hipped,
+        RoofShape::Shed,
+        RoofShape::Mansard,
+    ];
+
+    /// The name of the shape as it appears in cityscript
+    pub fn name(self) -> &'static str {
+        match self {
+            RoofShape::Flat => "flat",
+            RoofShape::Gabled => "gabled",
+            RoofShape::Hipped => "hipped",
+            RoofShape::Shed => "shed",
+            RoofShape::Mansard => "mansard",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Roof {
+    pub shape: RoofShape,
+    /// The slope of the roof as rise over run
+    pub pitch: f64,
+}
+
+impl Roof {
+    /// The height of the roof above the eaves at a point on a
+    /// footprint of the given size (relative to the footprint's
+    /// start), along with a number for the face the point is on.
+    /// Ridges and hips run wherever the face changes.
+    pub fn surface(&self, size: Vector2, point: Vector2) -> (f64, usize) {
+        // Work in a frame where the ridge runs along x
+        let (size, point) = if size.y > size.x {
+            (size.transpose(), point.transpose())
+        } else {
+            (size, point)
+        };
+
+        // Distances to the long sides, then to the ends
+        let distances = [point.y, size.y - point.y, point.x, size.x - point.x];
+        let nearest = |sides: &[f64]| {
+            sides
+                .iter()
+                .enumerate()
+                .fold((f64::INFINITY, 0), |(best, face), (index, &distance)| {
+                    if distance < best { (distance, index) } else { (best, face) }
+                })
+        };
+
+        match self.shape {
+            RoofShape::Flat => (0.0, 0),
+            RoofShape::Shed => (self.pitch * point.y, 0),
+            RoofShape::Gabled => {
+                let (distance, face) = nearest(&distances[..2]);
+                (self.pitch * distance, face)
+            },
+            RoofShape::Hipped => {
+                let (distance, face) = nearest(&distances);
+                (self.pitch * distance, face)
+            },
+            RoofShape::Mansard => {
+                let steepness = self.pitch * MANSARD_STEEPNESS;
+                let break_distance = size.y * MANSARD_BREAK;
+                let (distance, face) = nearest(&distances);
+                if distance < break_distance {
+                    (steepness * distance, face)
+                } else {
+                    (steepness * break_distance, distances.len())
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SIZE: Vector2 = Vector2 { x: 20.0, y: 10.0 };
+
+    #[test]
+    fn gable_peaks_along_the_middle() {
+        let roof = Roof { shape: RoofShape::Gabled, pitch: 0.5 };
+        let (ridge, _) = roof.surface(SIZE, Vector2 { x: 2.0, y: 5.0 });
+        let (eaves, _) = roof.surface(SIZE, Vector2 { x: 2.0, y: 0.0 });
+        assert!((ridge - 2.5).abs() < 1e-9);
+        assert!(eaves.abs() < 1e-9);
+
+        let (_, north) = roof.surface(SIZE, Vector2 { x: 10.0, y: 4.0 });
+        let (_, south) = roof.surface(SIZE, Vector2 { x: 10.0, y: 6.0 });
+        assert_ne!(north, south);
+    }
+
+    #[test]
+    fn hips_slope_down_to_the_ends() {
+        let roof = Roof { shape: RoofShape::Hipped, pitch: 0.5 };
+        let (end, _) = roof.surface(SIZE, Vector2 { x: 1.0, y: 5.0 });
+        let (middle, _) = roof.surface(SIZE, Vector2 { x: 10.0, y: 5.0 });
+        assert!((end - 0.5).abs() < 1e-9);
+        assert!((middle - 2.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ridge_follows_the_long_side() {
+        // The same roof stood on end
+        let roof = Roof { shape: RoofShape::Gabled, pitch: 0.5 };
+        let size = SIZE.transpose();
+        let (ridge, _) = roof.surface(size, Vector2 { x: 5.0, y: 2.0 });
+        assert!((ridge - 2.5).abs() < 1e-9);
+    }
+}
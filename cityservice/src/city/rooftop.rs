@@ -0,0 +1,275 @@
+use super::channels::{Canvas, Material};
+use super::geom::*;
+use super::objects::Renderable;
+use super::palette::Surface;
+
+/// How many spots are tried for each piece of equipment before
+/// giving up on it, on a crowded roof
+pub const PLACEMENT_ATTEMPTS: usize = 8;
+
+/// The random numbers used up by each placement attempt: the two
+/// coordinates, the size, and whether it's turned sideways
+pub const SAMPLES_PER_ATTEMPT: usize = 4;
+
+/// The gap kept clear between pieces of equipment, in meters
+const CLEARANCE: f64 = 0.5;
+
+/// The depth of a row of solar panels and the gap between rows
+const SOLAR_ROW: f64 = 1.0;
+const SOLAR_GAP: f64 = 0.4;
+
+/// The things found on a flat roof
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RooftopKind {
+    /// An air conditioning box, with a fan or two on top
+    Hvac,
+    Skylight,
+    /// The housing at the top of a stairwell
+    Bulkhead,
+    WaterTower,
+    /// A rack of solar panels in rows
+    Solar,
+}
+
+impl RooftopKind {
+    /// Largest first, so the big things get their pick of the roof
+    pub const ALL: [RooftopKind; 5] = [
+        RooftopKind::Solar,
+        RooftopKind::Bulkhead,
+        RooftopKind::WaterTower,
+        RooftopKind::Hvac,
+        RooftopKind::Skylight,
+    ];
+
+    /// The name of the kind as it appears in cityscript
+    pub fn name(self) -> &'static str {
+        match self {
+            RooftopKind::Hvac => "hvac",
+            RooftopKind::Skylight => "skylights",
+            RooftopKind::Bulkhead => "bulkheads",
+            RooftopKind::WaterTower => "watertowers",
+            RooftopKind::Solar => "solar",
+        }
+    }
+
+    /// The smallest and largest footprint, in meters
+    fn size_range(self) -> (Vector2, Vector2) {
+        let (min, max) = match self {
+            RooftopKind::Hvac => ((2.0, 1.5), (4.0, 3.0)),
+            RooftopKind::Skylight => ((1.5, 1.0), (4.0, 2.5)),
+            RooftopKind::Bulkhead => ((3.0, 2.5), (5.0, 3.5)),
+            // Towers are round, so they're square in plan
+            RooftopKind::WaterTower => ((3.0, 3.0), (6.0, 6.0)),
+            RooftopKind::Solar => ((4.0, 3.0), (12.0, 8.0)),
+        };
+
+        (Vector2 { x: min.0, y: min.1 }, Vector2 { x: max.0, y: max.1 })
+    }
+
+    /// How far it stands above the roof, in meters
+    fn height(self) -> f64 {
+        match self {
+            RooftopKind::Hvac => 1.5,
+            RooftopKind::Skylight => 0.3,
+            RooftopKind::Bulkhead => 2.8,
+            RooftopKind::WaterTower => 7.0,
+            RooftopKind::Solar => 0.6,
+        }
+    }
+}
+
+/// A piece of equipment standing on a roof
+#[derive(Clone, Debug)]
+pub struct Equipment {
+    pub kind: RooftopKind,
+    /// In the same frame as the footprint of the building it's on
+    pub footprint: Rectangle,
+    /// The height of the roof it stands on
+    pub elevation: f64,
+}
+
+/// Scatters equipment over a roof without any of it overlapping,
+/// taking random numbers in [0, 1] from the samples as it goes. Each
+/// piece gets a few attempts at finding a clear spot, and is left off
+/// if it can't find one.
+pub fn scatter(
+    roof: Rectangle,
+    counts: &[(RooftopKind, u32)],
+    elevation: f64,
+    samples: &mut impl Iterator<Item = f64>,
+) -> Vec<Equipment> {
+    let mut placed: Vec<Equipment> = Vec::new();
+
+    for &(kind, count) in counts {
+        for _ in 0..count {
+            for _ in 0..PLACEMENT_ATTEMPTS {
+                let mut sample = || samples.next().unwrap_or(0.5);
+                let (x, y, size, sideways) = (sample(), sample(), sample(), sample());
+
+                let (min, max) = kind.size_range();
+                let mut dimensions = min + (max - min) * size;
+                if sideways < 0.5 {
+                    dimensions = dimensions.transpose();
+                }
+
+                let room = roof.dimensions() - dimensions;
+                if room.x < 0.0 || room.y < 0.0 {
+                    continue;
+                }
+
+                let start = roof.start() + room * Vector2 { x, y };
+                let footprint = Rectangle::new(start, start + dimensions);
+                let clear = placed.iter().all(|other| {
+                    footprint.inset(-CLEARANCE).intersection(&other.footprint).is_none()
+                });
+
+                if clear {
+                    placed.push(Equipment {
+                        kind,
+                        footprint,
+                        elevation,
+                    });
+                    break;
+                }
+            }
+        }
+    }
+
+    placed
+}
+
+/// Paints and raises a circle (in meters, already offset) pixel by
+/// pixel
+fn paint_circle(
+    canvas: &mut Canvas,
+    circle: Circle,
+    scale: Vector2,
+    color: image::Rgba<u8>,
+    material: Material,
+    height: f64,
+) {
+    for pixel in circle.bounds().scale(scale).interior_int_coords() {
+        let centre = Vector2 {
+            x: (pixel.x as f64 + 0.5) / scale.x,
+            y: (pixel.y as f64 + 0.5) / scale.y,
+        };
+
+        if circle.contains(centre) {
+            canvas.paint_pixel(pixel.x, pixel.y, color, material);
+            canvas.heights.raise(pixel.x, pixel.y, height);
+        }
+    }
+}
+
+impl Renderable for Equipment {
+    fn render(&self, offset: Vector2, scale: Vector2, canvas: &mut Canvas) {
+        let footprint = self.footprint.translate(offset);
+        let region = footprint.scale(scale);
+        let top = self.elevation + self.kind.height();
+        let size = footprint.dimensions();
+        let short_side = size.x.min(size.y);
+
+        match self.kind {
+            RooftopKind::Hvac => {
+                let casing = canvas.palette.rgba(Surface::Hvac);
+                canvas.paint(region, casing, Material::Metal);
+                canvas.raise(region, top);
+
+                // Long units have room for two fans side by side
+                let fans = if size.x.max(size.y) >= 2.0 * short_side { 2 } else { 1 };
+                let (along, across) = if size.x >= size.y {
+                    (Vector2 { x: size.x / fans as f64, y: 0.0 }, Vector2 { x: 0.0, y: size.y / 2.0 })
+                } else {
+                    (Vector2 { x: 0.0, y: size.y / fans as f64 }, Vector2 { x: size.x / 2.0, y: 0.0 })
+                };
+
+                for fan in 0..fans {
+                    let centre = footprint.start() + across + along * (fan as f64 + 0.5);
+                    let radius = short_side * 0.4;
+                    let blades = canvas.palette.rgba(Surface::Fans);
+                    let hub = canvas.palette.rgba(Surface::Hubs);
+                    paint_circle(canvas, Circle::new(centre, radius), scale, blades, Material::Metal, top);
+                    paint_circle(canvas, Circle::new(centre, radius * 0.3), scale, hub, Material::Metal, top);
+                }
+            },
+            RooftopKind::Skylight => {
+                let frame = canvas.palette.rgba(Surface::SkylightFrames);
+                let glass = canvas.palette.rgba(Surface::Skylights);
+                canvas.paint(region, frame, Material::Metal);
+                canvas.paint(footprint.inset(0.2).scale(scale), glass, Material::Glass);
+                canvas.raise(region, top);
+            },
+            RooftopKind::Bulkhead => {
+                let walls = canvas.palette.rgba(Surface::Bulkheads);
+                canvas.paint(region, walls, Material::Concrete);
+                canvas.raise(region, top);
+            },
+            RooftopKind::WaterTower => {
+                // A wooden tank bound with a dark rim
+                let tank = Circle::new(footprint.centre(), short_side / 2.0);
+                let rim = canvas.palette.rgba(Surface::Tanks);
+                let lid = canvas.palette.rgba(Surface::Lids);
+                paint_circle(canvas, tank, scale, rim, Material::Concrete, top);
+                let lid_circle = Circle::new(tank.center, tank.radius * 0.8);
+                paint_circle(canvas, lid_circle, scale, lid, Material::Concrete, top);
+            },
+            RooftopKind::Solar => {
+                let rack = canvas.palette.rgba(Surface::SolarRacks);
+                let cells = canvas.palette.rgba(Surface::SolarPanels);
+                canvas.paint(region, rack, Material::Metal);
+                canvas.raise(region, top);
+
+                // Rows run along the long side of the rack
+                let along_x = size.x >= size.y;
+                let depth = if along_x { size.y } else { size.x };
+                let mut row_start = SOLAR_GAP / 2.0;
+                while row_start + SOLAR_ROW <= depth {
+                    let row = if along_x {
+                        Rectangle::new(
+                            footprint.start() + Vector2 { x: 0.0, y: row_start },
+                            footprint.start() + Vector2 { x: size.x, y: row_start + SOLAR_ROW },
+                        )
+                    } else {
+                        Rectangle::new(
+                            footprint.start() + Vector2 { x: row_start, y: 0.0 },
+                            footprint.start() + Vector2 { x: row_start + SOLAR_ROW, y: size.y },
+                        )
+                    };
+
+                    canvas.paint(row.scale(scale), cells, Material::Glass);
+                    row_start += SOLAR_ROW + SOLAR_GAP;
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn equipment_never_overlaps() {
+        let roof = Rectangle::new(Vector2 { x: 0.0, y: 0.0 }, Vector2 { x: 20.0, y: 15.0 });
+        let counts = [(RooftopKind::Solar, 2), (RooftopKind::Hvac, 10)];
+        let mut samples = (0..).map(|i| (i as f64 * 0.618_034).fract());
+        let placed = scatter(roof, &counts, 10.0, &mut samples);
+
+        assert!(!placed.is_empty());
+        for (i, first) in placed.iter().enumerate() {
+            let inside = first.footprint.intersection(&roof).unwrap();
+            assert!((inside.dimensions() - first.footprint.dimensions()).mag() < 1e-9);
+            for second in placed.iter().skip(i + 1) {
+                assert!(first.footprint.intersection(&second.footprint).is_none());
+            }
+        }
+    }
+
+    #[test]
+    fn nothing_fits_on_a_tiny_roof() {
+        let roof = Rectangle::new(Vector2 { x: 0.0, y: 0.0 }, Vector2 { x: 1.0, y: 1.0 });
+        let counts = [(RooftopKind::Bulkhead, 3)];
+        let mut samples = std::iter::repeat(0.5);
+        assert!(scatter(roof, &counts, 0.0, &mut samples).is_empty());
+    }
+}
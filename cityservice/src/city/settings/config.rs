@@ -4,6 +4,12 @@ use super::Settings;
 // This could happen if one of the settings doesn't exist, or is the wrong type.
 use super::Error as Error;
 use super::super::stats::{Distribution, Distribution2};
+use super::super::palette::{Palette, Surface, Theme, ROOF_SLOTS};
+use super::super::{FillerKind, FootprintShape, LandmarkKind, RooftopKind, RoofShape, VehicleKind, Zone};
+
+/// Every epoch of a time-lapse is a whole render of the city, so
+/// there are never more than this many
+const MAX_GROWTH_EPOCHS: u32 = 60;
 
 pub struct RoadConfig {
     pub density: Distribution2,
@@ -33,33 +39,346 @@ pub struct BuildingConfig {
     pub stepbacks: Distribution,
     pub roof_border: Distribution,
     pub roof_tint: Distribution,
+    /// The weight of each roof shape
+    pub roof_shapes: Vec<(RoofShape, f64)>,
+    pub roof_pitch: Distribution,
+    /// The weight of each footprint shape
+    pub footprint_shapes: Vec<(FootprintShape, f64)>,
+    /// The breadth of the wings of a shaped footprint, as a share of
+    /// the short span of its lot
+    pub footprint_wing: Distribution,
+    /// How many of each kind of rooftop equipment there are per
+    /// 1000 square meters of flat roof
+    pub rooftops: Vec<(RooftopKind, f64)>,
+    pub floors: Distribution,
+    pub storey_height: f64,
+    pub height_falloff: f64,
+    /// The most rings of buildings a block is filled with
+    pub rings: f64,
+    /// The chance of a building in the middle of a block, if there's
+    /// room left for one inside the rings
+    pub inner_chance: f64,
+    /// What the open ground inside the rings is laid out as, either
+    /// "garden" or "paved"
+    pub courtyards: String,
+    pub lots: LotConfig,
+    /// The chance of a block (or of a lot, on a subdivided block)
+    /// being left unbuilt, for each kind of filler
+    pub fillers: Vec<(FillerKind, f64)>,
+    /// The share of parking bays with a car in them
+    pub parking_occupancy: f64,
 }
 
-impl TryFrom<&Settings> for BuildingConfig {
-    type Error = Error;
+/// How a block is cut up into lots, when it's subdivided rather than
+/// lined along its faces
+pub struct LotConfig {
+    /// Either "faces" or "subdivide"
+    pub strategy: String,
+    /// The largest area a lot may have before it's split, in square
+    /// meters
+    pub area: Distribution,
+    /// The narrowest a lot may be along the street, in meters
+    pub frontage: f64,
+    pub front_setback: Distribution,
+    pub side_setback: Distribution,
+}
+
+impl BuildingConfig {
+    /// Reads the building settings under a base path, the base path
+    /// is empty for the city wide settings, or the path of a zone.
+    pub fn try_from_settings(
+        settings: &Settings,
+        base_path: Vec<&str>,
+    ) -> Result<Self, Error> {
+        let path = |rest: &[&'static str]| {
+            let mut path = base_path.clone();
+            path.extend_from_slice(rest);
+            path
+        };
+
+        let mut roof_shapes = Vec::new();
+        for shape in RoofShape::ALL {
+            let weight = settings.get(path(&["buildings", "roof", "shapes", shape.name()]))?;
+            roof_shapes.push((shape, weight));
+        }
+
+        let mut footprint_shapes = Vec::new();
+        for shape in FootprintShape::ALL {
+            let weight = settings.get(path(&["buildings", "footprints", shape.name()]))?;
+            footprint_shapes.push((shape, weight));
+        }
+
+        let mut fillers = Vec::new();
+        for kind in FillerKind::ALL {
+            let chance = settings.get(path(&["buildings", "fillers", kind.name()]))?;
+            fillers.push((kind, chance));
+        }
+
+        let mut rooftops = Vec::new();
+        for kind in RooftopKind::ALL {
+            let density = settings.get(path(&["buildings", "rooftops", kind.name()]))?;
+            rooftops.push((kind, density));
+        }
 
-    fn try_from(settings: &Settings) -> Result<Self, Error> {
         Ok(Self {
             density: Distribution2::try_from_settings(
                 settings,
-                vec!["buildings", "density"],
+                path(&["buildings", "density"]),
             )?,
             spacing: Distribution::try_from_settings(
                 settings,
-                vec!["alleys", "breadth"],
+                path(&["alleys", "breadth"]),
             )?,
             stepbacks: Distribution::try_from_settings(
                 settings,
-                vec!["buildings", "walls", "stepback"],
+                path(&["buildings", "walls", "stepback"]),
             )?,
             roof_border: Distribution::try_from_settings(
                 settings,
-                vec!["buildings", "roof", "border"],
+                path(&["buildings", "roof", "border"]),
             )?,
             roof_tint: Distribution::try_from_settings(
                 settings,
-                vec!["buildings", "roof", "tint"],
+                path(&["buildings", "roof", "tint"]),
             )?,
+            roof_shapes,
+            footprint_shapes,
+            footprint_wing: Distribution::try_from_settings(
+                settings,
+                path(&["buildings", "footprints", "wing"]),
+            )?,
+            rooftops,
+            roof_pitch: Distribution::try_from_settings(
+                settings,
+                path(&["buildings", "roof", "pitch"]),
+            )?,
+            floors: Distribution::try_from_settings(
+                settings,
+                path(&["buildings", "floors"]),
+            )?,
+            storey_height: settings.get(path(&["buildings", "storey", "height"]))?,
+            height_falloff: settings.get(path(&["buildings", "height", "falloff"]))?,
+            rings: settings.get(path(&["buildings", "rings"]))?,
+            inner_chance: settings.get(path(&["buildings", "inner", "chance"]))?,
+            courtyards: settings.get(path(&["buildings", "courtyards"]))?,
+            fillers,
+            parking_occupancy: settings.get(path(&["buildings", "parking", "occupancy"]))?,
+            lots: LotConfig {
+                strategy: settings.get(path(&["buildings", "lots", "strategy"]))?,
+                area: Distribution::try_from_settings(
+                    settings,
+                    path(&["buildings", "lots", "area"]),
+                )?,
+                frontage: settings.get(path(&["buildings", "lots", "frontage"]))?,
+                front_setback: Distribution::try_from_settings(
+                    settings,
+                    path(&["buildings", "lots", "setback", "front"]),
+                )?,
+                side_setback: Distribution::try_from_settings(
+                    settings,
+                    path(&["buildings", "lots", "setback", "side"]),
+                )?,
+            },
+        })
+    }
+}
+
+impl TryFrom<&Settings> for BuildingConfig {
+    type Error = Error;
+
+    fn try_from(settings: &Settings) -> Result<Self, Error> {
+        Self::try_from_settings(settings, Vec::new())
+    }
+}
+
+pub struct ZoneConfig {
+    pub zone: Zone,
+    pub weight: f64,
+    pub centrality: f64,
+    /// Parks aren't built on, so they have no building settings
+    pub buildings: Option<BuildingConfig>,
+}
+
+pub struct ZonesConfig {
+    pub mode: String,
+    pub spread: f64,
+    pub zones: Vec<ZoneConfig>,
+}
+
+impl ZonesConfig {
+    pub fn get(&self, zone: Zone) -> &ZoneConfig {
+        self.zones
+            .iter()
+            .find(|config| config.zone == zone)
+            .unwrap_or_else(|| unreachable!("Every zone has a config"))
+    }
+}
+
+impl TryFrom<&Settings> for ZonesConfig {
+    type Error = Error;
+
+    fn try_from(settings: &Settings) -> Result<Self, Error> {
+        let mut zones = Vec::new();
+        for zone in Zone::ALL {
+            let name = zone.name();
+            let buildings = if zone == Zone::Park {
+                None
+            } else {
+                Some(BuildingConfig::try_from_settings(settings, vec!["zones", name])?)
+            };
+
+            zones.push(ZoneConfig {
+                zone,
+                weight: settings.get(vec!["zones", name, "weight"])?,
+                centrality: settings.get(vec!["zones", name, "centrality"])?,
+                buildings,
+            });
+        }
+
+        Ok(Self {
+            mode: settings.get(vec!["zones", "mode"])?,
+            spread: settings.get(vec!["zones", "spread"])?,
+            zones,
+        })
+    }
+}
+
+pub struct WaterConfig {
+    pub kind: String,
+    pub flows_along_x: bool,
+    pub breadth: Distribution,
+    pub meander_amplitude: Distribution,
+    pub meander_wavelength: Distribution,
+    pub canal_count: f64,
+    pub quay_breadth: f64,
+    pub railing_breadth: f64,
+}
+
+impl TryFrom<&Settings> for WaterConfig {
+    type Error = Error;
+
+    fn try_from(settings: &Settings) -> Result<Self, Error> {
+        let axis: String = settings.get(vec!["water", "axis"])?;
+        Ok(Self {
+            kind: settings.get(vec!["water", "kind"])?,
+            flows_along_x: axis != "y",
+            breadth: Distribution::try_from_settings(
+                settings,
+                vec!["water", "breadth"],
+            )?,
+            meander_amplitude: Distribution::try_from_settings(
+                settings,
+                vec!["water", "meander", "amplitude"],
+            )?,
+            meander_wavelength: Distribution::try_from_settings(
+                settings,
+                vec!["water", "meander", "wavelength"],
+            )?,
+            canal_count: settings.get(vec!["water", "canals", "count"])?,
+            quay_breadth: settings.get(vec!["water", "quay", "breadth"])?,
+            railing_breadth: settings.get(vec!["bridges", "railing", "breadth"])?,
+        })
+    }
+}
+
+pub struct CoastConfig {
+    pub north: bool,
+    pub south: bool,
+    pub east: bool,
+    pub west: bool,
+    pub depth: Distribution,
+    pub roughness: f64,
+    pub wavelength: f64,
+    pub pier_density: Distribution,
+    pub pier_length: Distribution,
+    pub pier_breadth: Distribution,
+    pub pier_border: f64,
+}
+
+impl TryFrom<&Settings> for CoastConfig {
+    type Error = Error;
+
+    fn try_from(settings: &Settings) -> Result<Self, Error> {
+        Ok(Self {
+            north: settings.get(vec!["coast", "north"])?,
+            south: settings.get(vec!["coast", "south"])?,
+            east: settings.get(vec!["coast", "east"])?,
+            west: settings.get(vec!["coast", "west"])?,
+            depth: Distribution::try_from_settings(
+                settings,
+                vec!["coast", "depth"],
+            )?,
+            roughness: settings.get(vec!["coast", "shore", "roughness"])?,
+            wavelength: settings.get(vec!["coast", "shore", "wavelength"])?,
+            pier_density: Distribution::try_from_settings(
+                settings,
+                vec!["coast", "piers", "density"],
+            )?,
+            pier_length: Distribution::try_from_settings(
+                settings,
+                vec!["coast", "piers", "length"],
+            )?,
+            pier_breadth: Distribution::try_from_settings(
+                settings,
+                vec!["coast", "piers", "breadth"],
+            )?,
+            pier_border: settings.get(vec!["coast", "piers", "border"])?,
+        })
+    }
+}
+
+pub struct TerrainConfig {
+    pub vertical_scale: f64,
+    pub max_slope: f64,
+    pub max_bend: f64,
+}
+
+impl TryFrom<&Settings> for TerrainConfig {
+    type Error = Error;
+
+    fn try_from(settings: &Settings) -> Result<Self, Error> {
+        Ok(Self {
+            vertical_scale: settings.get(vec!["terrain", "height"])?,
+            max_slope: settings.get(vec!["terrain", "slope", "max"])?,
+            max_bend: settings.get(vec!["terrain", "bend", "max"])?,
+        })
+    }
+}
+
+pub struct LightingConfig {
+    pub sun_azimuth: f64,
+    pub sun_elevation: f64,
+    pub shadow_darkness: f64,
+    pub shadow_softness: f64,
+}
+
+impl TryFrom<&Settings> for LightingConfig {
+    type Error = Error;
+
+    fn try_from(settings: &Settings) -> Result<Self, Error> {
+        Ok(Self {
+            sun_azimuth: settings.get(vec!["lighting", "sun", "azimuth"])?,
+            sun_elevation: settings.get(vec!["lighting", "sun", "elevation"])?,
+            shadow_darkness: settings.get(vec!["lighting", "shadows", "darkness"])?,
+            shadow_softness: settings.get(vec!["lighting", "shadows", "softness"])?,
+        })
+    }
+}
+
+pub struct MaskConfig {
+    pub roads: bool,
+    pub buildings: bool,
+    pub colour_coded: bool,
+}
+
+impl TryFrom<&Settings> for MaskConfig {
+    type Error = Error;
+
+    fn try_from(settings: &Settings) -> Result<Self, Error> {
+        Ok(Self {
+            roads: settings.get(vec!["mask", "roads"])?,
+            buildings: settings.get(vec!["mask", "buildings"])?,
+            colour_coded: settings.get(vec!["mask", "channels"])?,
         })
     }
 }
@@ -68,16 +387,36 @@ pub struct CityConfig {
     pub width: f64,
     pub height: f64,
     pub sidewalk_breadth: f64,
+    /// How the streets are laid out, as a "grid", "suburban" streets,
+    /// or a grid core ringed by suburbs ("mixed")
+    pub layout: String,
+    /// The share of the city's width and height the grid core takes
+    /// up in the mixed layout
+    pub core: f64,
+    /// Seeds the textures, weather and growth, which are drawn from
+    /// noise rather than sampled
+    pub seed: u32,
 }
 
 impl TryFrom<&Settings> for CityConfig {
     type Error = Error;
 
     fn try_from(settings: &Settings) -> Result<Self, Error> {
+        let seed: f64 = settings.get(vec!["city", "seed"])?;
+        if !(0.0..=u32::MAX as f64).contains(&seed) {
+            return Err(Error::OutOfRange {
+                path: vec![String::from("city"), String::from("seed")],
+                expected: format!("between 0 and {}", u32::MAX),
+            });
+        }
+
         Ok(Self {
             width: settings.get(vec!["city", "width"])?,
             height: settings.get(vec!["city", "height"])?,
             sidewalk_breadth: settings.get(vec!["sidewalk", "breadth"])?,
+            layout: settings.get(vec!["city", "layout"])?,
+            core: settings.get(vec!["city", "core"])?,
+            seed: seed as u32,
         })
     }
 }
@@ -85,6 +424,8 @@ impl TryFrom<&Settings> for CityConfig {
 pub struct ImageConfig {
     pub width: u32,
     pub height: u32,
+    pub heightmap_scale: f64,
+    pub normal_strength: f64,
 }
 
 impl TryFrom<&Settings> for ImageConfig {
@@ -94,15 +435,316 @@ impl TryFrom<&Settings> for ImageConfig {
         Ok(Self {
             width: settings.get(vec!["image", "width"])?,
             height: settings.get(vec!["image", "height"])?,
+            heightmap_scale: settings.get(vec!["image", "heightmap", "scale"])?,
+            normal_strength: settings.get(vec!["image", "normal", "strength"])?,
+        })
+    }
+}
+
+pub struct LandmarkConfig {
+    pub kind: LandmarkKind,
+    pub count: f64,
+    /// The size of the landmark's footprint in meters, its length
+    /// runs along the longer side of the block it's on
+    pub length: f64,
+    pub breadth: f64,
+}
+
+pub struct LandmarksConfig {
+    pub landmarks: Vec<LandmarkConfig>,
+    /// The widest road that's taken out to merge two blocks into a
+    /// superblock for a landmark, in meters
+    pub max_merge_gap: f64,
+}
+
+impl TryFrom<&Settings> for LandmarksConfig {
+    type Error = Error;
+
+    fn try_from(settings: &Settings) -> Result<Self, Error> {
+        let mut landmarks = Vec::new();
+        for kind in LandmarkKind::ALL {
+            landmarks.push(LandmarkConfig {
+                kind,
+                count: settings.get(vec!["landmarks", kind.name(), "count"])?,
+                length: settings.get(vec!["landmarks", kind.name(), "length"])?,
+                breadth: settings.get(vec!["landmarks", kind.name(), "breadth"])?,
+            });
+        }
+
+        Ok(Self {
+            landmarks,
+            max_merge_gap: settings.get(vec!["landmarks", "merge", "gap"])?,
+        })
+    }
+}
+
+pub struct RailConfig {
+    /// The number of railway lines, each given its own band of the
+    /// city
+    pub lines: f64,
+    pub flows_along_x: bool,
+    pub tracks: f64,
+    pub curve_amplitude: Distribution,
+    pub curve_wavelength: Distribution,
+    pub verge_breadth: f64,
+    /// The distance between one station and the next along a line,
+    /// and the length of each, in meters
+    pub station_spacing: f64,
+    pub station_length: f64,
+    /// Roads at least this broad cross the tracks on a bridge rather
+    /// than at grade
+    pub bridge_breadth: f64,
+}
+
+impl TryFrom<&Settings> for RailConfig {
+    type Error = Error;
+
+    fn try_from(settings: &Settings) -> Result<Self, Error> {
+        let axis: String = settings.get(vec!["rail", "axis"])?;
+        Ok(Self {
+            lines: settings.get(vec!["rail", "lines"])?,
+            flows_along_x: axis != "y",
+            tracks: settings.get(vec!["rail", "tracks"])?,
+            curve_amplitude: Distribution::try_from_settings(
+                settings,
+                vec!["rail", "curve", "amplitude"],
+            )?,
+            curve_wavelength: Distribution::try_from_settings(
+                settings,
+                vec!["rail", "curve", "wavelength"],
+            )?,
+            verge_breadth: settings.get(vec!["rail", "verge", "breadth"])?,
+            station_spacing: settings.get(vec!["rail", "stations", "spacing"])?,
+            station_length: settings.get(vec!["rail", "stations", "length"])?,
+            bridge_breadth: settings.get(vec!["rail", "bridges", "breadth"])?,
         })
     }
 }
 
+pub struct SuburbConfig {
+    pub collector_breadth: f64,
+    pub street_breadth: f64,
+    pub collector_spacing: f64,
+    pub curve_amplitude: Distribution,
+    pub curve_wavelength: Distribution,
+    pub loop_chance: f64,
+    pub bulb_radius: f64,
+    pub lot_width: Distribution,
+    pub lot_depth: f64,
+    pub max_bow: f64,
+}
+
+impl TryFrom<&Settings> for SuburbConfig {
+    type Error = Error;
+
+    fn try_from(settings: &Settings) -> Result<Self, Error> {
+        Ok(Self {
+            collector_breadth: settings.get(vec!["suburbs", "collector", "breadth"])?,
+            street_breadth: settings.get(vec!["suburbs", "street", "breadth"])?,
+            collector_spacing: settings.get(vec!["suburbs", "collector", "spacing"])?,
+            curve_amplitude: Distribution::try_from_settings(
+                settings,
+                vec!["suburbs", "collector", "amplitude"],
+            )?,
+            curve_wavelength: Distribution::try_from_settings(
+                settings,
+                vec!["suburbs", "collector", "wavelength"],
+            )?,
+            loop_chance: settings.get(vec!["suburbs", "loops"])?,
+            bulb_radius: settings.get(vec!["suburbs", "bulb", "radius"])?,
+            lot_width: Distribution::try_from_settings(
+                settings,
+                vec!["suburbs", "lot", "width"],
+            )?,
+            lot_depth: settings.get(vec!["suburbs", "lot", "depth"])?,
+            max_bow: settings.get(vec!["suburbs", "street", "bow"])?,
+        })
+    }
+}
+
+pub struct TrafficConfig {
+    /// The chance of a stretch of lane away from intersections
+    /// holding a vehicle
+    pub density: f64,
+    /// The chance of a stretch of lane right behind an intersection
+    /// holding a vehicle
+    pub queueing: f64,
+    /// The side of the road traffic keeps to
+    pub side: String,
+    /// How often each kind of vehicle turns up, by weight
+    pub vehicles: Vec<(VehicleKind, f64)>,
+}
+
+impl TryFrom<&Settings> for TrafficConfig {
+    type Error = Error;
+
+    fn try_from(settings: &Settings) -> Result<Self, Error> {
+        let mut vehicles = Vec::new();
+        for kind in VehicleKind::ALL {
+            vehicles.push((kind, settings.get(vec!["traffic", "vehicles", kind.name()])?));
+        }
+
+        Ok(Self {
+            density: settings.get(vec!["traffic", "density"])?,
+            queueing: settings.get(vec!["traffic", "queueing"])?,
+            side: settings.get(vec!["traffic", "side"])?,
+            vehicles,
+        })
+    }
+}
+
+pub struct GrowthConfig {
+    pub epochs: u32,
+    /// The share of the epochs buildings take to rise to full height
+    pub densification: f64,
+    /// How far the edge of the city wanders as it spreads, as a share
+    /// of the distance it spreads
+    pub irregularity: f64,
+    /// How long each frame is shown for, in seconds
+    pub frame_duration: f64,
+}
+
+impl TryFrom<&Settings> for GrowthConfig {
+    type Error = Error;
+
+    fn try_from(settings: &Settings) -> Result<Self, Error> {
+        let epochs: f64 = settings.get(vec!["growth", "epochs"])?;
+        let epochs = epochs.max(1.0) as u32;
+        if epochs > MAX_GROWTH_EPOCHS {
+            return Err(Error::OutOfRange {
+                path: vec![String::from("growth"), String::from("epochs")],
+                expected: format!("at most {}", MAX_GROWTH_EPOCHS),
+            });
+        }
+
+        Ok(Self {
+            epochs,
+            densification: settings.get(vec!["growth", "densification"])?,
+            irregularity: settings.get(vec!["growth", "irregularity"])?,
+            frame_duration: settings.get(vec!["growth", "frame", "duration"])?,
+        })
+    }
+}
+
+pub struct RenderConfig {
+    pub time: String,
+    /// The share of daylight left at night
+    pub night_brightness: f64,
+    pub lamp_spacing: Distribution,
+    pub lamp_radius: f64,
+    /// The share of lights on flat roofs that are on top of them
+    /// rather than in windows
+    pub rooftop_share: f64,
+    /// How many lights each zone has per 100 square meters of floor
+    pub windows: Vec<(Zone, f64)>,
+    pub season: String,
+    pub weather: String,
+    pub snow_cover: f64,
+    pub puddles: f64,
+    /// How strongly surfaces are textured, zero for flat fills
+    pub texture_detail: f64,
+}
+
+impl TryFrom<&Settings> for RenderConfig {
+    type Error = Error;
+
+    fn try_from(settings: &Settings) -> Result<Self, Error> {
+        let mut windows = Vec::new();
+        for zone in Zone::ALL {
+            windows.push((zone, settings.get(vec!["render", "night", "windows", zone.name()])?));
+        }
+
+        Ok(Self {
+            time: settings.get(vec!["render", "time"])?,
+            night_brightness: settings.get(vec!["render", "night", "brightness"])?,
+            lamp_spacing: Distribution::try_from_settings(
+                settings,
+                vec!["render", "night", "lamps", "spacing"],
+            )?,
+            lamp_radius: settings.get(vec!["render", "night", "lamps", "radius"])?,
+            rooftop_share: settings.get(vec!["render", "night", "rooftops"])?,
+            windows,
+            season: settings.get(vec!["render", "season"])?,
+            weather: settings.get(vec!["render", "weather"])?,
+            snow_cover: settings.get(vec!["render", "snow", "cover"])?,
+            puddles: settings.get(vec!["render", "rain", "puddles"])?,
+            texture_detail: settings.get(vec!["render", "texture_detail"])?,
+        })
+    }
+}
+
+pub struct ColorConfig {
+    pub palette: Palette,
+}
+
+impl TryFrom<&Settings> for ColorConfig {
+    type Error = Error;
+
+    fn try_from(settings: &Settings) -> Result<Self, Error> {
+        // Unknown themes are turned away as the script is read
+        let theme: String = settings.get(vec!["colors", "theme"])?;
+        let theme = Theme::from_name(&theme)
+            .ok_or(Error::WrongType(vec![String::from("colors"), String::from("theme")]))?;
+
+        let mut palette = theme.palette();
+        for surface in Surface::ALL {
+            let mut path = vec!["colors"];
+            path.extend(surface.name().split("."));
+            palette.set(surface, settings.get(path)?);
+        }
+
+        for zone in Zone::ALL {
+            let mut total = 0.0;
+            for (index, slot) in ROOF_SLOTS.into_iter().enumerate() {
+                let weight: f64 = settings.get(vec!["colors", "roofs", zone.name(), slot, "weight"])?;
+                if weight < 0.0 {
+                    return Err(Error::OutOfRange {
+                        path: ["colors", "roofs", zone.name(), slot, "weight"].map(String::from).to_vec(),
+                        expected: String::from("at least 0"),
+                    });
+                }
+
+                total += weight;
+                palette.set_roof(
+                    zone,
+                    index,
+                    settings.get(vec!["colors", "roofs", zone.name(), slot, "color"])?,
+                    weight,
+                );
+            }
+
+            // Roofs are picked by weight, so at least one has to be
+            // in with a chance
+            if total <= 0.0 {
+                return Err(Error::OutOfRange {
+                    path: ["colors", "roofs", zone.name()].map(String::from).to_vec(),
+                    expected: String::from("have a weight above 0 on at least one roof"),
+                });
+            }
+        }
+
+        Ok(Self { palette })
+    }
+}
+
 pub struct Config {
     pub image: ImageConfig,
     pub city: CityConfig,
     pub roads: RoadConfig,
     pub buildings: BuildingConfig,
+    pub water: WaterConfig,
+    pub coast: CoastConfig,
+    pub terrain: TerrainConfig,
+    pub mask: MaskConfig,
+    pub zones: ZonesConfig,
+    pub lighting: LightingConfig,
+    pub landmarks: LandmarksConfig,
+    pub traffic: TrafficConfig,
+    pub rail: RailConfig,
+    pub suburbs: SuburbConfig,
+    pub growth: GrowthConfig,
+    pub render: RenderConfig,
+    pub colors: ColorConfig,
 }
 
 impl TryFrom<Settings> for Config {
@@ -114,6 +756,19 @@ impl TryFrom<Settings> for Config {
             city: CityConfig::try_from(&settings)?,
             roads: RoadConfig::try_from(&settings)?,
             buildings: BuildingConfig::try_from(&settings)?,
+            water: WaterConfig::try_from(&settings)?,
+            coast: CoastConfig::try_from(&settings)?,
+            terrain: TerrainConfig::try_from(&settings)?,
+            mask: MaskConfig::try_from(&settings)?,
+            zones: ZonesConfig::try_from(&settings)?,
+            lighting: LightingConfig::try_from(&settings)?,
+            landmarks: LandmarksConfig::try_from(&settings)?,
+            traffic: TrafficConfig::try_from(&settings)?,
+            rail: RailConfig::try_from(&settings)?,
+            suburbs: SuburbConfig::try_from(&settings)?,
+            growth: GrowthConfig::try_from(&settings)?,
+            render: RenderConfig::try_from(&settings)?,
+            colors: ColorConfig::try_from(&settings)?,
         })
     }
 }
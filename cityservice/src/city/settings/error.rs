@@ -2,6 +2,10 @@
 pub enum Error {
     NonexistantSetting(Vec<String>),
     WrongType(Vec<String>),
+    OutOfRange{
+        path: Vec<String>,
+        expected: String,
+    },
     LineError{
         line: u32,
         cause: LineError
@@ -21,6 +25,13 @@ impl std::fmt::Display for Error {
                     are no spaces between the number and unit.",
                 path.join("."),
             ),
+            Self::OutOfRange{
+                path,
+                expected,
+            } => write!(
+                f, "The value of {} is out of range, it should be {}",
+                path.join("."), expected,
+            ),
             Self::LineError{
                 line,
                 cause,
@@ -36,6 +47,8 @@ pub enum LineError {
     WordCount,
     Syntax,
     ExpectedNumber,
+    ExpectedColor,
+    UnknownTheme(String),
     NonexistantSetting(Vec<String>)
 }
 
@@ -55,6 +68,15 @@ impl std::fmt::Display for LineError {
                  because of a trailing unit (m, km), \
                  but the value was not parsable as a number"
             ),
+            Self::ExpectedColor => format!(
+                "The service expected a colour, \
+                 given as #rrggbb, 0xrrggbb or rgb(r,g,b) \
+                 with no spaces"
+            ),
+            Self::UnknownTheme(theme) => format!(
+                "There is no colour theme called {}, \
+                 try realistic, blueprint, pastel or dark",
+                theme),
             Self::NonexistantSetting(setting) => format!(
                 "You tried to update \
                  a nonexistant setting: {}",
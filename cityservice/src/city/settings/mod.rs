@@ -9,6 +9,9 @@ pub use error::*;
 mod tree;
 use tree::TreeMap;
 
+use super::Zone;
+use super::palette::{Surface, Theme, ROOF_SLOTS};
+
 pub struct Settings {
     tree: TreeMap<Setting>,
 }
@@ -27,9 +30,13 @@ impl Default for Settings {
         // Image parameters, these control properties of the image
         // returned by the service
         //
-        // For now, just the size in pixels
+        // The size in pixels, the height in meters that shows up as
+        // full white in the heightmap, and how strongly slopes tilt
+        // the normal map
         tree.add(["image", "width"], 2048);
         tree.add(["image", "height"], 2048);
+        tree.add(["image", "heightmap", "scale"], 200.0);
+        tree.add(["image", "normal", "strength"], 1.0);
 
         // City parameters, these control broad, overarching
         // properties of the city generated by the service
@@ -38,6 +45,18 @@ impl Default for Settings {
         tree.add(["city", "width"], 1000.0);
         tree.add(["city", "height"], 1000.0);
 
+        // The streets are laid out as a "grid", as winding "suburban"
+        // streets, or as a grid core ringed by suburbs ("mixed"). The
+        // core takes up this share of the city's width and height.
+        tree.add(["city", "layout"], "grid");
+        tree.add(["city", "core"], 0.5);
+
+        // The seed for anything drawn from noise rather than sampled:
+        // the grain of surface textures, where snow and puddles lie,
+        // and how the edge of a growing city wanders. It can be any
+        // whole number from 0 to 4294967295, fractions are dropped.
+        tree.add(["city", "seed"], 0.0);
+
         tree.add(["roads", "breadth", "skew"], 1.0);
         tree.add(["roads", "breadth", "distribution"], "normal");
         tree.add(["roads", "breadth", "min"], 6.0);
@@ -66,23 +85,545 @@ impl Default for Settings {
         tree.add(["buildings", "roof", "tint", "max"], 1.0);
         tree.add(["buildings", "roof", "tint", "min"], 0.0);
 
+        // Each building's roof shape is picked at random by weight,
+        // and pitched roofs rise by their pitch (rise over run)
+        tree.add(["buildings", "roof", "shapes", "flat"], 3.0);
+        tree.add(["buildings", "roof", "shapes", "gabled"], 1.0);
+        tree.add(["buildings", "roof", "shapes", "hipped"], 1.0);
+        tree.add(["buildings", "roof", "shapes", "shed"], 0.5);
+        tree.add(["buildings", "roof", "shapes", "mansard"], 0.5);
+        tree.add(["buildings", "roof", "pitch", "skew"], 1.0);
+        tree.add(["buildings", "roof", "pitch", "distribution"], "uniform");
+        tree.add(["buildings", "roof", "pitch", "max"], 0.8);
+        tree.add(["buildings", "roof", "pitch", "min"], 0.3);
+
+        // Each building's outline is picked at random by weight, and
+        // the wings of the shaped ones are a share of the short span
+        // of their lot across
+        let footprints = [
+            ("box", 6.0),
+            ("l", 1.0),
+            ("u", 0.5),
+            ("t", 0.5),
+            ("h", 0.3),
+            ("courtyard", 0.3),
+            ("stepped", 0.5),
+        ];
+
+        for (shape, weight) in footprints {
+            tree.add(["buildings", "footprints", shape], weight);
+        }
+
+        Self::add_distribution(&mut tree, &["buildings", "footprints", "wing"], "uniform", (0.3, 0.45));
+
+        // Flat roofs are cluttered with equipment, these are how many
+        // of each kind there are per 1000 square meters of roof
+        let rooftops = [
+            ("hvac", 3.0),
+            ("skylights", 1.0),
+            ("bulkheads", 0.5),
+            ("watertowers", 0.2),
+            ("solar", 0.5),
+        ];
+
+        for (kind, density) in rooftops {
+            tree.add(["buildings", "rooftops", kind], density);
+        }
+
         tree.add(["buildings", "walls", "stepback", "skew"], 1.0);
         tree.add(["buildings", "walls", "stepback", "distribution"], "uniform");
         tree.add(["buildings", "walls", "stepback", "max"], 30.0);
         tree.add(["buildings", "walls", "stepback", "min"], 10.0);
 
+        // Buildings are a whole number of floors, and get shorter
+        // further from the centre. The falloff is the fraction of
+        // their height lost by the corners of the city.
+        tree.add(["buildings", "floors", "skew"], 1.0);
+        tree.add(["buildings", "floors", "distribution"], "normal");
+        tree.add(["buildings", "floors", "min"], 1.0);
+        tree.add(["buildings", "floors", "max"], 6.0);
+        tree.add(["buildings", "storey", "height"], 3.5);
+        tree.add(["buildings", "height", "falloff"], 0.5);
+
+        // Blocks are filled with rings of buildings from the street
+        // inwards while there's room, and the middle can get a
+        // building of its own. What's left is a courtyard, either
+        // "garden" or "paved".
+        tree.add(["buildings", "rings"], 1.0);
+        tree.add(["buildings", "inner", "chance"], 0.3);
+        tree.add(["buildings", "courtyards"], "paved");
+
+        // Blocks are either lined with buildings along their faces, or
+        // subdivided into lots that each keep some frontage on the
+        // street. A lot is split while it's larger than the area, and
+        // its building is set back from the street at the front and
+        // from its neighbours at the sides.
+        tree.add(["buildings", "lots", "strategy"], "faces");
+        Self::add_distribution(&mut tree, &["buildings", "lots", "area"], "uniform", (400.0, 1200.0));
+        tree.add(["buildings", "lots", "frontage"], 8.0);
+        Self::add_distribution(&mut tree, &["buildings", "lots", "setback", "front"], "uniform", (1.0, 4.0));
+        Self::add_distribution(&mut tree, &["buildings", "lots", "setback", "side"], "uniform", (0.5, 2.0));
+
+        // Some blocks (or lots, on subdivided blocks) are left unbuilt
+        // as car parks, vacant lots or construction sites, each with
+        // its own chance. Occupancy is the share of parking bays with
+        // a car in them.
+        tree.add(["buildings", "fillers", "parking"], 0.05);
+        tree.add(["buildings", "fillers", "vacant"], 0.03);
+        tree.add(["buildings", "fillers", "construction"], 0.03);
+        tree.add(["buildings", "parking", "occupancy"], 0.6);
+
         tree.add(["alleys", "breadth", "skew"], 1.0);
         tree.add(["alleys", "breadth", "distribution"], "normal");
         tree.add(["alleys", "breadth", "min"], 0.0);
         tree.add(["alleys", "breadth", "max"], 1.0);
 
         tree.add(["sidewalk", "breadth"], 1.5);
+
+        // Water is carved out before the roads, "none" keeps the city
+        // dry, the other kinds are "river" and "canals"
+        tree.add(["water", "kind"], "none");
+        tree.add(["water", "axis"], "x");
+
+        tree.add(["water", "breadth", "skew"], 1.0);
+        tree.add(["water", "breadth", "distribution"], "normal");
+        tree.add(["water", "breadth", "min"], 20.0);
+        tree.add(["water", "breadth", "max"], 60.0);
+
+        tree.add(["water", "meander", "amplitude", "skew"], 1.0);
+        tree.add(["water", "meander", "amplitude", "distribution"], "uniform");
+        tree.add(["water", "meander", "amplitude", "min"], 10.0);
+        tree.add(["water", "meander", "amplitude", "max"], 80.0);
+
+        tree.add(["water", "meander", "wavelength", "skew"], 1.0);
+        tree.add(["water", "meander", "wavelength", "distribution"], "uniform");
+        tree.add(["water", "meander", "wavelength", "min"], 300.0);
+        tree.add(["water", "meander", "wavelength", "max"], 900.0);
+
+        tree.add(["water", "canals", "count"], 2.0);
+        tree.add(["water", "quay", "breadth"], 4.0);
+        tree.add(["bridges", "railing", "breadth"], 1.0);
+
+        // Any edge of the city can be coast, the sea reaches in from
+        // that edge to a noisy shoreline
+        for edge in ["north", "south", "east", "west"] {
+            tree.add(["coast", edge], false);
+        }
+
+        tree.add(["coast", "depth", "skew"], 1.0);
+        tree.add(["coast", "depth", "distribution"], "uniform");
+        tree.add(["coast", "depth", "min"], 100.0);
+        tree.add(["coast", "depth", "max"], 200.0);
+
+        tree.add(["coast", "shore", "roughness"], 40.0);
+        tree.add(["coast", "shore", "wavelength"], 250.0);
+
+        tree.add(["coast", "piers", "density", "skew"], 1.0);
+        tree.add(["coast", "piers", "density", "distribution"], "uniform");
+        tree.add(["coast", "piers", "density", "min"], 0.5/100.0);
+        tree.add(["coast", "piers", "density", "max"], 2.0/100.0);
+
+        tree.add(["coast", "piers", "length", "skew"], 1.0);
+        tree.add(["coast", "piers", "length", "distribution"], "normal");
+        tree.add(["coast", "piers", "length", "min"], 20.0);
+        tree.add(["coast", "piers", "length", "max"], 70.0);
+
+        tree.add(["coast", "piers", "breadth", "skew"], 1.0);
+        tree.add(["coast", "piers", "breadth", "distribution"], "normal");
+        tree.add(["coast", "piers", "breadth", "min"], 6.0);
+        tree.add(["coast", "piers", "breadth", "max"], 20.0);
+        tree.add(["coast", "piers", "border"], 1.0);
+
+        // These only matter when a heightmap is supplied. The height
+        // is that of a white pixel, and slopes are rise over run.
+        tree.add(["terrain", "height"], 100.0);
+        tree.add(["terrain", "slope", "max"], 0.25);
+        tree.add(["terrain", "bend", "max"], 30.0);
+
+        // These only matter when a density mask is supplied. With
+        // channels on, red weighs roads, green buildings and blue
+        // zones, otherwise the brightness weighs everything.
+        tree.add(["mask", "roads"], true);
+        tree.add(["mask", "buildings"], true);
+        tree.add(["mask", "channels"], false);
+
+        // Zoning assigns each block a land use. With the mode at
+        // "none" every block uses the city wide building settings,
+        // otherwise blocks are zoned "random"ly, by "distance" from
+        // the centre, or by "mask". Centrality is where a zone likes
+        // to be, 1 is the centre (or white on the mask) and 0 the
+        // outskirts (or black), and the spread is how fussy zones are
+        // about it.
+        tree.add(["zones", "mode"], "none");
+        tree.add(["zones", "spread"], 0.25);
+
+        //   zone, weight, centrality, density (/km), stepback, roof border, alleys
+        let zones = [
+            ("residential", 4.0, 0.5, (30.0, 80.0), (8.0, 15.0), (0.5, 1.5), (1.0, 3.0)),
+            ("commercial", 3.0, 1.0, (20.0, 50.0), (15.0, 30.0), (1.0, 2.0), (0.0, 1.0)),
+            ("industrial", 2.0, 0.0, (5.0, 20.0), (25.0, 60.0), (1.5, 3.0), (4.0, 10.0)),
+            ("civic", 1.0, 0.7, (5.0, 15.0), (20.0, 40.0), (2.0, 3.0), (5.0, 10.0)),
+        ];
+
+        //   zone, floors, storey height, height falloff
+        let zone_heights = [
+            ("residential", (1.0, 3.0), 3.0, 0.3),
+            ("commercial", (3.0, 20.0), 3.5, 0.8),
+            ("industrial", (1.0, 2.0), 6.0, 0.0),
+            ("civic", (2.0, 5.0), 4.5, 0.3),
+        ];
+
+        for (zone, floors, storey_height, falloff) in zone_heights {
+            Self::add_distribution(
+                &mut tree,
+                &["zones", zone, "buildings", "floors"],
+                "normal",
+                floors,
+            );
+
+            tree.add(["zones", zone, "buildings", "storey", "height"], storey_height);
+            tree.add(["zones", zone, "buildings", "height", "falloff"], falloff);
+        }
+
+        //   zone, rings, inner building chance, courtyards
+        let zone_layouts = [
+            ("residential", 1.0, 0.1, "garden"),
+            ("commercial", 2.0, 0.5, "paved"),
+            ("industrial", 1.0, 0.6, "paved"),
+            ("civic", 1.0, 0.3, "garden"),
+        ];
+
+        for (zone, rings, inner, courtyards) in zone_layouts {
+            tree.add(["zones", zone, "buildings", "rings"], rings);
+            tree.add(["zones", zone, "buildings", "inner", "chance"], inner);
+            tree.add(["zones", zone, "buildings", "courtyards"], courtyards);
+        }
+
+        //   zone, lot area (m²), frontage, front setback, side setback
+        let zone_lots = [
+            ("residential", (300.0, 800.0), 8.0, (2.0, 6.0), (1.0, 2.0)),
+            ("commercial", (400.0, 1500.0), 10.0, (0.0, 1.0), (0.0, 0.5)),
+            ("industrial", (1500.0, 5000.0), 20.0, (3.0, 10.0), (2.0, 6.0)),
+            ("civic", (1000.0, 4000.0), 20.0, (4.0, 10.0), (3.0, 6.0)),
+        ];
+
+        for (zone, area, frontage, front, side) in zone_lots {
+            tree.add(["zones", zone, "buildings", "lots", "strategy"], "faces");
+            Self::add_distribution(&mut tree, &["zones", zone, "buildings", "lots", "area"], "uniform", area);
+            tree.add(["zones", zone, "buildings", "lots", "frontage"], frontage);
+            Self::add_distribution(
+                &mut tree,
+                &["zones", zone, "buildings", "lots", "setback", "front"],
+                "uniform",
+                front,
+            );
+            Self::add_distribution(
+                &mut tree,
+                &["zones", zone, "buildings", "lots", "setback", "side"],
+                "uniform",
+                side,
+            );
+        }
+
+        //   zone, parking, vacant, construction, parking occupancy
+        let zone_fillers = [
+            ("residential", 0.02, 0.03, 0.02, 0.5),
+            ("commercial", 0.08, 0.02, 0.05, 0.8),
+            ("industrial", 0.1, 0.08, 0.03, 0.4),
+            ("civic", 0.08, 0.02, 0.02, 0.6),
+        ];
+
+        for (zone, parking, vacant, construction, occupancy) in zone_fillers {
+            tree.add(["zones", zone, "buildings", "fillers", "parking"], parking);
+            tree.add(["zones", zone, "buildings", "fillers", "vacant"], vacant);
+            tree.add(["zones", zone, "buildings", "fillers", "construction"], construction);
+            tree.add(["zones", zone, "buildings", "parking", "occupancy"], occupancy);
+        }
+
+        //   zone, flat, gabled, hipped, shed, mansard
+        let zone_roofs = [
+            ("residential", [1.0, 3.0, 2.0, 0.5, 0.5]),
+            ("commercial", [6.0, 0.0, 0.5, 0.0, 1.0]),
+            ("industrial", [3.0, 1.0, 0.0, 2.0, 0.0]),
+            ("civic", [2.0, 1.0, 1.0, 0.0, 1.0]),
+        ];
+
+        //   zone, hvac, skylights, bulkheads, water towers, solar
+        let zone_rooftops = [
+            ("residential", [1.0, 1.0, 0.5, 0.3, 1.0]),
+            ("commercial", [5.0, 0.5, 1.0, 0.5, 0.5]),
+            ("industrial", [4.0, 3.0, 0.3, 0.2, 2.0]),
+            ("civic", [2.0, 1.0, 0.5, 0.1, 1.0]),
+        ];
+
+        for (zone, densities) in zone_rooftops {
+            let kinds = ["hvac", "skylights", "bulkheads", "watertowers", "solar"];
+            for (kind, density) in kinds.into_iter().zip(densities) {
+                tree.add(["zones", zone, "buildings", "rooftops", kind], density);
+            }
+        }
+
+        //   zone, box, l, u, t, h, courtyard, stepped
+        let zone_footprints = [
+            ("residential", [6.0, 2.0, 0.5, 0.5, 0.2, 0.0, 0.0]),
+            ("commercial", [4.0, 1.0, 0.5, 1.0, 0.3, 0.5, 2.0]),
+            ("industrial", [6.0, 1.0, 0.5, 0.5, 0.0, 0.0, 0.0]),
+            ("civic", [3.0, 1.0, 1.0, 1.0, 1.0, 1.0, 0.5]),
+        ];
+
+        for (zone, weights) in zone_footprints {
+            let shapes = ["box", "l", "u", "t", "h", "courtyard", "stepped"];
+            for (shape, weight) in shapes.into_iter().zip(weights) {
+                tree.add(["zones", zone, "buildings", "footprints", shape], weight);
+            }
+
+            Self::add_distribution(
+                &mut tree,
+                &["zones", zone, "buildings", "footprints", "wing"],
+                "uniform",
+                (0.3, 0.45),
+            );
+        }
+
+        for (zone, weights) in zone_roofs {
+            for (shape, weight) in ["flat", "gabled", "hipped", "shed", "mansard"].into_iter().zip(weights) {
+                tree.add(["zones", zone, "buildings", "roof", "shapes", shape], weight);
+            }
+
+            Self::add_distribution(
+                &mut tree,
+                &["zones", zone, "buildings", "roof", "pitch"],
+                "uniform",
+                (0.3, 0.8),
+            );
+        }
+
+        for (zone, weight, centrality, density, stepback, border, alleys) in zones {
+            tree.add(["zones", zone, "weight"], weight);
+            tree.add(["zones", zone, "centrality"], centrality);
+
+            for dimension in ["x", "y"] {
+                Self::add_distribution(
+                    &mut tree,
+                    &["zones", zone, "buildings", "density", dimension],
+                    "normal",
+                    (density.0 / 1000.0, density.1 / 1000.0),
+                );
+            }
+
+            Self::add_distribution(
+                &mut tree,
+                &["zones", zone, "buildings", "walls", "stepback"],
+                "uniform",
+                stepback,
+            );
+
+            Self::add_distribution(
+                &mut tree,
+                &["zones", zone, "buildings", "roof", "border"],
+                "normal",
+                border,
+            );
+
+            Self::add_distribution(
+                &mut tree,
+                &["zones", zone, "buildings", "roof", "tint"],
+                "uniform",
+                (0.0, 1.0),
+            );
+
+            Self::add_distribution(
+                &mut tree,
+                &["zones", zone, "alleys", "breadth"],
+                "normal",
+                alleys,
+            );
+        }
+
+        // Parks aren't built on
+        tree.add(["zones", "park", "weight"], 1.0);
+        tree.add(["zones", "park", "centrality"], 0.5);
+
+        // The sun's azimuth is in degrees clockwise from north (the
+        // top of the image) and its elevation in degrees above the
+        // horizon. Shadow darkness is how much light a shadow takes
+        // away, from 0 (no shadows) to 1 (black), and the softness is
+        // the width in degrees of the penumbra.
+        tree.add(["lighting", "sun", "azimuth"], 135.0);
+        tree.add(["lighting", "sun", "elevation"], 45.0);
+        tree.add(["lighting", "shadows", "darkness"], 0.4);
+        tree.add(["lighting", "shadows", "softness"], 2.0);
+
+        // Landmarks each take a whole block, or a pair of blocks
+        // merged into one across a road no wider than the merge gap.
+        // Their length and breadth are in meters, and they're left
+        // out wherever there's no block big enough for them. There are
+        // none unless they're asked for.
+        //   kind, count, length, breadth
+        let landmarks = [
+            ("stadium", 0.0, 220.0, 170.0),
+            ("cathedral", 0.0, 110.0, 60.0),
+            ("station", 0.0, 200.0, 70.0),
+            ("mall", 0.0, 160.0, 110.0),
+        ];
+
+        for (kind, count, length, breadth) in landmarks {
+            tree.add(["landmarks", kind, "count"], count);
+            tree.add(["landmarks", kind, "length"], length);
+            tree.add(["landmarks", kind, "breadth"], breadth);
+        }
+
+        tree.add(["landmarks", "merge", "gap"], 30.0);
+
+        // Suburbs have winding collectors one to each band of the
+        // collector spacing, with side streets off them that are
+        // either loops or cul-de-sacs ending in a turning circle. The
+        // side streets bow out sideways by up to the bow. Houses are
+        // built to the zone's building settings on lots lining every
+        // street.
+        tree.add(["suburbs", "collector", "breadth"], 10.0);
+        tree.add(["suburbs", "collector", "spacing"], 240.0);
+        Self::add_distribution(&mut tree, &["suburbs", "collector", "amplitude"], "uniform", (10.0, 30.0));
+        Self::add_distribution(&mut tree, &["suburbs", "collector", "wavelength"], "uniform", (250.0, 600.0));
+        tree.add(["suburbs", "street", "breadth"], 7.0);
+        tree.add(["suburbs", "street", "bow"], 8.0);
+        tree.add(["suburbs", "loops"], 0.4);
+        tree.add(["suburbs", "bulb", "radius"], 11.0);
+        Self::add_distribution(&mut tree, &["suburbs", "lot", "width"], "uniform", (16.0, 24.0));
+        tree.add(["suburbs", "lot", "depth"], 30.0);
+
+        // Railway lines are cut through the city after the roads,
+        // each running along the axis in a band of its own and curving
+        // gently. A corridor holds its tracks on a bed of ballast with
+        // a verge either side, and widens for platforms at stations
+        // spaced out along the line. Roads cross at grade unless
+        // they're at least as broad as the bridge breadth, or cross
+        // at a station.
+        tree.add(["rail", "lines"], 0.0);
+        tree.add(["rail", "axis"], "x");
+        tree.add(["rail", "tracks"], 2.0);
+        Self::add_distribution(&mut tree, &["rail", "curve", "amplitude"], "uniform", (0.0, 20.0));
+        Self::add_distribution(&mut tree, &["rail", "curve", "wavelength"], "uniform", (800.0, 2000.0));
+        tree.add(["rail", "verge", "breadth"], 3.0);
+        tree.add(["rail", "stations", "spacing"], 600.0);
+        tree.add(["rail", "stations", "length"], 120.0);
+        tree.add(["rail", "bridges", "breadth"], 10.0);
+
+        // Traffic fills the lanes of every road, as many as fit
+        // across it. The density is the chance of a stretch of lane
+        // holding a vehicle, rising to the queueing chance right
+        // behind an intersection. Traffic keeps to the "right" or the
+        // "left", and vehicles are picked by weight.
+        tree.add(["traffic", "density"], 0.15);
+        tree.add(["traffic", "queueing"], 0.8);
+        tree.add(["traffic", "side"], "right");
+        tree.add(["traffic", "vehicles", "car"], 0.85);
+        tree.add(["traffic", "vehicles", "bus"], 0.05);
+        tree.add(["traffic", "vehicles", "truck"], 0.1);
+
+        // A growth time-lapse spreads the city out from its middle
+        // over the epochs, one frame to each. Roads and blocks are
+        // reached in order of distance, the edge wandering by up to
+        // the irregularity as a share of the reach, and buildings
+        // spend the densification share of the epochs rising to full
+        // height. Each frame is shown for the duration in seconds.
+        // There are at least 1 and at most 60 epochs.
+        tree.add(["growth", "epochs"], 12.0);
+        tree.add(["growth", "densification"], 0.3);
+        tree.add(["growth", "irregularity"], 0.15);
+        tree.add(["growth", "frame", "duration"], 0.4);
+
+        // The albedo is rendered by "day" or by "night". At night the
+        // daylight fades to the brightness, and street lamps line the
+        // curbs at intervals drawn from the spacing, each lighting a
+        // pool the radius across. Windows and rooftop lights are lit
+        // at the zone's rate per 100 square meters of floor, the
+        // rooftop share of them on flat roofs. Traffic has its
+        // headlights and taillights on.
+        tree.add(["render", "time"], "day");
+        tree.add(["render", "night", "brightness"], 0.25);
+        Self::add_distribution(&mut tree, &["render", "night", "lamps", "spacing"], "uniform", (24.0, 36.0));
+        tree.add(["render", "night", "lamps", "radius"], 7.0);
+        tree.add(["render", "night", "rooftops"], 0.3);
+        let windows = [
+            ("residential", 0.3),
+            ("commercial", 0.5),
+            ("industrial", 0.2),
+            ("civic", 0.25),
+            ("park", 0.0),
+        ];
+        for (zone, rate) in windows {
+            tree.add(["render", "night", "windows", zone], rate);
+        }
+
+        // The season is "summer", "autumn" (the grass turning gold
+        // and red) or "winter" (faded), and the weather "clear",
+        // "rain" (wet paving, puddles over roughly the puddles share
+        // of flat ground) or "snow" (lying on flat roofs and grass,
+        // roughly the cover share of it, but not the salted roads).
+        tree.add(["render", "season"], "summer");
+        tree.add(["render", "weather"], "clear");
+        tree.add(["render", "snow", "cover"], 0.9);
+        tree.add(["render", "rain", "puddles"], 0.15);
+
+        // Surfaces are broken up with grain, speckle and stains, and
+        // roofs patterned with tiles, gravel or metal seams, each
+        // roof a little off its palette colour. The detail scales all
+        // of it, 0 leaves every surface a flat fill.
+        tree.add(["render", "texture_detail"], 1.0);
+
+        // Every surface has a colour, given as #rrggbb, 0xrrggbb,
+        // rgb(r,g,b) or by name. The theme ("realistic", "blueprint",
+        // "pastel" or "dark") sets all of them at once, so it has to
+        // come before any colours of your own. Each zone's roofs are
+        // picked by weight from a palette of four colours, with the
+        // roof tint making the pick.
+        tree.add(["colors", "theme"], "realistic");
+        Self::add_theme(&mut tree, Theme::Realistic);
         
         Self { tree }
     }
 }
 
 impl Settings {
+    /// Adds the settings for a distribution at the given path with
+    /// the default skew
+    fn add_distribution(
+        tree: &mut TreeMap<Setting>,
+        path: &[&str],
+        distribution: &str,
+        (min, max): (f64, f64),
+    ) {
+        let endpoint = |name| {
+            let mut endpoint = path.to_vec();
+            endpoint.push(name);
+            endpoint
+        };
+
+        tree.add(endpoint("skew"), 1.0);
+        tree.add(endpoint("distribution"), distribution);
+        tree.add(endpoint("min"), min);
+        tree.add(endpoint("max"), max);
+    }
+
+    /// Sets every colour, and the roof palette of every zone, to
+    /// those of a theme
+    fn add_theme(tree: &mut TreeMap<Setting>, theme: Theme) {
+        let palette = theme.palette();
+        for surface in Surface::ALL {
+            let mut path = vec!["colors"];
+            path.extend(surface.name().split("."));
+            tree.add(path, palette.color(surface));
+        }
+
+        for zone in Zone::ALL {
+            for (slot, (color, weight)) in ROOF_SLOTS.iter().zip(palette.roofs(zone)) {
+                tree.add(["colors", "roofs", zone.name(), slot, "color"], *color);
+                tree.add(["colors", "roofs", zone.name(), slot, "weight"], *weight);
+            }
+        }
+    }
+
     fn process_line(&mut self, line: &str) -> Result<(), LineError> {
         let words: Vec<&str> = line.trim().split_whitespace().collect();
         println!("{:?}", words);
@@ -92,7 +633,14 @@ impl Settings {
         let key: Vec<&str> = words[1].split(".").collect();
         let value = Self::parse_value(words[3])?;
         self.set(key.clone(), value)
-            .map_err(|_| LineError::NonexistantSetting(Self::own_path(key)))?;
+            .map_err(|_| LineError::NonexistantSetting(Self::own_path(key.clone())))?;
+
+        // A theme presets every colour, anything set after it wins
+        if key == ["colors", "theme"] {
+            let theme = Theme::from_name(words[3])
+                .ok_or_else(|| LineError::UnknownTheme(String::from(words[3])))?;
+            Self::add_theme(&mut self.tree, theme);
+        }
 
         Ok(())
     }
@@ -127,9 +675,43 @@ impl Settings {
         }
     }
     
+    /// Parses a colour given as #rrggbb, 0xrrggbb or rgb(r,g,b),
+    /// colours given by name are left as strings until they're read
+    fn parse_color(value_string: &str) -> Result<Setting, LineError> {
+        let hex = value_string
+            .strip_prefix("#")
+            .or_else(|| value_string.strip_prefix("0x"));
+
+        let channels: Vec<u8> = if let Some(hex) = hex {
+            if hex.len() != 6 || !hex.is_ascii() {
+                return Err(LineError::ExpectedColor);
+            }
+
+            (0..3).map(|i| u8::from_str_radix(&hex[2 * i..2 * i + 2], 16))
+                .collect::<Result<_, _>>()
+                .map_err(|_| LineError::ExpectedColor)?
+        } else {
+            value_string
+                .strip_prefix("rgb(")
+                .and_then(|rest| rest.strip_suffix(")"))
+                .ok_or(LineError::ExpectedColor)?
+                .split(",")
+                .map(|channel| channel.parse::<u8>())
+                .collect::<Result<_, _>>()
+                .map_err(|_| LineError::ExpectedColor)?
+        };
+
+        match channels[..] {
+            [red, green, blue] => Ok(Setting::Color([red, green, blue])),
+            _ => Err(LineError::ExpectedColor),
+        }
+    }
+
     fn parse_value(value: &str) -> Result<Setting, LineError> {
 
-        if value.len() > 0 && value.chars().next().unwrap().is_digit(10) {
+        if value.starts_with("#") || value.starts_with("0x") || value.starts_with("rgb(") {
+            Self::parse_color(value)
+        } else if value.len() > 0 && value.chars().next().unwrap().is_digit(10) {
             Self::parse_quantitative(value)
         } else {
             Ok(Self::parse_qualitative(value))
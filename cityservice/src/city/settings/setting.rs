@@ -3,6 +3,7 @@ pub enum Setting {
     Uint(u32),
     Float(f64),
     Bool(bool),
+    Color([u8; 3]),
 }
 
 pub enum Error {
@@ -57,6 +58,53 @@ impl TryFrom<&Setting> for bool {
     }
 }
 
+/// Colours can also be given by name, these are the names known
+fn named_color(name: &str) -> Option<[u8; 3]> {
+    let color = match name {
+        "black" => [0x00, 0x00, 0x00],
+        "white" => [0xFF, 0xFF, 0xFF],
+        "grey" | "gray" => [0x80, 0x80, 0x80],
+        "silver" => [0xC0, 0xC0, 0xC0],
+        "charcoal" => [0x36, 0x45, 0x4F],
+        "slate" => [0x70, 0x80, 0x90],
+        "red" => [0xFF, 0x00, 0x00],
+        "maroon" => [0x80, 0x00, 0x00],
+        "terracotta" => [0xB8, 0x6A, 0x50],
+        "orange" => [0xFF, 0xA5, 0x00],
+        "brown" => [0x8B, 0x45, 0x13],
+        "tan" => [0xD2, 0xB4, 0x8C],
+        "beige" => [0xF5, 0xF5, 0xDC],
+        "sand" => [0xC2, 0xB2, 0x80],
+        "yellow" => [0xFF, 0xFF, 0x00],
+        "olive" => [0x80, 0x80, 0x00],
+        "green" => [0x00, 0x80, 0x00],
+        "lime" => [0x00, 0xFF, 0x00],
+        "teal" => [0x00, 0x80, 0x80],
+        "cyan" => [0x00, 0xFF, 0xFF],
+        "blue" => [0x00, 0x00, 0xFF],
+        "navy" => [0x00, 0x00, 0x80],
+        "purple" => [0x80, 0x00, 0x80],
+        "pink" => [0xFF, 0xC0, 0xCB],
+        _ => return None,
+    };
+
+    Some(color)
+}
+
+/// Colours are read from a colour setting, or from a string naming
+/// one of the known colours
+impl TryFrom<&Setting> for [u8; 3] {
+    type Error = Error;
+
+    fn try_from(from: &Setting) -> Result<Self, Error> {
+        match from {
+            Setting::Color(color) => Ok(*color),
+            Setting::String(name) => named_color(name).ok_or(Error::WrongType),
+            _ => Err(Error::WrongType),
+        }
+    }
+}
+
 impl From<String> for Setting {
     fn from(from: String) -> Self {
         Self::String(from)
@@ -86,3 +134,9 @@ impl From<bool> for Setting {
         Self::Bool(from)
     }
 }
+
+impl From<[u8; 3]> for Setting {
+    fn from(from: [u8; 3]) -> Self {
+        Self::Color(from)
+    }
+}
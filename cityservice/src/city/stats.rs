@@ -24,6 +24,17 @@ impl Distribution {
             max: settings.get_endpoint(base_path.clone(), "max")?,
         })
     }
+
+    /// A uniform distribution over [min, max], for values the
+    /// generator needs that aren't controlled by the settings
+    pub fn uniform(min: f64, max: f64) -> Self {
+        Distribution {
+            skew: 1.0,
+            dist: String::from("uniform"),
+            min,
+            max,
+        }
+    }
 }
 
 pub struct Distribution2 {
@@ -64,6 +75,25 @@ impl Distribution2 {
     }
 }
 
+/// Picks an index at random with probability proportional to its
+/// weight. The choice is a uniform sample in [0, 1] that decides the
+/// pick, so the same choice always picks the same index.
+pub fn weighted_index(weights: &[f64], choice: f64) -> usize {
+    let total: f64 = weights.iter().sum();
+    let mut remaining = choice * total;
+    for (index, weight) in weights.iter().enumerate() {
+        if remaining < *weight {
+            return index;
+        }
+
+        remaining -= weight;
+    }
+
+    // Rounding can carry us just past the end, so fall back on the
+    // last index with any weight at all
+    weights.iter().rposition(|weight| *weight > 0.0).unwrap_or(0)
+}
+
 #[derive(Debug)]
 pub enum RequestError {
     Network(reqwest::Error),
@@ -104,3 +134,17 @@ pub async fn request(
         .map_err(|_| RequestError::MalformedResponse)
         .map(|response| response.data)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn weighted_index_picks_by_weight() {
+        let weights = [1.0, 0.0, 3.0];
+        assert_eq!(weighted_index(&weights, 0.0), 0);
+        assert_eq!(weighted_index(&weights, 0.2), 0);
+        assert_eq!(weighted_index(&weights, 0.3), 2);
+        assert_eq!(weighted_index(&weights, 1.0), 2);
+    }
+}
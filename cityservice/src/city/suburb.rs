@@ -0,0 +1,399 @@
+use std::f64::consts::{PI, TAU};
+
+use super::geom::*;
+
+/// The length of the slices winding streets are laid out in, short
+/// enough for their curves to look smooth
+const STREET_SLICE_LENGTH: f64 = 2.0;
+
+/// The gap left between a street and the lots along it, where the
+/// sidewalk runs
+const VERGE: f64 = 1.5;
+
+/// Lots are never narrower than this, in meters
+pub const MIN_LOT_WIDTH: f64 = 4.0;
+
+/// Collectors wind no further from the middle of their band than
+/// this share of the band
+const MAX_WIND: f64 = 0.15;
+
+/// How a suburb is laid out, in meters unless noted otherwise
+#[derive(Debug)]
+pub struct SuburbLayout {
+    pub collector_breadth: f64,
+    pub street_breadth: f64,
+    /// The distance between neighbouring collectors
+    pub collector_spacing: f64,
+    /// The chance of a side street being a loop rather than a
+    /// cul-de-sac
+    pub loop_chance: f64,
+    pub bulb_radius: f64,
+    pub lot_depth: f64,
+    /// How far a side street bows sideways along its length
+    pub max_bow: f64,
+}
+
+/// A house lot along a suburban street
+#[derive(Debug)]
+pub struct Lot {
+    pub footprint: Rectangle,
+    /// Which sides face the street, clockwise from the top
+    pub frontage: [bool; 4],
+}
+
+#[derive(Debug, Default)]
+pub struct Suburb {
+    pub streets: Vec<Rectangle>,
+    pub lots: Vec<Lot>,
+}
+
+/// Works in a frame where the collectors run along x, which is along
+/// the longer side of the area
+fn to_frame(along_x: bool, rectangle: Rectangle) -> Rectangle {
+    if along_x { rectangle } else { rectangle.transpose() }
+}
+
+/// Swapping x for y turns the top side into the left, and the right
+/// side into the bottom
+fn transpose_frontage([top, right, bottom, left]: [bool; 4]) -> [bool; 4] {
+    [left, bottom, right, top]
+}
+
+/// The number of collectors running through an area, one for each
+/// band of the collector spacing across it
+pub fn collectors(area: Rectangle, layout: &SuburbLayout) -> u32 {
+    let across = area.width().min(area.height());
+    (across / layout.collector_spacing.max(1.0)).round().max(1.0) as u32
+}
+
+/// Cuts a street running along x into slices, bowed sideways by an
+/// offset that depends on how far along it each slice is
+fn street_along_x(from: f64, to: f64, breadth: f64, centre: impl Fn(f64) -> f64) -> Vec<Rectangle> {
+    let mut slices = Vec::new();
+    let mut x = from;
+    while x < to {
+        let end = f64::min(x + STREET_SLICE_LENGTH, to);
+        let middle = centre((x + end) / 2.0);
+        slices.push(Rectangle::new(
+            Vector2 { x, y: middle - breadth / 2.0 },
+            Vector2 { x: end, y: middle + breadth / 2.0 },
+        ));
+        x = end;
+    }
+
+    slices
+}
+
+/// The turning circle at the end of a cul-de-sac, as strips across it
+fn bulb(centre: Vector2, radius: f64) -> Vec<Rectangle> {
+    let mut strips = Vec::new();
+    let mut y = -radius;
+    while y < radius {
+        let end = f64::min(y + STREET_SLICE_LENGTH, radius);
+        let middle = (y + end) / 2.0;
+        let half = (radius * radius - middle * middle).max(0.0).sqrt();
+        strips.push(Rectangle::new(
+            centre + Vector2 { x: -half, y },
+            centre + Vector2 { x: half, y: end },
+        ));
+        y = end;
+    }
+
+    strips
+}
+
+/// Lines both sides of a street running along x with lots, taking the
+/// width of each lot from the widths until they run out. Each lot is
+/// set back from the street as far as the street reaches out along
+/// its width.
+fn lots_along_x(
+    street: &[Rectangle],
+    layout: &SuburbLayout,
+    widths: &mut impl Iterator<Item = f64>,
+) -> Vec<Lot> {
+    let from = street.iter().map(|slice| slice.start().x).fold(f64::INFINITY, f64::min);
+    let to = street.iter().map(|slice| slice.end().x).fold(f64::NEG_INFINITY, f64::max);
+
+    let mut lots = Vec::new();
+    let mut x = from;
+    for width in widths.by_ref() {
+        let width = width.max(MIN_LOT_WIDTH);
+        if x + width > to {
+            break;
+        }
+
+        let alongside: Vec<&Rectangle> = street
+            .iter()
+            .filter(|slice| slice.start().x < x + width && slice.end().x > x)
+            .collect();
+
+        if !alongside.is_empty() {
+            let top = alongside.iter().map(|slice| slice.start().y).fold(f64::INFINITY, f64::min);
+            let bottom = alongside.iter().map(|slice| slice.end().y).fold(f64::NEG_INFINITY, f64::max);
+
+            lots.push(Lot {
+                footprint: Rectangle::new(
+                    Vector2 { x, y: top - VERGE - layout.lot_depth },
+                    Vector2 { x: x + width, y: top - VERGE },
+                ),
+                frontage: [false, false, true, false],
+            });
+
+            lots.push(Lot {
+                footprint: Rectangle::new(
+                    Vector2 { x, y: bottom + VERGE },
+                    Vector2 { x: x + width, y: bottom + VERGE + layout.lot_depth },
+                ),
+                frontage: [true, false, false, false],
+            });
+        }
+
+        x += width;
+    }
+
+    lots
+}
+
+/// Lines both sides of a street running along y with lots
+fn lots_along_y(
+    street: &[Rectangle],
+    layout: &SuburbLayout,
+    widths: &mut impl Iterator<Item = f64>,
+) -> Vec<Lot> {
+    let street: Vec<Rectangle> = street.iter().map(|slice| slice.transpose()).collect();
+    lots_along_x(&street, layout, widths)
+        .into_iter()
+        .map(|lot| Lot {
+            footprint: lot.footprint.transpose(),
+            frontage: transpose_frontage(lot.frontage),
+        })
+        .collect()
+}
+
+/// Lays out a suburb over an area: winding collectors run along its
+/// length, one to a band, with side streets branching off either
+/// side of them. Each side street is either a cul-de-sac ending in a
+/// turning circle, or a loop of two streets joined at the far end.
+/// Roads already running up to the edge of the area (like those of a
+/// grid core) carry on in to the nearest collector. Every street is
+/// lined with lots facing it, wherever there's room for them.
+///
+/// The curve of each collector comes as its amplitude, wavelength and
+/// phase. Lot widths are taken from the widths, and the rest of the
+/// layout takes random numbers in [0, 1] from the choices as it goes.
+pub fn lay_out(
+    area: Rectangle,
+    layout: &SuburbLayout,
+    curves: &[(f64, f64, f64)],
+    entries: &[Rectangle],
+    widths: &mut impl Iterator<Item = f64>,
+    choices: &mut impl Iterator<Item = f64>,
+) -> Suburb {
+    let along_x = area.width() >= area.height();
+    let frame = to_frame(along_x, area);
+    let (start, end) = (frame.start(), frame.end());
+
+    let count = curves.len().max(1);
+    let band = frame.height() / count as f64;
+    let depth = layout.lot_depth + VERGE;
+
+    // Collectors, each winding about the middle of its band
+    let centres: Vec<_> = curves
+        .iter()
+        .enumerate()
+        .map(|(index, &(amplitude, wavelength, phase))| {
+            let middle = start.y + band * (index as f64 + 0.5);
+            let amplitude = amplitude.clamp(0.0, band * MAX_WIND);
+            let wavelength = wavelength.max(1.0);
+            move |x: f64| middle + amplitude * (TAU * x / wavelength + phase).sin()
+        })
+        .collect();
+
+    // Each street comes with whether it runs along x
+    let mut streets: Vec<(Vec<Rectangle>, bool)> = centres
+        .iter()
+        .map(|centre| (street_along_x(start.x, end.x, layout.collector_breadth, centre), true))
+        .collect();
+
+    // Roads coming in across the edges of the area run on to the
+    // first or last collector
+    let tolerance = 0.5;
+    let mut connectors = Vec::new();
+    for entry in entries.iter().map(|entry| to_frame(along_x, *entry)) {
+        let middle = entry.centre().x;
+        if entry.start().x < start.x || entry.end().x > end.x || entry.width() > entry.height() {
+            continue;
+        }
+
+        if (entry.end().y - start.y).abs() < tolerance {
+            if let Some(centre) = centres.first() {
+                connectors.push(Rectangle::new(
+                    Vector2 { x: entry.start().x, y: start.y },
+                    Vector2 { x: entry.end().x, y: centre(middle) },
+                ));
+            }
+        } else if (entry.start().y - end.y).abs() < tolerance {
+            if let Some(centre) = centres.last() {
+                connectors.push(Rectangle::new(
+                    Vector2 { x: entry.start().x, y: centre(middle) },
+                    Vector2 { x: entry.end().x, y: end.y },
+                ));
+            }
+        }
+    }
+
+    // Side streets branch off both sides of each collector, each in a
+    // strip of its own along it. They reach as far as the edge of
+    // their band, leaving room for the lots around their ends.
+    let street_breadth = layout.street_breadth;
+    let cul_de_sac_width = street_breadth + 2.0 * depth;
+    let loop_width = 2.0 * street_breadth + 4.0 * depth;
+    let mut bulbs = Vec::new();
+    for (index, centre) in centres.iter().enumerate() {
+        for side in [-1.0, 1.0] {
+            let band_edge = start.y + band * (index as f64 + if side < 0.0 { 0.0 } else { 1.0 });
+            let mut cursor = start.x + depth;
+            loop {
+                let is_loop = choices.next().unwrap_or(1.0) < layout.loop_chance
+                    && cursor + loop_width <= end.x - depth;
+                let bow = layout.max_bow * (2.0 * choices.next().unwrap_or(0.5) - 1.0);
+                let width = if is_loop { loop_width } else { cul_de_sac_width };
+                if cursor + width > end.x - depth {
+                    break;
+                }
+
+                let x = cursor + depth;
+                cursor += width;
+
+                let near = centre(x + street_breadth / 2.0) + side * layout.collector_breadth / 2.0;
+                let far = band_edge - side * (depth + if is_loop { 0.0 } else { layout.bulb_radius });
+                let length = (far - near) * side;
+                if length < layout.lot_depth {
+                    continue;
+                }
+
+                // Each strip keeps clear of the roads coming in
+                let strip = Rectangle::new(
+                    Vector2 { x: x - depth, y: near.min(far) },
+                    Vector2 { x: x - depth + width, y: near.max(far) },
+                );
+                if connectors.iter().any(|connector| connector.intersection(&strip).is_some()) {
+                    continue;
+                }
+
+                // The streets bow out sideways, and come back in at the
+                // far end to meet the street across a loop
+                let (low, high) = (near.min(far), near.max(far));
+                let bowed = |x: f64| move |y: f64| {
+                    x + street_breadth / 2.0 + bow * (PI * (y - near) / (far - near)).sin()
+                };
+
+                let mut branch = |x: f64| {
+                    let slices: Vec<Rectangle> = street_along_x(low, high, street_breadth, bowed(x))
+                        .into_iter()
+                        .map(|slice| slice.transpose())
+                        .collect();
+                    streets.push((slices, false));
+                };
+
+                branch(x);
+                if is_loop {
+                    let across = x + street_breadth + 2.0 * depth;
+                    branch(across);
+
+                    let end_y = if side < 0.0 { far } else { far - street_breadth };
+                    let far_end = Rectangle::new(
+                        Vector2 { x, y: end_y },
+                        Vector2 { x: across + street_breadth, y: end_y + street_breadth },
+                    );
+                    streets.push((vec![far_end], true));
+                } else {
+                    let circle = Vector2 { x: x + street_breadth / 2.0, y: far };
+                    bulbs.extend(bulb(circle, layout.bulb_radius));
+                }
+            }
+        }
+    }
+
+    // Lots go along the side streets first, then along the
+    // collectors wherever there's room left, and never on a street
+    let mut paved: Vec<Rectangle> = streets
+        .iter()
+        .flat_map(|(slices, _)| slices.iter().copied())
+        .collect();
+    paved.extend(bulbs.iter().copied());
+    paved.extend(connectors.iter().copied());
+
+    let mut lots: Vec<Lot> = Vec::new();
+    let candidates: Vec<Lot> = streets
+        .iter()
+        .skip(centres.len())
+        .chain(streets.iter().take(centres.len()))
+        .flat_map(|(slices, runs_along_x)| if *runs_along_x {
+            lots_along_x(slices, layout, widths)
+        } else {
+            lots_along_y(slices, layout, widths)
+        })
+        .collect();
+
+    for lot in candidates {
+        let footprint = lot.footprint;
+        let inside = footprint.start().x >= start.x
+            && footprint.start().y >= start.y
+            && footprint.end().x <= end.x
+            && footprint.end().y <= end.y;
+
+        let clear = paved.iter().all(|street| street.intersection(&footprint).is_none())
+            && lots.iter().all(|other| other.footprint.intersection(&footprint).is_none());
+
+        if inside && clear {
+            lots.push(lot);
+        }
+    }
+
+    Suburb {
+        streets: paved.into_iter().map(|street| to_frame(along_x, street)).collect(),
+        lots: lots
+            .into_iter()
+            .map(|lot| if along_x {
+                lot
+            } else {
+                Lot {
+                    footprint: lot.footprint.transpose(),
+                    frontage: transpose_frontage(lot.frontage),
+                }
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lots_stay_clear_of_streets_and_each_other() {
+        let layout = SuburbLayout {
+            collector_breadth: 10.0,
+            street_breadth: 7.0,
+            collector_spacing: 240.0,
+            loop_chance: 0.4,
+            bulb_radius: 11.0,
+            lot_depth: 30.0,
+            max_bow: 8.0,
+        };
+        let area = Rectangle::new(Vector2 { x: 0.0, y: 0.0 }, Vector2 { x: 300.0, y: 500.0 });
+        let curves = vec![(20.0, 400.0, 1.0); collectors(area, &layout) as usize];
+        let mut widths = std::iter::repeat(20.0);
+        let mut choices = (0..).map(|i| (i as f64 * 0.618_034).fract());
+        let suburb = lay_out(area, &layout, &curves, &[], &mut widths, &mut choices);
+
+        assert!(!suburb.lots.is_empty());
+        for (i, lot) in suburb.lots.iter().enumerate() {
+            assert!(lot.footprint.intersection(&area).is_some());
+            assert!(lot.frontage.iter().any(|&faces| faces));
+            assert!(suburb.streets.iter().all(|street| street.intersection(&lot.footprint).is_none()));
+            assert!(suburb.lots[i + 1..].iter().all(|other| other.footprint.intersection(&lot.footprint).is_none()));
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use image::{ImageBuffer, Luma};
+
+use super::geom::Vector2;
+
+/// A grayscale heightmap stretched over the whole city. Black is sea
+/// level, and white is the configured maximum height.
+pub struct Terrain {
+    heights: ImageBuffer<Luma<u16>, Vec<u16>>,
+    /// The size of the city in meters, the map covers all of it
+    size: Vector2,
+    /// The height in meters of a white pixel
+    vertical_scale: f64,
+}
+
+impl Terrain {
+    /// Decodes a heightmap from an encoded image in any format the
+    /// image crate understands. Colour images are converted to
+    /// grayscale first.
+    pub fn from_bytes(
+        bytes: &[u8],
+        size: Vector2,
+        vertical_scale: f64,
+    ) -> Result<Self, image::ImageError> {
+        let heights = image::load_from_memory(bytes)?.to_luma16();
+
+        Ok(Self {
+            heights,
+            size,
+            vertical_scale,
+        })
+    }
+
+    /// The size of one heightmap pixel in meters
+    fn pixel_size(&self) -> Vector2 {
+        let (width, height) = self.heights.dimensions();
+        Vector2 {
+            x: self.size.x / width as f64,
+            y: self.size.y / height as f64,
+        }
+    }
+
+    fn pixel_height(&self, x: i64, y: i64) -> f64 {
+        let (width, height) = self.heights.dimensions();
+        let x = x.clamp(0, width as i64 - 1) as u32;
+        let y = y.clamp(0, height as i64 - 1) as u32;
+        let Luma([value]) = *self.heights.get_pixel(x, y);
+
+        value as f64 / u16::MAX as f64 * self.vertical_scale
+    }
+
+    /// The height of the ground in meters at a point in the city,
+    /// bilinearly interpolated between heightmap pixels.
+    pub fn height(&self, point: Vector2) -> f64 {
+        let pixel_size = self.pixel_size();
+
+        // Pixel centres sit half a pixel in from their corners
+        let x = point.x / pixel_size.x - 0.5;
+        let y = point.y / pixel_size.y - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (ix, iy) = (x0 as i64, y0 as i64);
+
+        let top = self.pixel_height(ix, iy) * (1.0 - tx)
+            + self.pixel_height(ix + 1, iy) * tx;
+        let bottom = self.pixel_height(ix, iy + 1) * (1.0 - tx)
+            + self.pixel_height(ix + 1, iy + 1) * tx;
+
+        top * (1.0 - ty) + bottom * ty
+    }
+
+    /// The rise over run of the ground in each direction at a point
+    pub fn gradient(&self, point: Vector2) -> Vector2 {
+        let step = self.pixel_size();
+        let dx = Vector2 { x: step.x, y: 0.0 };
+        let dy = Vector2 { x: 0.0, y: step.y };
+
+        Vector2 {
+            x: (self.height(point + dx) - self.height(point - dx)) / (2.0 * step.x),
+            y: (self.height(point + dy) - self.height(point - dy)) / (2.0 * step.y),
+        }
+    }
+
+    /// The steepness of the ground at a point, as rise over run
+    pub fn slope(&self, point: Vector2) -> f64 {
+        self.gradient(point).mag()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ramp() -> Terrain {
+        // A ramp rising from west to east
+        let heights = ImageBuffer::from_fn(11, 11, |x, _| {
+            Luma([(x as f64 / 10.0 * u16::MAX as f64) as u16])
+        });
+
+        Terrain {
+            heights,
+            size: Vector2 { x: 110.0, y: 110.0 },
+            vertical_scale: 100.0,
+        }
+    }
+
+    #[test]
+    fn ramp_heights() {
+        let terrain = ramp();
+        assert!(terrain.height(Vector2 { x: 5.0, y: 50.0 }).abs() < 1e-6);
+        assert!((terrain.height(Vector2 { x: 105.0, y: 50.0 }) - 100.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn ramp_slope() {
+        let terrain = ramp();
+        let gradient = terrain.gradient(Vector2 { x: 55.0, y: 55.0 });
+        assert!((gradient.x - 1.0).abs() < 1e-6);
+        assert!(gradient.y.abs() < 1e-6);
+    }
+}
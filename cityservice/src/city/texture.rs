@@ -0,0 +1,302 @@
+use image::Rgb;
+
+use super::channels::{Canvas, Material};
+use super::geom::Vector2;
+use super::noise::Noise;
+use super::roof::RoofShape;
+use super::zone::Zone;
+
+/// How far each kind of surface is lightened or darkened at full
+/// detail, by the fine grain and by the broader patches over it
+const ASPHALT_GRAIN: f64 = 0.14;
+const ASPHALT_PATCHES: f64 = 0.08;
+const CONCRETE_SPECKLE: f64 = 0.07;
+const CONCRETE_STAINS: f64 = 0.05;
+const GRASS_MOTTLING: f64 = 0.12;
+const WATER_RIPPLES: f64 = 0.04;
+const GRAVEL_GRAIN: f64 = 0.12;
+
+/// How many features of grain there are per meter, and how far apart
+/// the patches and stains are, in meters
+const GRAIN_FREQUENCY: f64 = 3.0;
+const PATCH_SIZE: f64 = 9.0;
+
+/// The depth and length of a course of roof tiles, and the spacing
+/// of the standing seams on metal roofs, in meters
+const TILE_COURSE: f64 = 1.0;
+const TILE_LENGTH: f64 = 1.4;
+const SEAM_SPACING: f64 = 1.6;
+
+/// The share of each course that's in the shadow of the one above,
+/// and of each panel taken up by its seam
+const TILE_SHADOW: f64 = 0.3;
+const SEAM_SHARE: f64 = 0.25;
+
+/// How far, at full detail, the shadowed share of a course is
+/// darkened, each tile's shade strays, and seams catch the light
+const TILE_OVERLAP: f64 = 0.12;
+const TILE_VARIATION: f64 = 0.05;
+const SEAM_HIGHLIGHT: f64 = 0.1;
+
+/// How far the colour of a building's roof strays from its palette
+/// colour at full detail, channel by channel
+const BUILDING_VARIATION: f64 = 0.08;
+
+/// What a roof is covered in, this decides the pattern it's drawn
+/// with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Finish {
+    /// Overlapping courses of tiles, or slates
+    Tiles,
+    /// Gravel ballast over a flat membrane
+    Gravel,
+    /// Metal sheets joined by standing seams
+    Seams,
+}
+
+impl Finish {
+    /// How a roof in a zone is covered: sheds and warehouses are clad
+    /// in metal, other pitched roofs are tiled and flat roofs
+    /// gravelled
+    pub fn for_roof(zone: Zone, shape: RoofShape) -> Self {
+        match (zone, shape) {
+            (Zone::Industrial, _) => Finish::Seams,
+            (_, RoofShape::Flat) => Finish::Gravel,
+            _ => Finish::Tiles,
+        }
+    }
+}
+
+/// Breaks up the flat fills surfaces are painted with into grain,
+/// speckle and patterns. Everything is drawn from noise seeded by the
+/// city's seed, so the same seed always gives the same textures.
+#[derive(Clone, Copy, Debug)]
+pub struct Texture {
+    /// How strong the texturing is, zero leaves surfaces flat
+    pub detail: f64,
+    pub noise: Noise,
+}
+
+impl Default for Texture {
+    fn default() -> Self {
+        Self {
+            detail: 0.0,
+            noise: Noise::new(0),
+        }
+    }
+}
+
+impl Texture {
+    /// The fine grain and broader patches over a surface, as a shade
+    /// around 1. Each use gets a shift of its own so they don't line
+    /// up.
+    fn grain(&self, point: Vector2, grain: f64, patches: f64, shift: f64) -> f64 {
+        let shift = Vector2 { x: shift, y: shift };
+        let fine = self.noise.value(point * GRAIN_FREQUENCY + shift);
+        let broad = self.noise.fractal(point * (1.0 / PATCH_SIZE) + shift, 3);
+        1.0 + self.detail * (grain * fine + patches * broad)
+    }
+
+    /// The shade of a point on a roof from its finish. The point is
+    /// in meters, and roofs that slope along x have their courses and
+    /// seams running across x.
+    pub fn finish(&self, finish: Finish, point: Vector2, along_x: bool) -> f64 {
+        if self.detail <= 0.0 {
+            return 1.0;
+        }
+
+        let (down, across) = if along_x { (point.x, point.y) } else { (point.y, point.x) };
+        let pattern = match finish {
+            Finish::Tiles => {
+                // Every other course is offset by half a tile, and
+                // each tile is a slightly different shade
+                let course = (down / TILE_COURSE).floor();
+                let offset = if course as i64 % 2 == 0 { 0.0 } else { TILE_LENGTH / 2.0 };
+                let tile = ((across + offset) / TILE_LENGTH).floor();
+                let overlap = if (down / TILE_COURSE).fract() < TILE_SHADOW { -TILE_OVERLAP } else { 0.0 };
+                overlap + TILE_VARIATION * self.noise.value(Vector2 { x: course * 7.3, y: tile * 3.1 })
+            },
+            Finish::Gravel => GRAVEL_GRAIN * self.noise.value(point * GRAIN_FREQUENCY * 1.5),
+            Finish::Seams => {
+                let seam = (across / SEAM_SPACING).rem_euclid(1.0) < SEAM_SHARE;
+                if seam { SEAM_HIGHLIGHT } else { 0.0 }
+            },
+        };
+
+        1.0 + self.detail * pattern
+    }
+
+    /// Shifts a roof colour a little, differently for each building
+    /// depending on where it stands
+    pub fn vary(&self, color: [u8; 4], centre: Vector2) -> [u8; 4] {
+        if self.detail <= 0.0 {
+            return color;
+        }
+
+        let [red, green, blue, alpha] = color;
+        let [red, green, blue] = [(red, 0.0), (green, 11.0), (blue, 23.0)].map(|(channel, shift)| {
+            let stray = self.noise.value(centre * 0.37 + Vector2 { x: shift, y: shift });
+            let shade = 1.0 + self.detail * BUILDING_VARIATION * stray;
+            (channel as f64 * shade).round().clamp(0.0, u8::MAX as f64) as u8
+        });
+
+        [red, green, blue, alpha]
+    }
+
+    /// Textures the ground, roads and water of everything drawn on a
+    /// canvas. Roofs carry their own patterns, and vehicles and
+    /// equipment are left as they are. The pixel size is in meters.
+    pub fn apply(&self, canvas: &mut Canvas, pixel_size: Vector2) {
+        if self.detail <= 0.0 {
+            return;
+        }
+
+        let (width, height) = canvas.heights.dimensions();
+        for y in 0..height {
+            for x in 0..width {
+                let material = canvas.material(x, y);
+                let point = Vector2 {
+                    x: (x as f64 + 0.5) * pixel_size.x,
+                    y: (y as f64 + 0.5) * pixel_size.y,
+                };
+
+                let shade = match material {
+                    Material::Asphalt => self.grain(point, ASPHALT_GRAIN, ASPHALT_PATCHES, 0.0),
+                    Material::Concrete => self.grain(point, CONCRETE_SPECKLE, CONCRETE_STAINS, 41.0),
+                    Material::Grass => self.grain(point, GRASS_MOTTLING / 2.0, GRASS_MOTTLING, 73.0),
+                    Material::Water => self.grain(point * 0.5, 0.0, WATER_RIPPLES, 97.0),
+                    _ => continue,
+                };
+
+                let Rgb(color) = canvas.color(x, y);
+                let color = color.map(|channel| (channel as f64 * shade).round().clamp(0.0, u8::MAX as f64) as u8);
+                canvas.restyle(x, y, Rgb(color), material);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::lighting::Sun;
+    use super::super::settings::Settings;
+    use super::super::settings::config::CityConfig;
+
+    fn textured(seed: u32) -> Canvas {
+        let sun = Sun::from_degrees(0.0, 45.0);
+        let mut canvas = Canvas::new(16, 16, Rgb([0x80, 0x80, 0x80]), Material::Asphalt, sun);
+        let texture = Texture { detail: 1.0, noise: Noise::new(seed) };
+        texture.apply(&mut canvas, Vector2 { x: 0.5, y: 0.5 });
+        canvas
+    }
+
+    #[test]
+    fn textures_follow_the_seed() {
+        let colors = |canvas: &Canvas| -> Vec<Rgb<u8>> {
+            (0..16).flat_map(|y| (0..16).map(move |x| (x, y))).map(|(x, y)| canvas.color(x, y)).collect()
+        };
+
+        let first = colors(&textured(5));
+        assert_eq!(first, colors(&textured(5)));
+        assert_ne!(first, colors(&textured(6)));
+
+        // The grain actually breaks up the fill
+        assert!(first.iter().any(|color| *color != first[0]));
+    }
+
+    #[test]
+    fn seeds_are_plain_whole_numbers() {
+        let mut settings = Settings::default();
+        settings.update("let city.seed be 42").unwrap();
+        assert_eq!(CityConfig::try_from(&settings).unwrap().seed, 42);
+
+        settings.update("let city.seed be -1").unwrap();
+        assert!(CityConfig::try_from(&settings).is_err());
+    }
+
+    #[test]
+    fn no_detail_leaves_roofs_flat() {
+        let texture = Texture { detail: 0.0, noise: Noise::new(5) };
+        for finish in [Finish::Tiles, Finish::Gravel, Finish::Seams] {
+            for step in 0..100 {
+                let point = Vector2 { x: step as f64 * 0.37, y: step as f64 * 0.53 };
+                assert_eq!(texture.finish(finish, point, true), 1.0);
+                assert_eq!(texture.finish(finish, point, false), 1.0);
+            }
+        }
+
+        let color = [0x80, 0x40, 0x20, 0xFF];
+        assert_eq!(texture.vary(color, Vector2 { x: 12.0, y: 34.0 }), color);
+    }
+
+    #[test]
+    fn every_other_course_of_tiles_is_offset_by_half_a_tile() {
+        let texture = Texture { detail: 1.0, noise: Noise::new(5) };
+        let step = TILE_LENGTH / 20.0;
+
+        // Where the shade changes along a course, which is at the
+        // edges between its tiles
+        let edges = |down: f64| -> Vec<f64> {
+            let shade = |across: f64| texture.finish(Finish::Tiles, Vector2 { x: across, y: down }, false);
+            (1..200)
+                .map(|index| index as f64 * step)
+                .filter(|across| shade(*across) != shade(across - step))
+                .collect()
+        };
+
+        // The lit part of the first two courses
+        for (down, offset) in [(0.5, 0.0), (TILE_COURSE + 0.5, TILE_LENGTH / 2.0)] {
+            let edges = edges(down);
+            assert!(!edges.is_empty());
+            for edge in edges {
+                let tiles = (edge + offset) / TILE_LENGTH;
+                assert!((tiles - tiles.round()).abs() * TILE_LENGTH <= step);
+            }
+        }
+
+        // The top of each course is in the shadow of the one above
+        let shadowed = Vector2 { x: 0.3, y: TILE_SHADOW / 2.0 };
+        let lit = Vector2 { x: 0.3, y: (TILE_SHADOW + 1.0) / 2.0 };
+        let overlap = texture.finish(Finish::Tiles, lit, false) - texture.finish(Finish::Tiles, shadowed, false);
+        assert!((overlap - TILE_OVERLAP).abs() < 1e-9);
+    }
+
+    #[test]
+    fn seams_take_their_share_of_each_panel() {
+        let texture = Texture { detail: 1.0, noise: Noise::new(5) };
+        let samples = 1000;
+        let seams = (0..samples)
+            .map(|index| (index as f64 + 0.5) * SEAM_SPACING * 10.0 / samples as f64)
+            .filter(|across| {
+                let shade = texture.finish(Finish::Seams, Vector2 { x: 3.0, y: *across }, true);
+                shade == 1.0 + SEAM_HIGHLIGHT
+            })
+            .count();
+
+        assert_eq!(seams as f64 / samples as f64, SEAM_SHARE);
+    }
+
+    #[test]
+    fn buildings_vary_by_where_they_stand() {
+        let texture = Texture { detail: 1.0, noise: Noise::new(5) };
+        let color = [0x80, 0x80, 0x80, 0xC0];
+        let centres: Vec<Vector2> = (0..20)
+            .map(|index| Vector2 { x: index as f64 * 13.0, y: index as f64 * 7.0 })
+            .collect();
+        let varied: Vec<[u8; 4]> = centres.iter().map(|centre| texture.vary(color, *centre)).collect();
+
+        // The same spot always gives the same colour
+        assert_eq!(varied[3], texture.vary(color, centres[3]));
+        assert!(varied.iter().any(|other| *other != varied[0]));
+
+        // Only a little, and never the alpha
+        let most = (0x80 as f64 * BUILDING_VARIATION).ceil() as i32;
+        for [red, green, blue, alpha] in varied {
+            assert_eq!(alpha, 0xC0);
+            for channel in [red, green, blue] {
+                assert!((channel as i32 - 0x80).abs() <= most);
+            }
+        }
+    }
+}
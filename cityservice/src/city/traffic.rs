@@ -0,0 +1,443 @@
+use super::channels::{Canvas, Material};
+use super::geom::*;
+use super::objects::Renderable;
+use super::palette::Surface;
+
+/// Roads are split into as many lanes of at least this breadth as fit
+/// across them, in meters
+const LANE_BREADTH: f64 = 3.2;
+
+/// The stretch of lane each vehicle is given a chance to stand on,
+/// longer vehicles spill over into the next
+const SLOT_LENGTH: f64 = 7.0;
+
+/// The gap kept between a vehicle and whatever is in front of it
+const GAP: f64 = 1.5;
+
+/// How far back from an intersection queues reach before they thin
+/// out to the usual density, in meters
+const QUEUE_LENGTH: f64 = 30.0;
+
+/// The paints cars come in
+const CAR_PAINTS: [Surface; 8] = [
+    Surface::CarWhite,
+    Surface::CarBlack,
+    Surface::CarSilver,
+    Surface::CarGrey,
+    Surface::CarRed,
+    Surface::CarBlue,
+    Surface::CarGreen,
+    Surface::CarBeige,
+];
+
+/// The liveries buses come in
+const BUS_PAINTS: [Surface; 3] = [
+    Surface::BusRed,
+    Surface::BusYellow,
+    Surface::BusBlue,
+];
+
+/// The paints of the cabs on trucks, their loads are always a pale
+/// container
+const TRUCK_PAINTS: [Surface; 4] = [
+    Surface::TruckWhite,
+    Surface::TruckBlue,
+    Surface::TruckRed,
+    Surface::TruckGrey,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VehicleKind {
+    Car,
+    Bus,
+    Truck,
+}
+
+impl VehicleKind {
+    pub const ALL: [VehicleKind; 3] = [
+        VehicleKind::Car,
+        VehicleKind::Bus,
+        VehicleKind::Truck,
+    ];
+
+    /// The name of the vehicle as it appears in cityscript
+    pub fn name(self) -> &'static str {
+        match self {
+            VehicleKind::Car => "car",
+            VehicleKind::Bus => "bus",
+            VehicleKind::Truck => "truck",
+        }
+    }
+
+    /// The length, width and height of the vehicle, in meters
+    pub fn size(self) -> (f64, f64, f64) {
+        match self {
+            VehicleKind::Car => (4.4, 1.8, 1.5),
+            VehicleKind::Bus => (12.0, 2.5, 3.2),
+            VehicleKind::Truck => (9.0, 2.5, 3.8),
+        }
+    }
+
+    /// The paints the vehicle comes in, picked at random
+    pub fn paints(self) -> &'static [Surface] {
+        match self {
+            VehicleKind::Car => &CAR_PAINTS,
+            VehicleKind::Bus => &BUS_PAINTS,
+            VehicleKind::Truck => &TRUCK_PAINTS,
+        }
+    }
+
+    /// Picks a paint from a random number in [0, 1]
+    pub fn paint(self, choice: f64) -> Surface {
+        let paints = self.paints();
+        paints[((choice * paints.len() as f64) as usize).min(paints.len() - 1)]
+    }
+}
+
+/// Which side of the road traffic keeps to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrafficSide {
+    Right,
+    Left,
+}
+
+impl TrafficSide {
+    /// Reads a side from its name in cityscript
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "right" => Some(TrafficSide::Right),
+            "left" => Some(TrafficSide::Left),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Vehicle {
+    pub kind: VehicleKind,
+    pub footprint: Rectangle,
+    /// The way the vehicle faces, one of the four unit vectors along
+    /// the axes
+    pub heading: Vector2,
+    pub paint: Surface,
+    /// The height of the ground it stands on
+    pub elevation: f64,
+}
+
+impl Vehicle {
+    /// A vehicle of the given kind with its centre at a point
+    pub fn new(kind: VehicleKind, centre: Vector2, heading: Vector2, paint: Surface) -> Self {
+        let (length, width, _) = kind.size();
+        let half = if heading.x != 0.0 {
+            Vector2 { x: length / 2.0, y: width / 2.0 }
+        } else {
+            Vector2 { x: width / 2.0, y: length / 2.0 }
+        };
+
+        Self {
+            kind,
+            footprint: Rectangle::new(centre - half, centre + half),
+            heading,
+            paint,
+            elevation: 0.0,
+        }
+    }
+
+    /// The part of a footprint between two shares of its length,
+    /// measured from the back of the vehicle, inset from its sides
+    fn section(&self, footprint: Rectangle, from: f64, to: f64, inset: f64) -> Rectangle {
+        let (start, end) = (footprint.start(), footprint.end());
+        if self.heading.x != 0.0 {
+            let length = footprint.width();
+            let (x0, x1) = if self.heading.x > 0.0 {
+                (start.x + from * length, start.x + to * length)
+            } else {
+                (end.x - to * length, end.x - from * length)
+            };
+
+            Rectangle::new(
+                Vector2 { x: x0, y: start.y + inset },
+                Vector2 { x: x1, y: end.y - inset },
+            )
+        } else {
+            let length = footprint.height();
+            let (y0, y1) = if self.heading.y > 0.0 {
+                (start.y + from * length, start.y + to * length)
+            } else {
+                (end.y - to * length, end.y - from * length)
+            };
+
+            Rectangle::new(
+                Vector2 { x: start.x + inset, y: y0 },
+                Vector2 { x: end.x - inset, y: y1 },
+            )
+        }
+    }
+}
+
+impl Renderable for Vehicle {
+    fn render(&self, offset: Vector2, scale: Vector2, canvas: &mut Canvas) {
+        let footprint = self.footprint.translate(offset);
+        let (_, _, height) = self.kind.size();
+        let body = canvas.palette.rgba(self.paint);
+        let glass = canvas.palette.rgba(Surface::Windscreens);
+
+        match self.kind {
+            VehicleKind::Car => {
+                canvas.paint(footprint.scale(scale), body, Material::Metal);
+                canvas.raise(footprint.scale(scale), self.elevation + height);
+
+                // The roof sits between the windscreens, the one at
+                // the front being the longer
+                let cabin = self.section(footprint, 0.2, 0.75, 0.2);
+                canvas.paint(cabin.scale(scale), glass, Material::Glass);
+                let roof = self.section(footprint, 0.3, 0.62, 0.3);
+                canvas.paint(roof.scale(scale), body, Material::Metal);
+            },
+            VehicleKind::Bus => {
+                canvas.paint(footprint.scale(scale), body, Material::Metal);
+                canvas.raise(footprint.scale(scale), self.elevation + height);
+
+                // A windscreen across the front, and air conditioning
+                // on the roof
+                let windscreen = self.section(footprint, 0.96, 1.0, 0.1);
+                canvas.paint(windscreen.scale(scale), glass, Material::Glass);
+                let units = canvas.palette.rgba(Surface::Units);
+                for (from, to) in [(0.2, 0.32), (0.6, 0.72)] {
+                    let unit = self.section(footprint, from, to, 0.5);
+                    canvas.paint(unit.scale(scale), units, Material::Metal);
+                    canvas.raise(unit.scale(scale), self.elevation + height + 0.3);
+                }
+            },
+            VehicleKind::Truck => {
+                // A cab up front, and a container behind it
+                let cab = self.section(footprint, 0.78, 1.0, 0.0);
+                let windscreen = self.section(footprint, 0.95, 1.0, 0.15);
+                canvas.paint(cab.scale(scale), body, Material::Metal);
+                canvas.raise(cab.scale(scale), self.elevation + height * 0.75);
+                canvas.paint(windscreen.scale(scale), glass, Material::Glass);
+
+                let container = self.section(footprint, 0.0, 0.75, 0.0);
+                let load = canvas.palette.rgba(Surface::Containers);
+                canvas.paint(container.scale(scale), load, Material::Metal);
+                canvas.raise(container.scale(scale), self.elevation + height);
+            },
+        }
+    }
+}
+
+/// Whether a road runs along x rather than y
+pub fn runs_along_x(asphalt: Rectangle) -> bool {
+    asphalt.width() >= asphalt.height()
+}
+
+/// A lane of a road, traffic on it all heads the same way
+#[derive(Debug)]
+pub struct Lane {
+    pub area: Rectangle,
+    /// One of the four unit vectors along the axes
+    pub heading: Vector2,
+}
+
+/// A stretch of lane a vehicle could stand on. Distances run along
+/// the lane the way traffic does, from where it enters.
+#[derive(Debug)]
+pub struct Slot {
+    pub start: f64,
+    /// How far the lane runs on from the start before reaching an
+    /// intersection or the end of the road
+    pub room: f64,
+    /// How far the slot ends short of the next intersection, or
+    /// infinity when there's none ahead
+    pub ahead: f64,
+}
+
+impl Lane {
+    fn along_x(&self) -> bool {
+        self.heading.x != 0.0
+    }
+
+    fn forward(&self) -> bool {
+        self.heading.x + self.heading.y > 0.0
+    }
+
+    fn length(&self) -> f64 {
+        if self.along_x() { self.area.width() } else { self.area.height() }
+    }
+
+    /// The distance along the lane, the way traffic goes, to a point
+    /// on its axis
+    fn travel(&self, position: f64) -> f64 {
+        let (start, end) = if self.along_x() {
+            (self.area.start().x, self.area.end().x)
+        } else {
+            (self.area.start().y, self.area.end().y)
+        };
+
+        if self.forward() { position - start } else { end - position }
+    }
+
+    /// The stretches of the lane a vehicle could stand on, avoiding
+    /// the intersections with the given crossing roads
+    pub fn slots(&self, crossings: &[Rectangle]) -> Vec<Slot> {
+        let length = self.length();
+        let mut blocked: Vec<(f64, f64)> = crossings
+            .iter()
+            .filter_map(|crossing| crossing.intersection(&self.area))
+            .map(|crossing| {
+                let (first, second) = if self.along_x() {
+                    (crossing.start().x, crossing.end().x)
+                } else {
+                    (crossing.start().y, crossing.end().y)
+                };
+
+                let (first, second) = (self.travel(first), self.travel(second));
+                (first.min(second), first.max(second))
+            })
+            .collect();
+        blocked.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        // The stretches between intersections, and whether each ends
+        // at one
+        let mut stretches = Vec::new();
+        let mut from = 0.0;
+        for (start, end) in blocked {
+            if start > from {
+                stretches.push((from, start, true));
+            }
+            from = f64::max(from, end);
+        }
+        if from < length {
+            stretches.push((from, length, false));
+        }
+
+        let mut slots = Vec::new();
+        for (start, end, intersection) in stretches {
+            let mut position = start;
+            while position + SLOT_LENGTH <= end {
+                slots.push(Slot {
+                    start: position,
+                    room: end - position,
+                    ahead: if intersection {
+                        end - position - SLOT_LENGTH
+                    } else {
+                        f64::INFINITY
+                    },
+                });
+                position += SLOT_LENGTH;
+            }
+        }
+
+        slots
+    }
+
+    /// The chance of a slot holding a vehicle, rising from the usual
+    /// density to the queueing density right behind an intersection
+    pub fn occupancy(slot: &Slot, density: f64, queueing: f64) -> f64 {
+        let queue = (-slot.ahead / QUEUE_LENGTH).exp();
+        density + (queueing - density).max(0.0) * queue
+    }
+
+    /// Puts a vehicle at the front of a slot, or nowhere if there's
+    /// no room for it before the next intersection. Returns the
+    /// vehicle with how far along the lane it leaves free.
+    pub fn place(&self, slot: &Slot, kind: VehicleKind, paint: Surface) -> Option<(Vehicle, f64)> {
+        let (length, _, _) = kind.size();
+        if length + GAP > slot.room {
+            return None;
+        }
+
+        let middle = slot.start + GAP / 2.0 + length / 2.0;
+        let across = self.area.centre();
+        let centre = match (self.along_x(), self.forward()) {
+            (true, true) => Vector2 { x: self.area.start().x + middle, y: across.y },
+            (true, false) => Vector2 { x: self.area.end().x - middle, y: across.y },
+            (false, true) => Vector2 { x: across.x, y: self.area.start().y + middle },
+            (false, false) => Vector2 { x: across.x, y: self.area.end().y - middle },
+        };
+
+        let vehicle = Vehicle::new(kind, centre, self.heading, paint);
+        Some((vehicle, slot.start + length + GAP))
+    }
+}
+
+/// Splits a road into lanes, half heading each way. Looking down on
+/// the city with y increasing downwards, right hand traffic heads
+/// down the left side of a road running along y, and left along the
+/// top side of a road running along x. The odd lane out on narrow
+/// roads goes with the second half.
+pub fn lanes(asphalt: Rectangle, side: TrafficSide) -> Vec<Lane> {
+    let along_x = runs_along_x(asphalt);
+    let breadth = if along_x { asphalt.height() } else { asphalt.width() };
+    let count = (breadth / LANE_BREADTH).floor().max(1.0) as u32;
+    let lane_breadth = breadth / count as f64;
+
+    let first_heading = match (along_x, side) {
+        (true, TrafficSide::Right) => Vector2 { x: -1.0, y: 0.0 },
+        (true, TrafficSide::Left) => Vector2 { x: 1.0, y: 0.0 },
+        (false, TrafficSide::Right) => Vector2 { x: 0.0, y: 1.0 },
+        (false, TrafficSide::Left) => Vector2 { x: 0.0, y: -1.0 },
+    };
+
+    (0..count)
+        .map(|lane| {
+            let from = lane as f64 * lane_breadth;
+            let to = from + lane_breadth;
+            let area = if along_x {
+                Rectangle::new(
+                    Vector2 { x: asphalt.start().x, y: asphalt.start().y + from },
+                    Vector2 { x: asphalt.end().x, y: asphalt.start().y + to },
+                )
+            } else {
+                Rectangle::new(
+                    Vector2 { x: asphalt.start().x + from, y: asphalt.start().y },
+                    Vector2 { x: asphalt.start().x + to, y: asphalt.end().y },
+                )
+            };
+
+            let heading = if lane < count / 2 { first_heading } else { first_heading * -1.0 };
+            Lane { area, heading }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn road() -> Rectangle {
+        Rectangle::new(Vector2 { x: 100.0, y: 0.0 }, Vector2 { x: 112.0, y: 200.0 })
+    }
+
+    #[test]
+    fn traffic_keeps_to_its_side() {
+        for (side, down) in [(TrafficSide::Right, 1.0), (TrafficSide::Left, -1.0)] {
+            let lanes = lanes(road(), side);
+            assert_eq!(lanes.len(), 3);
+            assert_eq!(lanes[0].heading.y, down);
+            assert_eq!(lanes[1].heading.y, -down);
+            assert_eq!(lanes[2].heading.y, -down);
+        }
+    }
+
+    #[test]
+    fn vehicles_stay_out_of_intersections() {
+        let crossing = Rectangle::new(Vector2 { x: 0.0, y: 80.0 }, Vector2 { x: 300.0, y: 90.0 });
+        for lane in lanes(road(), TrafficSide::Right) {
+            let slots = lane.slots(&[crossing]);
+            assert!(!slots.is_empty());
+            for slot in slots.iter() {
+                let Some((vehicle, _)) = lane.place(slot, VehicleKind::Car, CAR_PAINTS[0]) else {
+                    continue;
+                };
+
+                assert!(vehicle.footprint.intersection(&crossing).is_none());
+                assert!(vehicle.footprint.intersection(&lane.area).is_some());
+            }
+
+            // Queues build up behind the intersection, not past it
+            let behind = slots.iter().filter(|slot| slot.ahead < SLOT_LENGTH).count();
+            assert_eq!(behind, 1);
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use image::Rgb;
+
+use super::channels::{Canvas, Material};
+use super::geom::Vector2;
+use super::noise::Noise;
+use super::palette::Surface;
+
+/// Snow slides off and puddles drain from anything steeper than this,
+/// as rise over run
+const MAX_SETTLING_SLOPE: f64 = 0.2;
+
+/// The colours leaves turn in autumn, from the first to turn to the
+/// last
+const AUTUMN_LEAVES: [Surface; 4] = [
+    Surface::AutumnOlive,
+    Surface::AutumnGold,
+    Surface::AutumnOrange,
+    Surface::AutumnRusset,
+];
+
+/// How bright summer grass is, autumn colours keep the light and
+/// shade of the grass they replace relative to this
+const GRASS_LUMA: f64 = 108.0;
+
+/// Grass fades and browns over winter
+const WINTER_TINT: [f64; 3] = [0.9, 0.85, 0.7];
+
+/// How much rain darkens asphalt, and other paving
+const WET_ASPHALT: f64 = 0.6;
+const WET_PAVING: f64 = 0.85;
+
+/// The size of the patches of autumn colour, of drifts and bare spots
+/// in the snow, and of puddles, in meters
+const FOLIAGE_FEATURE_SIZE: f64 = 8.0;
+const SNOW_FEATURE_SIZE: f64 = 4.0;
+const PUDDLE_FEATURE_SIZE: f64 = 3.0;
+
+/// Stretches fractal noise out over [0, 1], it has a spread of about
+/// 0.3 either side of zero
+const PATCH_SPREAD: f64 = 5.7;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Season {
+    Summer,
+    Autumn,
+    Winter,
+}
+
+impl Season {
+    pub const ALL: [Season; 3] = [Season::Summer, Season::Autumn, Season::Winter];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "summer" => Some(Season::Summer),
+            "autumn" => Some(Season::Autumn),
+            "winter" => Some(Season::Winter),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Season::Summer => "summer",
+            Season::Autumn => "autumn",
+            Season::Winter => "winter",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Weather {
+    Clear,
+    /// Wet paving, with puddles wherever it's flat
+    Rain,
+    /// Snow lying on flat roofs and grass, the roads are salted
+    Snow,
+}
+
+impl Weather {
+    pub const ALL: [Weather; 3] = [Weather::Clear, Weather::Rain, Weather::Snow];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "clear" => Some(Weather::Clear),
+            "rain" => Some(Weather::Rain),
+            "snow" => Some(Weather::Snow),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Weather::Clear => "clear",
+            Weather::Rain => "rain",
+            Weather::Snow => "snow",
+        }
+    }
+}
+
+/// How many variants of a city there are, one for every season in
+/// every weather
+pub const VARIANTS: usize = Season::ALL.len() * Weather::ALL.len();
+
+/// The season and weather a city is rendered in
+#[derive(Clone, Copy, Debug)]
+pub struct Conditions {
+    pub season: Season,
+    pub weather: Weather,
+    /// Roughly the share of flat roofs and grass under snow
+    pub snow_cover: f64,
+    /// Roughly the share of flat paving under puddles after rain
+    pub puddles: f64,
+    /// Lays out the patches of colour, snow and puddles
+    pub noise: Noise,
+}
+
+impl Conditions {
+    /// A value in [0, 1] from the noise at a point, with features of
+    /// the given size. Each use gets a shift of its own so they don't
+    /// all line up.
+    fn patch(&self, point: Vector2, feature_size: f64, shift: f64) -> f64 {
+        let point = point * (1.0 / feature_size) + Vector2 { x: shift, y: shift };
+
+        // Fractal noise bunches up around zero, this spreads it out
+        // roughly evenly so shares of it come out about right
+        1.0 / (1.0 + (-PATCH_SPREAD * self.noise.fractal(point, 3)).exp())
+    }
+
+    /// Changes the look of everything drawn on a canvas to suit the
+    /// season and weather. This goes by what each pixel is made of
+    /// and how steep it is, so every variant of a city lines up pixel
+    /// for pixel. The pixel size is in meters.
+    pub fn apply(&self, canvas: &mut Canvas, pixel_size: Vector2) {
+        if self.season == Season::Summer && self.weather == Weather::Clear {
+            return;
+        }
+
+        let autumn_colors = AUTUMN_LEAVES.map(|surface| canvas.palette.color(surface));
+        let snow_color = canvas.palette.color(Surface::Snow);
+        let puddle_color = canvas.palette.color(Surface::Puddles);
+
+        let (width, height) = canvas.heights.dimensions();
+        for y in 0..height {
+            for x in 0..width {
+                let material = canvas.material(x, y);
+                let Rgb(color) = canvas.color(x, y);
+                let point = Vector2 {
+                    x: (x as f64 + 0.5) * pixel_size.x,
+                    y: (y as f64 + 0.5) * pixel_size.y,
+                };
+                let flat = canvas.heights.gradient(x, y, pixel_size).mag() < MAX_SETTLING_SLOPE;
+
+                let mut restyled = match (self.season, material) {
+                    (Season::Autumn, Material::Grass) => {
+                        let turn = self.patch(point, FOLIAGE_FEATURE_SIZE, 0.0);
+                        let index = ((turn * autumn_colors.len() as f64) as usize).min(autumn_colors.len() - 1);
+                        let luma = (color[0] as f64 * 0.3 + color[1] as f64 * 0.59 + color[2] as f64 * 0.11) / GRASS_LUMA;
+                        Some((scale(autumn_colors[index], [luma; 3]), material))
+                    },
+                    (Season::Winter, Material::Grass) => Some((scale(color, WINTER_TINT), material)),
+                    _ => None,
+                };
+
+                let color = restyled.map_or(color, |(color, _)| color);
+                match (self.weather, material) {
+                    (Weather::Snow, Material::Grass | Material::Roof) if flat => {
+                        let drift = self.patch(point, SNOW_FEATURE_SIZE, 17.0);
+                        if drift < self.snow_cover {
+                            let shade = 0.95 + 0.05 * drift;
+                            restyled = Some((scale(snow_color, [shade; 3]), Material::Snow));
+                        }
+                    },
+                    (Weather::Rain, Material::Asphalt | Material::Concrete) => {
+                        let wetness = if material == Material::Asphalt { WET_ASPHALT } else { WET_PAVING };
+                        let wet = scale(color, [wetness; 3]);
+                        let puddle = self.patch(point, PUDDLE_FEATURE_SIZE, 31.0);
+                        restyled = if flat && puddle > 1.0 - self.puddles {
+                            let mixed = [0, 1, 2].map(|i| ((puddle_color[i] as u16 + wet[i] as u16) / 2) as u8);
+                            Some((mixed, Material::Water))
+                        } else {
+                            Some((wet, material))
+                        };
+                    },
+                    _ => (),
+                }
+
+                if let Some((color, material)) = restyled {
+                    canvas.restyle(x, y, Rgb(color), material);
+                }
+            }
+        }
+    }
+}
+
+/// Scales each channel of a colour
+fn scale(color: [u8; 3], by: [f64; 3]) -> [u8; 3] {
+    [0, 1, 2].map(|i| (color[i] as f64 * by[i]).round().clamp(0.0, u8::MAX as f64) as u8)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::lighting::Sun;
+    use super::super::geom::Rectangle;
+
+    #[test]
+    fn snow_settles_on_grass_but_not_roads() {
+        let sun = Sun::from_degrees(0.0, 45.0);
+        let mut canvas = Canvas::new(8, 8, Rgb([0x50, 0x80, 0x48]), Material::Grass, sun);
+        let road = Rectangle::new(Vector2 { x: 0.0, y: 0.0 }, Vector2 { x: 8.0, y: 4.0 });
+        canvas.paint(road, image::Rgba([0x20, 0x20, 0x20, 0xFF]), Material::Asphalt);
+
+        let conditions = Conditions {
+            season: Season::Winter,
+            weather: Weather::Snow,
+            snow_cover: 1.0,
+            puddles: 0.0,
+            noise: Noise::new(3),
+        };
+        conditions.apply(&mut canvas, Vector2 { x: 1.0, y: 1.0 });
+
+        for x in 0..8 {
+            assert_eq!(canvas.material(x, 0), Material::Asphalt);
+            assert_eq!(canvas.color(x, 0), Rgb([0x20, 0x20, 0x20]));
+            assert_eq!(canvas.material(x, 7), Material::Snow);
+        }
+    }
+}
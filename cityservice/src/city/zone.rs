@@ -0,0 +1,31 @@
+/// The land use of a block, this decides what kind of buildings (if
+/// any) are built on it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Zone {
+    Residential,
+    Commercial,
+    Industrial,
+    Civic,
+    Park,
+}
+
+impl Zone {
+    pub const ALL: [Zone; 5] = [
+        Zone::Residential,
+        Zone::Commercial,
+        Zone::Industrial,
+        Zone::Civic,
+        Zone::Park,
+    ];
+
+    /// The name of the zone as it appears in cityscript
+    pub fn name(self) -> &'static str {
+        match self {
+            Zone::Residential => "residential",
+            Zone::Commercial => "commercial",
+            Zone::Industrial => "industrial",
+            Zone::Civic => "civic",
+            Zone::Park => "park",
+        }
+    }
+}
@@ -6,6 +6,8 @@ use super::city;
 pub enum InputError {
     BadSettingsStructure(city::SettingsError),
     BadDistribution(city::GenerateError),
+    BadOption(city::GenerateError),
+    BadImage(city::GenerateError),
 }
 
 impl std::fmt::Display for InputError {
@@ -20,6 +22,11 @@ impl std::fmt::Display for InputError {
                 "There was a random number sampling issue: {}",
                 err,
             ),
+            Self::BadOption(err) => format!(
+                "One of the settings had an unexpected value: {}",
+                err,
+            ),
+            Self::BadImage(err) => format!("{}", err),
         };
         
         write!(f, "{}", message)
@@ -46,6 +53,11 @@ impl From<city::GenerateError> for Error {
             city::GenerateError::UnknownDistribution(_) |
             city::GenerateError::DistributionInverted{..} =>
                 Self::Input(InputError::BadDistribution(error)),
+            city::GenerateError::UnknownOption{..} =>
+                Self::Input(InputError::BadOption(error)),
+            city::GenerateError::BadInputImage{..} |
+            city::GenerateError::MissingInputImage{..} =>
+                Self::Input(InputError::BadImage(error)),
             city::GenerateError::StatsRequest(_) => Self::Server,
         }
     }
@@ -69,7 +81,12 @@ impl std::fmt::Display for Error {
                  \
                  Otherwise, there was a problem with the JSON you submitted, \
                  please submit a POST request to the /generate endpoint \
-                 with the JSON body {cityscript: <script>}",
+                 with the JSON body {cityscript: <script>}, optionally \
+                 with base64 encoded images under \"heightmap\" \
+                 and \"mask\", and the \"channel\" to render \
+                 (\"albedo\", \"height\", \"normal\", \"roughness\", \
+                 \"metallic\", \"shadow\" or \"mask\"), or a list of \"channels\" \
+                 to bundle",
             ),
         };
         
@@ -8,7 +8,26 @@
 //!
 //! To make a request of the web service you can make a POST request
 //! at the /generate endpoint of the webserver. This will generate and
-//! return an image. To avoid resource hogging, the code limits the
+//! return an image, a jpeg albedo by default. The body can ask for
+//! another `channel` instead: `"height"` for a 16 bit png heightmap,
+//! `"normal"` for a tangent space normal map, or `"roughness"` and
+//! `"metallic"` for material masks, `"shadow"` for a mask of the
+//! shadows cast by the sun, or `"mask"` for a map of what every
+//! pixel is, in a flat colour per kind of surface (the colours are
+//! listed in the metadata). To get several channels of the
+//! same city at once, list them under `channels` (along with
+//! `"metadata"` for a JSON description of the city), or ask for
+//! `"all"`, and they come back as a `multipart/mixed` response.
+//! Asking for an `animation` of `"gif"`, `"apng"` or `"zip"` (a png
+//! per frame) instead returns a time-lapse of the city growing out
+//! from its middle, one frame to each of the `growth.epochs` (at
+//! most 60).
+//! Other seasons and weathers of the same city can be added to a
+//! bundle under `variants`, as a list like `["winter/snow"]`, each
+//! one an albedo and a roughness map that line up with the rest.
+//! Variants asked for twice are only rendered once.
+//!
+//! To avoid resource hogging, the code limits the
 //! number of concurrent jobs to 3, this allows for easy DOS on the
 //! open internet, so please only run this project only on a trusted
 //! network.
@@ -16,6 +35,19 @@
 //! For now [warp] listens on port 5000. Use this port to send
 //! requests.
 //!
+//! The body of a request is JSON with the script under `cityscript`,
+//! and optionally base64 encoded images under `heightmap` (a
+//! grayscale heightmap shaping the city's terrain) and `mask` (a
+//! painted mask weighing the density of roads and buildings).
+//!
+//! A city can also be generated offline, without running the
+//! service, by passing a cityscript file and an output path as
+//! arguments, optionally followed by `--heightmap <image>`,
+//! `--mask <image>` and `--channel <name>`, where `--channel all`
+//! writes out the full texture set, or `--animation <format>` for a
+//! growth time-lapse. Any number of `--variant <season>/<weather>`
+//! can be added too.
+//!
 //! The project also hosts a small web interface available at the root
 //! endpoint. The interface allows you to input a configuration
 //! "script" in "cityscript". You can "compile" the script using the
@@ -38,8 +70,11 @@ use warp::filters as wf;
 use warp::reply::{with_status, Response};
 use warp::{Filter, Reply, Rejection};
 
+mod bundle;
+use bundle::Bundle;
+
 mod city;
-use city::{Builder, Settings};
+use city::{Animation, Builder, Channel, City, Settings, VARIANTS};
 
 mod error;
 use error::*;
@@ -47,6 +82,10 @@ use error::*;
 const SOCKET: &str = "127.0.0.1:5000";
 const SIMULTANEOUS_JOBS: usize = 3;
 
+// Heightmaps are sent base64 encoded in the request body, so leave
+// plenty of room for them
+const MAX_REQUEST_BYTES: u64 = 16 * 1024 * 1024;
+
 async fn map_rejections(rejection: Rejection) -> Result<Response, Rejection> {
     if let Some(error) = rejection.find::<Error>() {
         println!("Error: {:?}", *error);
@@ -67,39 +106,341 @@ async fn map_rejections(rejection: Rejection) -> Result<Response, Rejection> {
     }
 }
 
-async fn generate_albedo(job_semaphore: Arc<Semaphore>, form: serde_json::Value) -> Result<Response, Rejection> {
+/// The encoded images that can be sent along with a cityscript
+#[derive(Default)]
+struct InputImages {
+    heightmap: Option<Vec<u8>>,
+    mask: Option<Vec<u8>>,
+}
+
+impl InputImages {
+    /// Pulls the optional base64 encoded images out of a request body
+    fn from_form(form: &serde_json::Value) -> Result<Self, Error> {
+        let decode = |key: &str| match form.get(key) {
+            None | Some(serde_json::Value::Null) => Ok(None),
+            Some(serde_json::Value::String(encoded)) => base64::decode(encoded)
+                .map(Some)
+                .map_err(|_| Error::Submission),
+            Some(_) => Err(Error::Submission),
+        };
+
+        Ok(Self {
+            heightmap: decode("heightmap")?,
+            mask: decode("mask")?,
+        })
+    }
+}
+
+/// Picks the channel requested in a body, defaulting to albedo
+fn channel_from_form(form: &serde_json::Value) -> Result<Channel, Error> {
+    match form.get("channel") {
+        None | Some(serde_json::Value::Null) => Ok(Channel::Albedo),
+        Some(serde_json::Value::String(name)) => {
+            Channel::from_name(name).ok_or(Error::Submission)
+        },
+        Some(_) => Err(Error::Submission),
+    }
+}
+
+/// Picks the format of the growth time-lapse requested in a body, if
+/// it asks for one under `animation`
+fn animation_from_form(form: &serde_json::Value) -> Result<Option<Animation>, Error> {
+    match form.get("animation") {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(serde_json::Value::String(name)) => {
+            Animation::from_name(name).map(Some).ok_or(Error::Submission)
+        },
+        Some(_) => Err(Error::Submission),
+    }
+}
+
+/// Splits a variant like `"winter/snow"` into its season and weather
+fn parse_variant(name: &str) -> Option<(String, String)> {
+    let (season, weather) = name.split_once('/')?;
+    Some((String::from(season), String::from(weather)))
+}
+
+/// Drops any variant asked for more than once, keeping the order they
+/// were asked for in. There's no more to ask for than there are
+/// seasons and weathers to go together.
+fn distinct_variants(variants: Vec<(String, String)>) -> Option<Vec<(String, String)>> {
+    let mut distinct: Vec<(String, String)> = Vec::new();
+    for variant in variants {
+        if !distinct.contains(&variant) {
+            distinct.push(variant);
+        }
+    }
+
+    if distinct.len() > VARIANTS {
+        return None;
+    }
+
+    Some(distinct)
+}
+
+/// Picks the seasons and weathers requested under `variants` in a
+/// body, if any
+fn variants_from_form(form: &serde_json::Value) -> Result<Vec<(String, String)>, Error> {
+    let variants = match form.get("variants") {
+        None | Some(serde_json::Value::Null) => Vec::new(),
+        Some(serde_json::Value::Array(names)) => names
+            .iter()
+            .map(|name| name.as_str().and_then(parse_variant).ok_or(Error::Submission))
+            .collect::<Result<_, _>>()?,
+        Some(_) => return Err(Error::Submission),
+    };
+
+    distinct_variants(variants).ok_or(Error::Submission)
+}
+
+/// Picks the channels requested as a bundle in a body, if it asks for
+/// one under `channels`, and whether to include the metadata. This is
+/// either a list of channel names (and `"metadata"`), or `"all"` for
+/// the full texture set with metadata.
+fn bundle_from_form(form: &serde_json::Value) -> Result<Option<(Vec<Channel>, bool)>, Error> {
+    match form.get("channels") {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(serde_json::Value::String(name)) if name == "all" => {
+            Ok(Some((Channel::ALL.to_vec(), true)))
+        },
+        Some(serde_json::Value::Array(names)) => {
+            let mut channels = Vec::new();
+            let mut metadata = false;
+            for name in names {
+                match name.as_str() {
+                    Some("metadata") => metadata = true,
+                    Some(name) => channels.push(
+                        Channel::from_name(name).ok_or(Error::Submission)?
+                    ),
+                    None => return Err(Error::Submission),
+                }
+            }
+
+            Ok(Some((channels, metadata)))
+        },
+        Some(_) => Err(Error::Submission),
+    }
+}
+
+/// Renders the city once into every requested channel and packs them
+/// up, along with the metadata if asked for, and the albedo and
+/// roughness of each variant
+fn bundle_city(
+    city: &City,
+    channels: &[Channel],
+    metadata: bool,
+    variants: &[(String, String)],
+) -> Result<Bundle, Error> {
+    let mut bundle = Bundle::default();
+    for (channel, encoded) in channels.iter().zip(city.render_channels(channels)) {
+        let file_name = format!("{}.{}", channel.name(), channel.extension());
+        bundle.add(file_name, channel.content_type(), encoded);
+    }
+
+    let rendered = city.render_variants(variants)
+        .map_err(Error::from)?;
+    for ((season, weather), encoded) in variants.iter().zip(rendered) {
+        for (channel, encoded) in [Channel::Albedo, Channel::Roughness].iter().zip(encoded) {
+            let file_name = format!("{}-{}-{}.{}", channel.name(), season, weather, channel.extension());
+            bundle.add(file_name, channel.content_type(), encoded);
+        }
+    }
+
+    if metadata {
+        let json = city.metadata(channels).to_string();
+        bundle.add(String::from("metadata.json"), "application/json", json.into_bytes());
+    }
+
+    Ok(bundle)
+}
+
+/// Runs the whole generation pipeline for a script, optionally
+/// shaped by a heightmap and density mask, and returns the city.
+async fn generate_city(cityscript: &str, images: &InputImages) -> Result<City, Error> {
+    let mut settings = Settings::default();
+
+    settings.update(cityscript)
+        .map_err(Error::from)?;
+
+    let config = settings.try_into()
+        .map_err(Error::from)?;
+
+    let mut builder = Builder::new(config);
+    if let Some(heightmap) = &images.heightmap {
+        builder = builder.with_terrain(heightmap)
+            .map_err(Error::from)?;
+    }
+
+    if let Some(mask) = &images.mask {
+        builder = builder.with_mask(mask)
+            .map_err(Error::from)?;
+    }
+
+    let city = builder
+        .build_water().await
+        .map_err(Error::from)?
+        .build_roads().await
+        .map_err(Error::from)?
+        .build_rail().await
+        .map_err(Error::from)?
+        .build_zones().await
+        .map_err(Error::from)?
+        .build_landmarks().await
+        .map_err(Error::from)?
+        .build_buildings().await
+        .map_err(Error::from)?
+        .build_rooftops().await
+        .map_err(Error::from)?
+        .build_traffic().await
+        .map_err(Error::from)?
+        .build_lights().await
+        .map_err(Error::from)?
+        .build_weather().await
+        .map_err(Error::from)?
+        .build_growth().await
+        .map_err(Error::from)?
+        .build();
+
+    Ok(city)
+}
+
+async fn generate_texture(job_semaphore: Arc<Semaphore>, form: serde_json::Value) -> Result<Response, Rejection> {
     let permit = job_semaphore.try_acquire();
 
     if permit.is_err() {
         return Err(warp::reject::custom(Error::Overloaded));
     }
+
+    let images = InputImages::from_form(&form)?;
+    let channel = channel_from_form(&form)?;
+    let bundle = bundle_from_form(&form)?;
+    let animation = animation_from_form(&form)?;
+    let variants = variants_from_form(&form)?;
+
+    // Variants only come in a bundle, alongside the albedo if nothing
+    // else was asked for
+    let bundle = match bundle {
+        None if !variants.is_empty() => Some((vec![channel], false)),
+        bundle => bundle,
+    };
     
     if let Some(serde_json::Value::String(cityscript)) = form.get("cityscript") {
-        let mut settings = Settings::default();
+        let city = generate_city(cityscript, &images).await?;
 
-        settings.update(cityscript)
-            .map_err(|err| Error::from(err))?;
-        
-        let config = settings.try_into()
-            .map_err(|err| Error::from(err))?;
-
-        let stream = Builder::new(config)
-            .generate_roads().await
-            .map_err(|err| Error::from(err))?
-            .generate_buildings().await
-            .map_err(|err| Error::from(err))?
-            .build()
-            .into_jpeg()
-            .into_inner()
-            .map_err(|_| Error::Server)?;
-
-        Ok(Response::new(stream.into()))
+        if let Some(animation) = animation {
+            let encoded = city.render_growth(animation);
+            return Ok(warp::reply::with_header(encoded, "content-type", animation.content_type()).into_response());
+        }
+
+        if let Some((channels, metadata)) = bundle {
+            return Ok(bundle_city(&city, &channels, metadata, &variants)?.into_response());
+        }
+
+        let encoded = city.render_channels(&[channel]).pop().ok_or(Error::Server)?;
+
+        Ok(warp::reply::with_header(encoded, "content-type", channel.content_type()).into_response())
     } else {
         // The form did not contain a cityscript in the right place
         Err(Error::Submission.into())
     }
 }
 
+/// Generates a single city from files on disk instead of serving
+/// requests, the arguments are the cityscript file and the path to
+/// write the output to, optionally followed by `--heightmap <image>`,
+/// `--mask <image>` and `--channel <name>`. The channel `all` writes
+/// the full texture set and its metadata, each to the output path
+/// suffixed with its name. Passing `--animation <format>` writes a
+/// growth time-lapse instead.
+async fn generate_offline(args: &[String]) -> Result<(), String> {
+    const USAGE: &str = "usage: cityservice [<cityscript> <output> \
+                         [--heightmap <image>] [--mask <image>] \
+                         [--channel albedo|height|normal|roughness|metallic|shadow|mask|all] \
+                         [--animation gif|apng|zip] [--variant <season>/<weather>]...]";
+
+    let (script_path, output_path, options) = match args {
+        [script, output, options @ ..] => (script, output, options),
+        _ => return Err(String::from(USAGE)),
+    };
+
+    let read = |path: &String| std::fs::read(path)
+        .map_err(|err| format!("Could not read {}: {}", path, err));
+
+    let mut images = InputImages::default();
+    let mut channels = vec![Channel::Albedo];
+    let mut animation = None;
+    let mut variants = Vec::new();
+    for option in options.chunks(2) {
+        match option {
+            [flag, path] if flag == "--heightmap" => images.heightmap = Some(read(path)?),
+            [flag, path] if flag == "--mask" => images.mask = Some(read(path)?),
+            [flag, name] if flag == "--channel" => {
+                channels = match name.as_str() {
+                    "all" => Channel::ALL.to_vec(),
+                    _ => vec![Channel::from_name(name)
+                        .ok_or(format!("Unknown channel {}", name))?],
+                };
+            },
+            [flag, name] if flag == "--animation" => {
+                animation = Some(Animation::from_name(name)
+                    .ok_or(format!("Unknown animation format {}", name))?);
+            },
+            [flag, name] if flag == "--variant" => {
+                variants.push(parse_variant(name)
+                    .ok_or(format!("Variants are <season>/<weather>, not {}", name))?);
+            },
+            _ => return Err(String::from(USAGE)),
+        }
+    }
+
+    let variants = distinct_variants(variants)
+        .ok_or(format!("There are only {} variants to ask for", VARIANTS))?;
+
+    let cityscript = std::fs::read_to_string(script_path)
+        .map_err(|err| format!("Could not read {}: {}", script_path, err))?;
+
+    let city = generate_city(&cityscript, &images)
+        .await
+        .map_err(|err| format!("{}", err))?;
+
+    if let Some(animation) = animation {
+        return std::fs::write(output_path, city.render_growth(animation))
+            .map_err(|err| format!("Could not write {}: {}", output_path, err));
+    }
+
+    let encoded = city.render_channels(&channels);
+    let single = channels.len() == 1;
+
+    for (channel, encoded) in channels.iter().zip(encoded) {
+        let path = if single {
+            output_path.clone()
+        } else {
+            format!("{}-{}.{}", output_path, channel.name(), channel.extension())
+        };
+
+        std::fs::write(&path, encoded)
+            .map_err(|err| format!("Could not write {}: {}", path, err))?;
+    }
+
+    if !single {
+        let path = format!("{}-metadata.json", output_path);
+        std::fs::write(&path, city.metadata(&channels).to_string())
+            .map_err(|err| format!("Could not write {}: {}", path, err))?;
+    }
+
+    let rendered = city.render_variants(&variants)
+        .map_err(|err| format!("{}", Error::from(err)))?;
+    for ((season, weather), encoded) in variants.iter().zip(rendered) {
+        for (channel, encoded) in [Channel::Albedo, Channel::Roughness].iter().zip(encoded) {
+            let path = format!("{}-{}-{}-{}.{}", output_path, season, weather, channel.name(), channel.extension());
+            std::fs::write(&path, encoded)
+                .map_err(|err| format!("Could not write {}: {}", path, err))?;
+        }
+    }
+
+    Ok(())
+}
+
 fn log_requests(info: warp::log::Info) {
     let client_addr = match info.remote_addr() {
         Some(socket) => format!("{}", socket),
@@ -122,6 +463,18 @@ fn log_requests(info: warp::log::Info) {
 
 #[tokio::main]
 async fn main() {
+    // Any arguments mean we're generating a city offline rather than
+    // running as a service
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        if let Err(message) = generate_offline(&args).await {
+            eprintln!("Error: {}", message);
+            std::process::exit(1);
+        }
+
+        return;
+    }
+
     let job_semaphore = Arc::new(Semaphore::new(SIMULTANEOUS_JOBS));
 
     // Set up a logging filter that logs all requests in detail
@@ -137,13 +490,13 @@ async fn main() {
     // Serve the static content at (and in) /vendor
     let vendor_filter = warp::path("vendor").and(wf::fs::dir("vendor"));
 
-    // Generate a 2048x2048 jpeg every time someone sends a request to the
+    // Generate a 2048x2048 texture every time someone sends a request to the
     // /generate endpoint, unless the server is overloaded.
     let generate_filter = warp::path!("generate")
-        .and(wf::body::content_length_limit(4096))
+        .and(wf::body::content_length_limit(MAX_REQUEST_BYTES))
         .map(move || Arc::clone(&job_semaphore))
         .and(wf::body::json())
-        .and_then(generate_albedo)
+        .and_then(generate_texture)
         .recover(map_rejections);
 
     // Serve all of our filters
@@ -154,3 +507,71 @@ async fn main() {
         .run(SocketAddr::from_str(SOCKET).unwrap())
         .await;
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn bundles_list_their_channels_or_ask_for_all() {
+        assert!(bundle_from_form(&json!({})).unwrap().is_none());
+        assert!(bundle_from_form(&json!({ "channels": null })).unwrap().is_none());
+
+        let (channels, metadata) = bundle_from_form(&json!({ "channels": "all" })).unwrap().unwrap();
+        assert_eq!(channels, Channel::ALL.to_vec());
+        assert!(metadata);
+
+        let form = json!({ "channels": ["height", "metadata", "albedo"] });
+        let (channels, metadata) = bundle_from_form(&form).unwrap().unwrap();
+        assert_eq!(channels, vec![Channel::Height, Channel::Albedo]);
+        assert!(metadata);
+
+        let (channels, metadata) = bundle_from_form(&json!({ "channels": ["normal"] })).unwrap().unwrap();
+        assert_eq!(channels, vec![Channel::Normal]);
+        assert!(!metadata);
+    }
+
+    #[test]
+    fn bundles_turn_away_unknown_channels() {
+        assert!(bundle_from_form(&json!({ "channels": ["albedo", "sheen"] })).is_err());
+        assert!(bundle_from_form(&json!({ "channels": [3] })).is_err());
+        assert!(bundle_from_form(&json!({ "channels": "albedo" })).is_err());
+        assert!(bundle_from_form(&json!({ "channels": 7 })).is_err());
+    }
+
+    #[test]
+    fn variants_are_split_at_the_slash() {
+        assert_eq!(parse_variant("winter/snow"), Some((String::from("winter"), String::from("snow"))));
+        assert_eq!(parse_variant("winter"), None);
+
+        assert!(variants_from_form(&json!({ "variants": ["winter"] })).is_err());
+        assert!(variants_from_form(&json!({ "variants": [1] })).is_err());
+        assert!(variants_from_form(&json!({ "variants": "winter/snow" })).is_err());
+        assert!(variants_from_form(&json!({})).unwrap().is_empty());
+    }
+
+    #[test]
+    fn variants_asked_for_twice_count_once() {
+        let form = json!({ "variants": ["winter/snow", "summer/clear", "winter/snow"] });
+        assert_eq!(variants_from_form(&form).unwrap(), vec![
+            (String::from("winter"), String::from("snow")),
+            (String::from("summer"), String::from("clear")),
+        ]);
+    }
+
+    #[test]
+    fn variants_are_capped() {
+        let names = |count: usize| -> Vec<(String, String)> {
+            (0..count).map(|index| (format!("season{}", index), String::from("clear"))).collect()
+        };
+
+        assert_eq!(distinct_variants(names(VARIANTS)).map(|variants| variants.len()), Some(VARIANTS));
+        assert!(distinct_variants(names(VARIANTS + 1)).is_none());
+
+        // Repeats don't count towards the cap
+        let mut repeated = names(VARIANTS);
+        repeated.extend(names(VARIANTS));
+        assert_eq!(distinct_variants(repeated).map(|variants| variants.len()), Some(VARIANTS));
+    }
+}
@@ -26,6 +26,23 @@ function createImageDropdown(image, categoryName) {
   return container;
 }
 
+// Reads the file chosen in a file input as base64, resolving to
+// undefined if there is no file chosen.
+function readBase64(inputId) {
+  const file = document.getElementById(inputId).files[0];
+  if(file === undefined) {
+    return Promise.resolve(undefined);
+  }
+
+  return new Promise((resolve, reject) => {
+    const reader = new FileReader();
+    // The result is a data URL, the base64 comes after the comma
+    reader.onload = () => resolve(reader.result.split(',')[1]);
+    reader.onerror = () => reject(Error(`Could not read ${file.name}`));
+    reader.readAsDataURL(file);
+  });
+}
+
 function generate(ev) {
   const button = document.getElementById('generate-button');
   button.setAttribute('disabled', 'true');
@@ -50,14 +67,17 @@ function generate(ev) {
   // data in memory as an url that the <img> tag could use, but
   // createObjectURL allows me to create an url to the data
   // returned by the fetch.
-  fetch('generate', {
+  Promise.all([
+    readBase64('heightmap-input'),
+    readBase64('mask-input'),
+  ]).then(([heightmap, mask]) => fetch('generate', {
     method: 'POST',
     headers: {
       'Cache-Control': 'no-cache',
       'Content-Type': 'application/json',
     },
-    body: JSON.stringify({ cityscript: script }),
-  }).then(async (response) => {
+    body: JSON.stringify({ cityscript: script, heightmap: heightmap, mask: mask }),
+  })).then(async (response) => {
     if(response.status > 199 && response.status < 300) {
       return response.blob();
     } else if(response.status > 399 && response.status < 500) {